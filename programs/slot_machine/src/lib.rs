@@ -1,29 +1,118 @@
-//! 老虎机合约（单文件，精简 ≤800 行）
+//! 老虎机合约（单文件 Anchor 程序）
 //! 功能：
-//! - Switchboard VRF 随机：支持即时玩法与两段式玩法（request_play / settle_play）
-//! - 代理商：SOL 质押、房卡推广、基于净输赢的佣金累计与结算周期提取
-//! - 赔率系统：6符号，两连/三连赔率；Double 触发自动连续转轮（乘数递增至 16x，受 max_auto_spins）
+//! - Switchboard VRF 随机：即时玩法读取共享 VRF；两段式玩法（request_play / settle_play）从 VRF 池中按局 CPI 请求随机数并绑定 VRF 账户与轮次
+//! - 承诺-揭示随机源：庄家预先提交种子哈希，两段式玩法绑定下一个承诺，结算时揭示并混合玩家 client seed
+//! - 超时退款：PendingPlay 超过 play_timeout_slots 未结算可由玩家或 crank 取消，全额退还下注与租金
+//! - PendingPlay 为 PDA（game_state, player, seq），PlayerProfile 记录玩家序号与未结算局数，便于枚举与 crank
+//! - 结算 crank：非玩家本人结算可获 keeper_fee_bps 比例的奖池代币奖励（由庄家承担）；settle_many 批量结算
+//! - 代理商：SOL 质押、房卡推广、基于净输赢的佣金累计与结算周期提取；每个代理商独立 PDA，房卡→代理商索引 PDA
+//! - 房卡绑定：玩家与代理商粘性绑定（bind_room_card 或首次带房卡下注），佣金按绑定归属；换绑受 rebind_cooldown 限制
+//! - 反自推广：玩家为代理商本人或质押代付人、或独占代理商推广量时不计佣金；佣金率 + RTP 上限 + jackpot 比例 + keeper 奖励须低于 100%
+//! - 多级代理：代理商可挂靠上级（最多 4 级），佣金按直属与各级费率逐级分配，总额不超过佣金率上限
+//! - 佣金分档：代理商可单独设置佣金率，或按 CommissionSchedule 阶梯以质押额 / 近期推广量自动分档
+//! - 赔率系统：6符号，三个转轮各自独立的权重表，两连/三连赔率；可切换为 GameConfig 中的 N×M 网格 + 赔付线布局；Double 触发自动连续转轮（乘数阶梯、最多次数与触发所需个数可配置）
+//! - 赔率时间锁：权重/赔率修改先排队，config_delay_slots 后生效；PendingPlay 快照请求时的赔率与版本
+//! - RTP 校验：链上精确计算各符号期望返还率（含 Double 自动转轮），超出 rtp_min_bps..rtp_max_bps 的配置被拒绝；compute_rtp 只读查询
 //! - 支付方式：绑定指定 SPL Token 奖池账户与 mint；所有下注/派彩走 SPL Token
+//! - 事件：所有改变状态的指令均 emit! 结构化事件（下注结果含每次转轮、Double 次数、乘数、派彩）
+//! - 玩家统计：PlayerStats PDA（game_state, player）累计下注、派彩、局数、最大单局派彩、最近 10 局与房卡
+//! - 转轮记录：play / settle_play 通过 return data 返回完整 SpinTranscript，并在 PlayerProfile 保存精简 LastResult
+//! - 紧急暂停：paused 位掩码可分别暂停 play / request_play / 结算 / 代理质押 / 佣金提取；guardian 只能暂停
 //! - 管理功能：权重、赔率、佣金率、质押门槛、VRF 设置；奖池同步/提取/关闭
+//! - 权限：所有者两步转移（propose_owner / accept_owner）；配置管理员、财务、代理管理员、暂停员角色分权
+//! - 风险敞口：单注/单局下注上限；最坏情况派彩不得超过扣除未结算局负债后奖池的 max_exposure_bps
+//! - 累进 jackpot：每局下注按 jackpot_bps 划入独立 jackpot 账户（不计入 total_pool），按 JackpotTrigger 中奖派出全部
+//! - 多实例：initialize 以 game_id 派生 game_state PDA，同一程序可运行多台独立老虎机（不同代币、赔率与所有者）
 //! - 安全机制：账户与权限校验、VRF 所属与偏移校验、结算周期限制、溢出保护
+//!
+//! 升级须知：已部署的旧版（8 + 4096）game_state 在升级程序后必须先由 owner 调用 migrate_agents
+//! 扩容并改写为新布局，在此之前其余指令均无法反序列化该账户
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::{hash::hashv, program_pack::Pack};
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::{
+    hash::{hash, hashv},
+    instruction::{AccountMeta, Instruction},
+    program::{invoke_signed, set_return_data},
+    program_pack::Pack,
+    sysvar,
+};
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use switchboard_solana::VrfAccountData;
 
 declare_id!("8cozexydPUo9jTBT7PRWVe5Qmi3bpkjgQuPo2ZaTKHus");
 pub const SWITCHBOARD_V2_PROGRAM_ID: Pubkey =
     pubkey!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
 
 const SYMBOLS: usize = 6;
+const REELS: usize = 3;
+// 乘数 1→2→4→8→16 封顶，单局最多 5 次转轮
+const MAX_SPINS: usize = 5;
+// 网格布局上限：最多 5 个转轮 × 3 行，20 条赔付线
+pub const MAX_REELS: usize = 5;
+pub const MAX_ROWS: usize = 3;
+pub const MAX_CELLS: usize = MAX_REELS * MAX_ROWS;
+pub const MAX_PAYLINES: usize = 20;
+// 默认乘数阶梯：每次自动转轮翻倍
+pub const DEFAULT_MULTIPLIERS: [u8; MAX_SPINS] = [1, 2, 4, 8, 16];
+// 默认符号角色：最后一个符号（Double）为乘数符号，其余为普通符号
+pub const DEFAULT_SYMBOL_ROLES: [SymbolRole; 6] = [
+    SymbolRole::Regular,
+    SymbolRole::Regular,
+    SymbolRole::Regular,
+    SymbolRole::Regular,
+    SymbolRole::Regular,
+    SymbolRole::Multiplier,
+];
 const GAME_STATE_SEED: &[u8] = b"game_state";
-const MAX_AGENT_COUNT: usize = 48;
+const GAME_CONFIG_SEED: &[u8] = b"game_config";
+const COMMITMENT_SEED: &[u8] = b"seed_commitment";
+const PLAYER_PROFILE_SEED: &[u8] = b"player_profile";
+const PLAYER_STATS_SEED: &[u8] = b"player_stats";
+const PENDING_PLAY_SEED: &[u8] = b"pending_play";
+const AGENT_SEED: &[u8] = b"agent";
+const ROOM_CARD_SEED: &[u8] = b"room_card";
+const JACKPOT_SEED: &[u8] = b"jackpot";
+const COMMISSION_SCHEDULE_SEED: &[u8] = b"commission_schedule";
+// 换绑冷却取此值时绑定永久有效
+pub const REBIND_NEVER: u64 = u64::MAX;
+// 佣金最多向上分配的上级代理商层数
+pub const MAX_UPLINE_DEPTH: usize = 4;
+// 两段式 Switchboard 玩法可轮换使用的 VRF 账户数
+pub const MAX_VRF_POOL: usize = 8;
+// 佣金阶梯最多档数
+pub const MAX_COMMISSION_TIERS: usize = 4;
+// PlayerStats 保留的最近结果条数
+pub const RECENT_RESULTS: usize = 10;
+// 每局下注划入 jackpot 的比例上限 10%
+const MAX_JACKPOT_BPS: u16 = 1_000;
+const MAX_KEEPER_FEE_BPS: u16 = 100;
+// 赔率修改默认延迟约 1 小时（400ms/slot），上限约 7 天
+const DEFAULT_CONFIG_DELAY_SLOTS: u64 = 9_000;
+const MAX_CONFIG_DELAY_SLOTS: u64 = 1_512_000;
+// RTP 上限不得超过 100%；定点计算精度 1e-12
+const MAX_RTP_BPS: u16 = 10_000;
+// 默认 RTP 上限 88%，与默认 10% 佣金率之和低于 100%
+const DEFAULT_RTP_MAX_BPS: u16 = 8_800;
+const RTP_SCALE: u128 = 1_000_000_000_000;
+// 单局最坏派彩默认不超过奖池 10%
+const DEFAULT_MAX_EXPOSURE_BPS: u16 = 1_000;
+// 单一玩家默认最多占代理商推广量的 50%，超过时该局不计佣金
+const DEFAULT_MAX_REFERRAL_SHARE_BPS: u16 = 5_000;
+// paused 位掩码
+pub const PAUSE_PLAY: u8 = 1 << 0;
+pub const PAUSE_REQUEST_PLAY: u8 = 1 << 1;
+pub const PAUSE_SETTLE: u8 = 1 << 2;
+pub const PAUSE_AGENT_STAKE: u8 = 1 << 3;
+pub const PAUSE_COMMISSION: u8 = 1 << 4;
+const PAUSE_ALL: u8 =
+    PAUSE_PLAY | PAUSE_REQUEST_PLAY | PAUSE_SETTLE | PAUSE_AGENT_STAKE | PAUSE_COMMISSION;
 
 #[program]
 pub mod slot_machine {
     use super::*;
 
     // 初始化：绑定奖池账户与 mint；写入默认参数
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    // game_id 区分同一程序下的多台老虎机（各自的代币、赔率与所有者）
+    pub fn initialize(ctx: Context<Initialize>, game_id: u64) -> Result<()> {
         require!(
             ctx.accounts.pool_token_account.owner == &anchor_spl::token::ID,
             ErrorCode::InvalidTokenAccount
@@ -48,79 +137,665 @@ pub mod slot_machine {
             ErrorCode::PoolAuthorityMismatch
         );
 
-        let s = &mut ctx.accounts.game_state;
-        s.owner = ctx.accounts.user.key();
-        s.bump = ctx.bumps.game_state;
-        s.pool_mint = ctx.accounts.token_mint.key();
-        s.pool_token_account = ctx.accounts.pool_token_account.key();
-        s.total_pool = pool_state.amount;
-        s.nonce = 0;
-        s.agents = Vec::new();
-        s.next_room_card = 10_000;
-        s.commission_rate = 10;
-        s.stake_threshold = 1_000_000;
-        s.settlement_period = 86_400;
-        s.vrf = Pubkey::default();
-        s.vrf_result_offset = 0;
-        s.symbol_weights = [2500, 2500, 250, 1600, 2150, 1000];
-        s.payout_triple = [220, 180, 2000, 360, 450, 0];
-        s.payout_double = [65, 50, 100, 75, 85, 0];
-        s.max_auto_spins = 5;
-        s.min_bet = 100;
+        ctx.accounts.game_state.set_inner(GameState::new(
+            ctx.accounts.user.key(),
+            ctx.bumps.game_state,
+            game_id,
+            ctx.accounts.token_mint.key(),
+            ctx.accounts.pool_token_account.key(),
+            pool_state.amount,
+        ));
         Ok(())
     }
 
     // 管理：佣金率
     pub fn set_commission_rate(ctx: Context<SetOwnerConfig>, rate: u8) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
         require!(rate <= 100, ErrorCode::InvalidCommissionRate);
+        require!(
+            total_commission_rate(rate, &s.upline_rates, s.upline_depth)
+                <= config_commission_cap(s),
+            ErrorCode::CommissionExceedsEdge
+        );
         s.commission_rate = rate;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::CommissionRate(rate),
+        });
         Ok(())
     }
     // 管理：质押门槛（lamports）
     pub fn set_stake_threshold(ctx: Context<SetOwnerConfig>, threshold: u64) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
         s.stake_threshold = threshold;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::StakeThreshold(threshold),
+        });
+        Ok(())
+    }
+    // 管理：玩家换绑房卡的冷却时间（秒），REBIND_NEVER 表示绑定永久有效
+    pub fn set_rebind_cooldown(ctx: Context<SetOwnerConfig>, cooldown: u64) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        s.rebind_cooldown = cooldown;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::RebindCooldown(cooldown),
+        });
+        Ok(())
+    }
+    // 管理：上级代理商分成（按层，占玩家净输额的百分比）与向上分配的层数；
+    // 直属代理商 commission_rate 加各层分成之和不得超过佣金率上限
+    pub fn set_upline_commission(
+        ctx: Context<SetOwnerConfig>,
+        rates: [u8; 4],
+        depth: u8,
+    ) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        require!(depth as usize <= MAX_UPLINE_DEPTH, ErrorCode::InvalidUplineConfig);
+        require!(
+            total_commission_rate(s.commission_rate, &rates, depth) <= config_commission_cap(s),
+            ErrorCode::CommissionExceedsEdge
+        );
+        s.upline_rates = rates;
+        s.upline_depth = depth;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::UplineCommission { rates, depth },
+        });
+        Ok(())
+    }
+    // 代理管理：设置代理商的上级（不传 parent_account 表示清除）；上级须为活跃代理商且不能形成自环
+    pub fn set_agent_parent(ctx: Context<SetAgentParent>) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        let agent_key = ctx.accounts.agent_account.agent;
+        let parent = match &ctx.accounts.parent_account {
+            Some(p) => {
+                require!(
+                    p.agent != agent_key && p.parent != agent_key && p.is_active,
+                    ErrorCode::InvalidAgentParent
+                );
+                p.agent
+            }
+            None => Pubkey::default(),
+        };
+        ctx.accounts.agent_account.parent = parent;
+        emit!(AgentParentSet {
+            agent: agent_key,
+            parent,
+        });
+        Ok(())
+    }
+    // 代理管理：单独设置某代理商的直属佣金率（None 表示取消，恢复按阶梯或 commission_rate）
+    pub fn set_agent_commission(ctx: Context<SetAgentCommission>, rate: Option<u8>) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        if let Some(r) = rate {
+            require!(r <= 100, ErrorCode::InvalidCommissionRate);
+            require!(
+                total_commission_rate(r, &s.upline_rates, s.upline_depth)
+                    <= config_commission_cap(s),
+                ErrorCode::CommissionExceedsEdge
+            );
+        }
+        let a = &mut ctx.accounts.agent_account;
+        a.commission_override = rate;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::AgentCommission {
+                agent: a.agent,
+                rate,
+            },
+        });
+        Ok(())
+    }
+    // 代理管理：创建佣金阶梯账户（创建后为空档，需 set_commission_schedule 写入）；
+    // 创建后 play / settle_play / settle_many 计佣时必须传入
+    pub fn init_commission_schedule(ctx: Context<InitCommissionSchedule>) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        s.tiered_commission = true;
+        let c = &mut ctx.accounts.commission_schedule;
+        c.game_state = s.key();
+        c.bump = ctx.bumps.commission_schedule;
+        c.basis = TierBasis::Stake;
+        Ok(())
+    }
+    // 代理管理：佣金阶梯——按代理商质押额或近期推广量（volume_period 秒为一个统计周期）分档，
+    // 达到门槛的最高档生效，低于所有门槛时取 commission_rate；门槛须严格递增，各档同样受佣金率上限约束
+    pub fn set_commission_schedule(
+        ctx: Context<SetCommissionSchedule>,
+        basis: TierBasis,
+        volume_period: i64,
+        tiers: [CommissionTier; 4],
+        tier_count: u8,
+    ) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        require!(
+            tier_count as usize <= MAX_COMMISSION_TIERS,
+            ErrorCode::InvalidCommissionSchedule
+        );
+        require!(
+            basis == TierBasis::Stake || volume_period > 0,
+            ErrorCode::InvalidCommissionSchedule
+        );
+        let active = &tiers[..tier_count as usize];
+        require!(
+            active.windows(2).all(|w| w[0].threshold < w[1].threshold),
+            ErrorCode::InvalidCommissionSchedule
+        );
+        for t in active {
+            require!(t.rate <= 100, ErrorCode::InvalidCommissionRate);
+            require!(
+                total_commission_rate(t.rate, &s.upline_rates, s.upline_depth)
+                    <= config_commission_cap(s),
+                ErrorCode::CommissionExceedsEdge
+            );
+        }
+        let c = &mut ctx.accounts.commission_schedule;
+        c.basis = basis;
+        c.volume_period = volume_period;
+        c.tiers = tiers;
+        c.tier_count = tier_count;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::CommissionSchedule {
+                basis,
+                volume_period,
+                tiers,
+                tier_count,
+            },
+        });
+        Ok(())
+    }
+    // 管理：单一玩家占代理商推广量的上限（万分比），超过时该局不计佣金；
+    // 默认 DEFAULT_MAX_REFERRAL_SHARE_BPS，10000 表示不限制
+    pub fn set_referral_share(ctx: Context<SetOwnerConfig>, max_share_bps: u16) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::AgentManager)?;
+        require!(max_share_bps <= 10_000, ErrorCode::InvalidReferralShare);
+        s.max_referral_share_bps = max_share_bps;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::ReferralShare(max_share_bps),
+        });
         Ok(())
     }
     // 管理：最低下注额（SPL Token 最小单位）
     pub fn set_min_bet(ctx: Context<SetOwnerConfig>, min_bet: u64) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
         require!(min_bet > 0, ErrorCode::InvalidAmount);
         s.min_bet = min_bet;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::MinBet(min_bet),
+        });
+        Ok(())
+    }
+    // 管理：下注上限与风险敞口（0 表示不限制）
+    pub fn set_bet_limits(
+        ctx: Context<SetOwnerConfig>,
+        max_bet_per_symbol: u64,
+        max_bet_per_play: u64,
+        max_exposure_bps: u16,
+    ) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(max_exposure_bps <= 10_000, ErrorCode::InvalidExposureLimit);
+        s.max_bet_per_symbol = max_bet_per_symbol;
+        s.max_bet_per_play = max_bet_per_play;
+        s.max_exposure_bps = max_exposure_bps;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::BetLimits {
+                max_bet_per_symbol,
+                max_bet_per_play,
+                max_exposure_bps,
+            },
+        });
         Ok(())
     }
-    // 管理：符号权重（概率）
+    // 管理：符号权重（概率），三个转轮使用同一张表，排队 config_delay_slots 后生效
     pub fn set_symbol_weights(ctx: Context<SetOwnerConfig>, w: [u16; 6]) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
         validate_weights(&w)?;
-        s.symbol_weights = w;
-        Ok(())
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, None, |q| {
+            q.odds.reel_weights = [w; REELS]
+        })
+    }
+    // 管理：逐转轮符号权重，排队 config_delay_slots 后生效
+    pub fn set_reel_weights(ctx: Context<SetOwnerConfig>, w: [[u16; 6]; 3]) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        for reel in w.iter() {
+            validate_weights(reel)?;
+        }
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, None, |q| {
+            q.odds.reel_weights = w
+        })
     }
-    // 管理：三连赔率（百分比）
+    // 管理：三连赔率（百分比），排队 config_delay_slots 后生效
     pub fn set_payout_triple(ctx: Context<SetOwnerConfig>, p: [u16; 6]) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, None, |q| {
+            q.odds.payout_triple = p
+        })
+    }
+    // 管理：两连赔率（百分比），排队 config_delay_slots 后生效
+    pub fn set_payout_double(ctx: Context<SetOwnerConfig>, p: [u16; 6]) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, None, |q| {
+            q.odds.payout_double = p
+        })
+    }
+    // 管理：赔率修改延迟（延长立即生效；缩短本身也需排队，防止绕过时间锁）
+    pub fn set_config_delay(ctx: Context<SetOwnerConfig>, slots: u64) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
         require_owner(s, ctx.accounts.owner.key())?;
-        s.payout_triple = p;
+        require!(slots <= MAX_CONFIG_DELAY_SLOTS, ErrorCode::InvalidConfigDelay);
+        if slots < s.config_delay_slots {
+            return queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, None, |q| {
+                q.config_delay_slots = slots
+            });
+        }
+        s.config_delay_slots = slots;
+        if s.queued_config.pending {
+            s.queued_config.config_delay_slots = slots;
+        }
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::ConfigDelay(slots),
+        });
         Ok(())
     }
-    // 管理：两连赔率（百分比）
-    pub fn set_payout_double(ctx: Context<SetOwnerConfig>, p: [u16; 6]) -> Result<()> {
+    // 生效已到期的排队配置（任何人可调用）
+    pub fn apply_config(ctx: Context<ApplyConfig>) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require!(
+            apply_due_config(s, Clock::get()?.slot)?,
+            ErrorCode::ConfigNotReady
+        );
+        Ok(())
+    }
+    // 管理：RTP 上下限（万分比，仅所有者；只约束之后的赔率修改）
+    pub fn set_rtp_bounds(ctx: Context<SetOwnerConfig>, min_bps: u16, max_bps: u16) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
         require_owner(s, ctx.accounts.owner.key())?;
-        s.payout_double = p;
+        require!(
+            min_bps <= max_bps && max_bps <= MAX_RTP_BPS,
+            ErrorCode::InvalidRtpBounds
+        );
+        require!(
+            total_commission_rate(s.commission_rate, &s.upline_rates, s.upline_depth)
+                <= commission_cap(max_bps, jackpot_bps_ceiling(s), s.keeper_fee_bps) as u16,
+            ErrorCode::CommissionExceedsEdge
+        );
+        s.rtp_min_bps = min_bps;
+        s.rtp_max_bps = max_bps;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::RtpBounds { min_bps, max_bps },
+        });
+        Ok(())
+    }
+    // 只读：计算各符号 RTP（odds/lines 为空时取当前生效赔率与 GameConfig 布局），
+    // 通过 return data 返回 RtpReport
+    pub fn compute_rtp(
+        ctx: Context<ViewGameState>,
+        odds: Option<Odds>,
+        lines: Option<LineOdds>,
+    ) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        let (config_version, current) = current_odds(s, Clock::get()?.slot)?;
+        let odds = odds.unwrap_or(current);
+        let lines = match lines {
+            Some(l) => l,
+            None => active_lines(&odds, &ctx.accounts.game_config)?,
+        };
+        let per_symbol_bps = rtp_bps(&odds, &lines)?;
+        let bettable = || {
+            per_symbol_bps
+                .iter()
+                .zip(odds.roles.iter())
+                .filter(|(_, role)| **role == SymbolRole::Regular)
+                .map(|(r, _)| *r)
+        };
+        let report = RtpReport {
+            config_version,
+            per_symbol_bps,
+            min_bps: bettable().min().unwrap_or_default(),
+            max_bps: bettable().max().unwrap_or_default(),
+        };
+        set_return_data(&report.try_to_vec()?);
+        Ok(())
+    }
+    // 管理：创建网格布局配置账户（创建后为空，需 set_line_config 写入）
+    pub fn init_game_config(ctx: Context<InitGameConfig>) -> Result<()> {
+        require_role(
+            &ctx.accounts.game_state,
+            ctx.accounts.owner.key(),
+            Role::ConfigAdmin,
+        )?;
+        let c = &mut ctx.accounts.game_config;
+        c.game_state = ctx.accounts.game_state.key();
+        c.bump = ctx.bumps.game_config;
+        c.lines = LineOdds::default();
+        Ok(())
+    }
+    // 管理：网格布局（转轮数、行数、逐转轮权重、赔付线、3/4/5 连赔率）；
+    // 网格布局生效或排队生效期间不可修改，需先切回 Classic
+    pub fn set_line_config(ctx: Context<SetGameConfig>, lines: LineOdds) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        let q = &s.queued_config;
+        require!(
+            s.layout == Layout::Classic && !(q.pending && q.odds.layout == Layout::Lines),
+            ErrorCode::LineConfigLocked
+        );
+        validate_lines(&lines)?;
+        validate_line_rtp(s, &s.odds(), &lines)?;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::LineConfig {
+                reels: lines.reels,
+                rows: lines.rows,
+                paylines: lines.paylines.len() as u8,
+            },
+        });
+        ctx.accounts.game_config.lines = lines;
         Ok(())
     }
-    // 管理：VRF 账户与偏移
+    // 管理：切换赔付布局（Classic / Lines），与赔率修改一样排队生效
+    pub fn set_layout(ctx: Context<SetGameConfig>, layout: Layout) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        let lines = &ctx.accounts.game_config.lines;
+        let check = if layout == Layout::Lines {
+            validate_lines(lines)?;
+            Some(lines)
+        } else {
+            None
+        };
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, check, |q| {
+            q.odds.layout = layout
+        })
+    }
+    // 管理：符号角色与 Scatter 赔率，排队 config_delay_slots 后生效；
+    // 网格布局生效或排队生效时需传入 game_config 以校验网格 RTP
+    pub fn set_symbol_roles(
+        ctx: Context<SetOdds>,
+        roles: [SymbolRole; 6],
+        scatter_pays: [u16; 4],
+    ) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(
+            roles.contains(&SymbolRole::Regular),
+            ErrorCode::InvalidSymbolRoles
+        );
+        let lines = lines_to_validate(s, &ctx.accounts.game_config)?;
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, lines, |q| {
+            q.odds.roles = roles;
+            q.odds.scatter_pays = scatter_pays;
+        })
+    }
+    // 管理：自动转轮乘数阶梯、最多次数与触发所需 Multiplier 个数，排队生效并校验 RTP
+    pub fn set_auto_spin(
+        ctx: Context<SetOdds>,
+        multipliers: [u8; 5],
+        max_auto_spins: u8,
+        trigger_count: u8,
+    ) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(
+            multipliers.iter().all(|m| *m > 0)
+                && (max_auto_spins as usize) < MAX_SPINS
+                && (1..=MAX_CELLS).contains(&(trigger_count as usize)),
+            ErrorCode::InvalidAutoSpin
+        );
+        let lines = lines_to_validate(s, &ctx.accounts.game_config)?;
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, lines, |q| {
+            q.odds.multipliers = multipliers;
+            q.odds.max_auto_spins = max_auto_spins;
+            q.odds.trigger_count = trigger_count;
+        })
+    }
+    // 管理：jackpot 划入比例与中奖条件，排队 config_delay_slots 后生效
+    pub fn set_jackpot(
+        ctx: Context<SetOwnerConfig>,
+        bps: u16,
+        trigger: JackpotTrigger,
+    ) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(bps <= MAX_JACKPOT_BPS, ErrorCode::InvalidJackpotConfig);
+        require!(
+            total_commission_rate(s.commission_rate, &s.upline_rates, s.upline_depth)
+                <= commission_cap(s.rtp_max_bps, bps, s.keeper_fee_bps) as u16,
+            ErrorCode::CommissionExceedsEdge
+        );
+        match trigger {
+            JackpotTrigger::Disabled => {}
+            JackpotTrigger::Combo { symbol, .. } => {
+                require!((symbol as usize) < SYMBOLS, ErrorCode::InvalidJackpotConfig)
+            }
+            JackpotTrigger::Draw { one_in, .. } => {
+                require!(one_in > 0, ErrorCode::InvalidJackpotConfig)
+            }
+        }
+        if bps > 0 || trigger != JackpotTrigger::Disabled {
+            require_keys_neq!(
+                s.jackpot_token_account,
+                Pubkey::default(),
+                ErrorCode::MissingJackpotAccount
+            );
+        }
+        queue_config(s, ctx.accounts.owner.key(), Clock::get()?.slot, None, |q| {
+            q.odds.jackpot_bps = bps;
+            q.odds.jackpot_trigger = trigger;
+        })
+    }
+    // 管理：即时玩法读取的共享 VRF 账户与偏移
     pub fn set_vrf(ctx: Context<SetOwnerConfig>, vrf: Pubkey, offset: u32) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
         s.vrf = vrf;
         s.vrf_result_offset = offset;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::Vrf { vrf, offset },
+        });
+        Ok(())
+    }
+    // 管理：两段式玩法的 VRF 账户池（authority 须为 game_state PDA）；每个 VRF 同时只服务一局，
+    // 池中账户越多可并发的请求越多。被替换或移除的槽位不得有进行中的请求
+    pub fn set_vrf_pool(ctx: Context<SetOwnerConfig>, vrfs: Vec<Pubkey>) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(vrfs.len() <= MAX_VRF_POOL, ErrorCode::InvalidVrfPool);
+        for (i, v) in vrfs.iter().enumerate() {
+            require!(*v != Pubkey::default(), ErrorCode::InvalidVrfPool);
+            require!(!vrfs[..i].contains(v), ErrorCode::InvalidVrfPool);
+        }
+        let mut pool = [VrfSlot::default(); MAX_VRF_POOL];
+        for (i, v) in vrfs.iter().enumerate() {
+            pool[i] = match s.vrf_pool.iter().find(|x| x.vrf == *v) {
+                Some(x) => *x,
+                None => VrfSlot {
+                    vrf: *v,
+                    ..VrfSlot::default()
+                },
+            };
+        }
+        for old in s.vrf_pool.iter().filter(|x| x.pending_play != Pubkey::default()) {
+            require!(vrfs.contains(&old.vrf), ErrorCode::VrfRequestInFlight);
+        }
+        s.vrf_pool = pool;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::VrfPool(vrfs),
+        });
+        Ok(())
+    }
+    // 管理：PendingPlay 超时（slot 数），超时后可 cancel_expired_play 退款
+    pub fn set_play_timeout(ctx: Context<SetOwnerConfig>, slots: u64) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(slots > 0, ErrorCode::InvalidAmount);
+        s.play_timeout_slots = slots;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::PlayTimeout(slots),
+        });
+        Ok(())
+    }
+    // 管理：keeper 结算奖励（万分比，按 total_bet 计，由奖池支付，上限 MAX_KEEPER_FEE_BPS）；
+    // 与 RTP 上限、jackpot 比例、佣金率共用庄家优势
+    pub fn set_keeper_fee(ctx: Context<SetOwnerConfig>, bps: u16) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        require!(bps <= MAX_KEEPER_FEE_BPS, ErrorCode::InvalidKeeperFee);
+        require!(
+            total_commission_rate(s.commission_rate, &s.upline_rates, s.upline_depth)
+                <= commission_cap(s.rtp_max_bps, jackpot_bps_ceiling(s), bps) as u16,
+            ErrorCode::CommissionExceedsEdge
+        );
+        s.keeper_fee_bps = bps;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::KeeperFee(bps),
+        });
+        Ok(())
+    }
+    // 管理：设置暂停位掩码（所有者或暂停员，可解除暂停）
+    pub fn set_pause(ctx: Context<SetOwnerConfig>, mask: u8) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::Pauser)?;
+        require!(mask & !PAUSE_ALL == 0, ErrorCode::InvalidPauseMask);
+        s.paused = mask;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::Paused(mask),
+        });
+        Ok(())
+    }
+    // 管理：设置 guardian（Pubkey::default() 表示不设置）
+    pub fn set_guardian(ctx: Context<SetOwnerConfig>, guardian: Pubkey) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_owner(s, ctx.accounts.owner.key())?;
+        s.guardian = guardian;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::Guardian(guardian),
+        });
+        Ok(())
+    }
+    // 管理：设置角色（Pubkey::default() 表示撤销，该权限仅所有者可用）
+    pub fn set_role(ctx: Context<SetOwnerConfig>, role: Role, key: Pubkey) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_owner(s, ctx.accounts.owner.key())?;
+        match role {
+            Role::ConfigAdmin => s.config_admin = key,
+            Role::Treasurer => s.treasurer = key,
+            Role::AgentManager => s.agent_manager = key,
+            Role::Pauser => s.pauser = key,
+        }
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::Role { role, key },
+        });
+        Ok(())
+    }
+    // 所有权转移第一步：提名新所有者（Pubkey::default() 表示撤销提名）
+    pub fn propose_owner(ctx: Context<SetOwnerConfig>, new_owner: Pubkey) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_owner(s, ctx.accounts.owner.key())?;
+        s.pending_owner = new_owner;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::OwnerProposed(new_owner),
+        });
+        Ok(())
+    }
+    // 所有权转移第二步：被提名者签名接受
+    pub fn accept_owner(ctx: Context<AcceptOwner>) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        let signer = ctx.accounts.new_owner.key();
+        require!(
+            s.pending_owner != Pubkey::default() && s.pending_owner == signer,
+            ErrorCode::NotPendingOwner
+        );
+        s.owner = signer;
+        s.pending_owner = Pubkey::default();
+        emit!(ConfigChanged {
+            admin: signer,
+            change: ConfigChange::OwnerChanged(signer),
+        });
+        Ok(())
+    }
+    // 紧急暂停：guardian 或所有者只能追加暂停位，不能解除暂停、也不能提取资金
+    pub fn guardian_pause(ctx: Context<GuardianPause>, mask: u8) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        let signer = ctx.accounts.authority.key();
+        require!(
+            signer == s.owner || (s.guardian != Pubkey::default() && signer == s.guardian),
+            ErrorCode::Unauthorized
+        );
+        require!(mask != 0 && mask & !PAUSE_ALL == 0, ErrorCode::InvalidPauseMask);
+        s.paused |= mask;
+        emit!(ConfigChanged {
+            admin: signer,
+            change: ConfigChange::Paused(s.paused),
+        });
+        Ok(())
+    }
+    // 管理：随机源（进行中的 PendingPlay 仍按其请求时记录的随机源结算）
+    pub fn set_randomness_source(
+        ctx: Context<SetOwnerConfig>,
+        source: RandomnessSource,
+    ) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        s.randomness_source = source;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::RandomnessSource(source),
+        });
+        Ok(())
+    }
+    // 管理：提交下一个种子承诺 hash(seed)；request_play 按序号依次绑定
+    pub fn post_seed_commitment(ctx: Context<PostSeedCommitment>, seed_hash: [u8; 32]) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::ConfigAdmin)?;
+        let c = &mut ctx.accounts.commitment;
+        c.index = s.commit_posted;
+        c.hash = seed_hash;
+        c.pending_play = Pubkey::default();
+        c.revealed = false;
+        c.seed = [0u8; 32];
+        c.bump = ctx.bumps.commitment;
+        s.commit_posted = s
+            .commit_posted
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::SeedCommitment {
+                index: c.index,
+                hash: seed_hash,
+            },
+        });
         Ok(())
     }
     // 管理：设置支付代币（绑定奖池账户与 mint）
@@ -142,25 +817,36 @@ pub mod slot_machine {
         s.pool_mint = ctx.accounts.token_mint.key();
         s.pool_token_account = ctx.accounts.pool_token_account.key();
         s.total_pool = ctx.accounts.pool_token_account.amount;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::PaymentToken {
+                mint: s.pool_mint,
+                pool_token_account: s.pool_token_account,
+            },
+        });
         Ok(())
     }
 
     // 管理：同步奖池余额
     pub fn sync_pool_total(ctx: Context<SyncPoolTotal>) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::Treasurer)?;
         require_keys_eq!(
             ctx.accounts.pool_token_account.key(),
             s.pool_token_account,
             ErrorCode::InvalidPoolAccount
         );
         s.total_pool = ctx.accounts.pool_token_account.amount;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::PoolSynced(s.total_pool),
+        });
         Ok(())
     }
     // 管理：提取奖池资金
     pub fn withdraw_pool(ctx: Context<WithdrawPool>, amount: u64) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        require_owner(s, ctx.accounts.owner.key())?;
+        require_role(s, ctx.accounts.owner.key(), Role::Treasurer)?;
         require_keys_eq!(
             ctx.accounts.pool_token_account.key(),
             s.pool_token_account,
@@ -188,9 +874,50 @@ pub mod slot_machine {
             .total_pool
             .checked_sub(amount)
             .ok_or(ErrorCode::MathOverflow)?;
+        emit!(PoolWithdrawn {
+            owner: ctx.accounts.owner.key(),
+            amount,
+            total_pool: s.total_pool,
+            closed: false,
+        });
+        Ok(())
+    }
+    // 管理：创建 jackpot 代币账户（PDA，authority 为 game_state，mint 与奖池一致）
+    pub fn init_jackpot(ctx: Context<InitJackpot>) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_owner(s, ctx.accounts.owner.key())?;
+        s.jackpot_token_account = ctx.accounts.jackpot_token_account.key();
+        s.jackpot_total = 0;
+        emit!(ConfigChanged {
+            admin: ctx.accounts.owner.key(),
+            change: ConfigChange::JackpotAccount(s.jackpot_token_account),
+        });
+        Ok(())
+    }
+    // 管理：向 jackpot 注入初始资金（只进不出，仅由中奖派出）
+    pub fn seed_jackpot(ctx: Context<SeedJackpot>, amount: u64) -> Result<()> {
+        let s = &mut ctx.accounts.game_state;
+        require_role(s, ctx.accounts.owner.key(), Role::Treasurer)?;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        pool_transfer_from_user(
+            &ctx.accounts.token_program,
+            &ctx.accounts.owner_token_account,
+            &ctx.accounts.jackpot_token_account,
+            &ctx.accounts.owner,
+            amount,
+        )?;
+        s.jackpot_total = s
+            .jackpot_total
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        emit!(JackpotSeeded {
+            funder: ctx.accounts.owner.key(),
+            amount,
+            jackpot_total: s.jackpot_total,
+        });
         Ok(())
     }
-    // 管理：关闭游戏（清空奖池）
+    // 管理：关闭游戏（清空奖池；jackpot 余额一并转给所有者并关闭 jackpot 账户）
     pub fn close_game(ctx: Context<CloseGame>) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
         require_owner(s, ctx.accounts.owner.key())?;
@@ -215,100 +942,136 @@ pub mod slot_machine {
             )?;
         }
         s.total_pool = 0;
+        emit!(PoolWithdrawn {
+            owner: ctx.accounts.owner.key(),
+            amount,
+            total_pool: 0,
+            closed: true,
+        });
+        // jackpot 账户的 authority 是 game_state，关闭后无人能再签名：余额转给所有者并关闭该账户
+        if s.jackpot_token_account != Pubkey::default() {
+            let jackpot = required_jackpot(ctx.accounts.jackpot_token_account.as_ref())?;
+            let amount = jackpot.amount;
+            if amount > 0 {
+                pool_transfer_signed(
+                    &*s,
+                    &ctx.accounts.token_program,
+                    jackpot,
+                    &ctx.accounts.owner_token_account,
+                    amount,
+                )?;
+            }
+            close_token_account_signed(
+                &*s,
+                &ctx.accounts.token_program,
+                jackpot,
+                &ctx.accounts.owner,
+            )?;
+            s.jackpot_total = 0;
+            emit!(JackpotWithdrawn {
+                owner: ctx.accounts.owner.key(),
+                amount,
+            });
+        }
         Ok(())
     }
 
-    // 代理商：SOL 质押成为代理商（分配房卡）
+    // 代理商：SOL 质押成为代理商（分配房卡）；质押 lamports 存放在代理商 PDA 中
     pub fn become_agent(ctx: Context<BecomeAgent>, stake_amount: u64) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
+        require_not_paused(s, PAUSE_AGENT_STAKE)?;
         require!(stake_amount >= s.stake_threshold, ErrorCode::StakeBelowThreshold);
-        require!(s.agents.len() < MAX_AGENT_COUNT, ErrorCode::TooManyAgents);
         let now = Clock::get()?.unix_timestamp;
+        let funder = match &ctx.accounts.funder {
+            Some(f) => f.to_account_info(),
+            None => ctx.accounts.agent.to_account_info(),
+        };
         invoke_sol_transfer(
-            &ctx.accounts.agent.to_account_info(),
-            &s.to_account_info(),
+            &funder,
+            &ctx.accounts.agent_account.to_account_info(),
             &ctx.accounts.system_program.to_account_info(),
             stake_amount,
         )?;
         let agent_key = ctx.accounts.agent.key();
-        if let Some(i) = s.agents.iter().position(|a| a.pubkey == agent_key) {
-            let mut room_card = s.agents[i].room_card;
-            if room_card == 0 {
-                room_card = s.next_room_card;
-                s.next_room_card = s
-                    .next_room_card
-                    .checked_add(1)
-                    .ok_or(ErrorCode::MathOverflow)?;
-            }
-            let a = &mut s.agents[i];
-            a.stake = a.stake.checked_add(stake_amount).ok_or(ErrorCode::MathOverflow)?;
-            a.is_active = true;
-            a.room_card = room_card;
-            a.stake_time = now;
-            if a.last_settlement < 0 {
-                a.last_settlement = now;
-            }
-            return Ok(());
+        let a = &mut ctx.accounts.agent_account;
+        if a.agent == Pubkey::default() {
+            a.agent = agent_key;
+            a.bump = ctx.bumps.agent_account;
+            a.last_settlement = now;
         }
-        let room_card = s.next_room_card;
-        s.next_room_card = s
-            .next_room_card
-            .checked_add(1)
-            .ok_or(ErrorCode::MathOverflow)?;
-        s.agents.push(Agent {
-            pubkey: agent_key,
-            stake: stake_amount,
-            room_card,
-            commission: 0,
-            stake_time: now,
-            last_settlement: now,
-            is_active: true,
+        // 记录首个代付质押的钱包：其作为玩家时不计佣金；之后他人追加质押不会覆盖
+        if funder.key() != agent_key && a.stake_funder == Pubkey::default() {
+            a.stake_funder = funder.key();
+        }
+        if a.room_card == 0 {
+            a.room_card = s.next_room_card;
+            s.next_room_card = s
+                .next_room_card
+                .checked_add(1)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        a.stake = a.stake.checked_add(stake_amount).ok_or(ErrorCode::MathOverflow)?;
+        a.is_active = true;
+        a.stake_time = now;
+        if a.last_settlement < 0 {
+            a.last_settlement = now;
+        }
+        let idx = &mut ctx.accounts.room_card_index;
+        require!(
+            idx.agent == Pubkey::default() || idx.agent == agent_key,
+            ErrorCode::InvalidRoomCard
+        );
+        idx.room_card = a.room_card;
+        idx.agent = agent_key;
+        idx.bump = ctx.bumps.room_card_index;
+        emit!(AgentStaked {
+            agent: agent_key,
+            amount: stake_amount,
+            total_stake: a.stake,
+            room_card: a.room_card,
         });
         Ok(())
     }
-    // 代理商：赎回质押（stake/房卡/佣金）
+    // 代理商：赎回质押（stake/房卡/佣金），同时关闭房卡索引
     pub fn redeem_agent_stake(ctx: Context<RedeemAgentStake>) -> Result<()> {
-        let s = &mut ctx.accounts.game_state;
-        let k = ctx.accounts.agent.key();
-        let i = s
-            .agents
-            .iter()
-            .position(|a| a.pubkey == k)
-            .ok_or(ErrorCode::AgentNotFound)?;
-        let amount = s.agents[i].stake;
+        let a = &mut ctx.accounts.agent_account;
+        let amount = a.stake;
+        let room_card = a.room_card;
         require!(amount > 0, ErrorCode::NoStakeToRedeem);
-        let vault = **s.to_account_info().lamports.borrow();
-        require!(vault >= amount, ErrorCode::InsufficientStakeVault);
-        **s.to_account_info().try_borrow_mut_lamports()? -= amount;
+        let info = a.to_account_info();
+        let rent = Rent::get()?.minimum_balance(info.data_len());
+        let needed = amount.checked_add(rent).ok_or(ErrorCode::MathOverflow)?;
+        require!(info.lamports() >= needed, ErrorCode::InsufficientStakeVault);
+        **info.try_borrow_mut_lamports()? -= amount;
         **ctx.accounts.agent.to_account_info().try_borrow_mut_lamports()? += amount;
-        let a = &mut s.agents[i];
         a.stake = 0;
         a.room_card = 0;
         a.commission = 0;
+        emit!(AgentRedeemed {
+            agent: a.agent,
+            amount,
+            room_card,
+        });
         Ok(())
     }
     // 代理商：提取佣金（需满足结算周期）
     pub fn withdraw_commission(ctx: Context<WithdrawCommission>) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
+        require_not_paused(s, PAUSE_COMMISSION)?;
         require_keys_eq!(
             ctx.accounts.pool_token_account.key(),
             s.pool_token_account,
             ErrorCode::InvalidPoolAccount
         );
-        let k = ctx.accounts.agent.key();
-        let i = s
-            .agents
-            .iter()
-            .position(|a| a.pubkey == k)
-            .ok_or(ErrorCode::AgentNotFound)?;
+        let a = &mut ctx.accounts.agent_account;
         let now = Clock::get()?.unix_timestamp;
-        require!(s.agents[i].room_card > 0, ErrorCode::AgentInactive);
-        let elapsed = now.saturating_sub(s.agents[i].last_settlement);
+        require!(a.room_card > 0, ErrorCode::AgentInactive);
+        let elapsed = now.saturating_sub(a.last_settlement);
         require!(
             elapsed >= s.settlement_period as i64,
             ErrorCode::SettlementNotReady
         );
-        let amount: u64 = s.agents[i]
+        let amount: u64 = a
             .commission
             .try_into()
             .map_err(|_| error!(ErrorCode::InvalidCommissionBalance))?;
@@ -329,36 +1092,206 @@ pub mod slot_machine {
             .total_pool
             .checked_sub(amount)
             .ok_or(ErrorCode::MathOverflow)?;
-        s.agents[i].commission = 0;
-        s.agents[i].last_settlement = now;
+        a.commission = 0;
+        a.last_settlement = now;
+        emit!(CommissionWithdrawn {
+            agent: a.agent,
+            amount,
+            total_pool: s.total_pool,
+        });
+        Ok(())
+    }
+    // 管理：将 GameState 内联的旧代理商迁移到独立 PDA（可分批调用）；
+    // 旧版布局的 game_state 先扩容并按新布局重写（扩容租金由调用者支付）；
+    // remaining_accounts 依次为每个代理商的 [agent_account, room_card_index?]（房卡为 0 时不传索引），
+    // 质押 lamports 从 game_state 转入代理商 PDA
+    pub fn migrate_agents<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MigrateAgents<'info>>,
+    ) -> Result<()> {
+        let vault = ctx.accounts.game_state.to_account_info();
+        let owner = ctx.accounts.owner.to_account_info();
+        let sys = ctx.accounts.system_program.to_account_info();
+        let upgraded = vault.data_len() == 8 + LegacyGameState::SPACE;
+        if upgraded {
+            upgrade_legacy_game_state(&vault, &owner, &sys)?;
+        }
+        let mut state = GameState::try_deserialize(&mut &vault.try_borrow_data()?[..])?;
+        let s = &mut state;
+        require_keys_eq!(vault.key(), game_state_address(s)?, ErrorCode::InvalidMigrationAccount);
+        require_role(s, owner.key(), Role::AgentManager)?;
+        let game_key = vault.key();
+        let mut rest = ctx.remaining_accounts;
+        // 仅升级布局时可不带代理商账户
+        require!(upgraded || !rest.is_empty(), ErrorCode::InvalidMigrationAccount);
+        while !rest.is_empty() {
+            let legacy = s.agents.first().cloned().ok_or(ErrorCode::AgentNotFound)?;
+            let info = &rest[0];
+            let (expected, bump) = Pubkey::find_program_address(
+                &[AGENT_SEED, game_key.as_ref(), legacy.pubkey.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(info.key(), expected, ErrorCode::InvalidMigrationAccount);
+            create_pda_account(
+                &owner,
+                info,
+                &sys,
+                8 + AgentAccount::SPACE,
+                &[AGENT_SEED, game_key.as_ref(), legacy.pubkey.as_ref(), &[bump]],
+            )?;
+            let agent = AgentAccount {
+                agent: legacy.pubkey,
+                bump,
+                stake: legacy.stake,
+                room_card: legacy.room_card,
+                commission: legacy.commission,
+                stake_time: legacy.stake_time,
+                last_settlement: legacy.last_settlement,
+                is_active: legacy.is_active,
+                stake_funder: Pubkey::default(),
+                referred_volume: 0,
+                parent: Pubkey::default(),
+                commission_override: None,
+                period_start: 0,
+                period_volume: 0,
+                prev_period_volume: 0,
+            };
+            agent.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+            if legacy.stake > 0 {
+                require!(vault.lamports() >= legacy.stake, ErrorCode::InsufficientStakeVault);
+                **vault.try_borrow_mut_lamports()? -= legacy.stake;
+                **info.try_borrow_mut_lamports()? += legacy.stake;
+            }
+            let mut used = 1;
+            if legacy.room_card > 0 {
+                require!(rest.len() >= 2, ErrorCode::InvalidMigrationAccount);
+                let idx_info = &rest[1];
+                let card = legacy.room_card.to_le_bytes();
+                let (expected, bump) = Pubkey::find_program_address(
+                    &[ROOM_CARD_SEED, game_key.as_ref(), &card],
+                    &crate::ID,
+                );
+                require_keys_eq!(idx_info.key(), expected, ErrorCode::InvalidMigrationAccount);
+                create_pda_account(
+                    &owner,
+                    idx_info,
+                    &sys,
+                    8 + RoomCardIndex::SPACE,
+                    &[ROOM_CARD_SEED, game_key.as_ref(), &card, &[bump]],
+                )?;
+                let idx = RoomCardIndex {
+                    room_card: legacy.room_card,
+                    agent: legacy.pubkey,
+                    bump,
+                };
+                idx.try_serialize(&mut &mut idx_info.try_borrow_mut_data()?[..])?;
+                used = 2;
+            }
+            s.agents.remove(0);
+            emit!(AgentMigrated {
+                agent: legacy.pubkey,
+                stake: legacy.stake,
+                room_card: legacy.room_card,
+            });
+            rest = &rest[used..];
+        }
+        state.try_serialize(&mut &mut vault.try_borrow_mut_data()?[..])?;
         Ok(())
     }
 
+    // 玩家：绑定房卡。首次绑定随时可用；已绑定时需满足 rebind_cooldown，
+    // 原代理商已赎回（传入 bound_agent_account 证明）时不受冷却限制
+    pub fn bind_room_card(ctx: Context<BindRoomCard>, room_card: u64) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        let now = Clock::get()?.unix_timestamp;
+        let agent = &ctx.accounts.agent_account;
+        check_room_card_agent(Some(agent), Some(room_card))?;
+        let player_key = ctx.accounts.player.key();
+        let stats = &mut ctx.accounts.player_stats;
+        if stats.player == Pubkey::default() {
+            stats.player = player_key;
+            stats.bump = ctx.bumps.player_stats;
+        }
+        if stats.room_card == room_card && stats.bound_agent == agent.agent {
+            return Ok(());
+        }
+        if stats.room_card != 0 {
+            let lapsed = match &ctx.accounts.bound_agent_account {
+                Some(b) => binding_lapsed(stats, b)?,
+                None => false,
+            };
+            if !lapsed {
+                let ready = s.rebind_cooldown != REBIND_NEVER
+                    && now.saturating_sub(stats.bound_at).max(0) as u64 >= s.rebind_cooldown;
+                require!(ready, ErrorCode::RebindCooldown);
+            }
+        }
+        bind_player(stats, agent, room_card, now)
+    }
+
     // 即时玩法：下注→读取 VRF →派彩→代理佣金
-    pub fn play(ctx: Context<Play>, bets: [u64; 6], room_card: Option<u64>) -> Result<()> {
+    // remaining_accounts：使用房卡时依次传入各层上级代理商账户
+    pub fn play<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Play<'info>>,
+        bets: [u64; 6],
+        room_card: Option<u64>,
+    ) -> Result<()> {
         let s = &mut ctx.accounts.game_state;
-        validate_bets(&bets, s.min_bet)?;
+        require_not_paused(s, PAUSE_PLAY)?;
+        require!(
+            s.randomness_source == RandomnessSource::Switchboard,
+            ErrorCode::RandomnessSourceMismatch
+        );
+        let slot = Clock::get()?.slot;
+        apply_due_config(s, slot)?;
+        let odds = s.odds();
+        let lines = active_lines(&odds, &ctx.accounts.game_config)?;
+        validate_bets(&bets, s, &odds, &lines)?;
         require_keys_eq!(
             ctx.accounts.pool_token_account.key(),
             s.pool_token_account,
             ErrorCode::InvalidPoolAccount
         );
+        let player_key = ctx.accounts.player.key();
+        let stats = &mut ctx.accounts.player_stats;
+        if stats.player == Pubkey::default() {
+            stats.player = player_key;
+            stats.bump = ctx.bumps.player_stats;
+        }
+        let room_card = resolve_room_card(
+            stats,
+            ctx.accounts.agent_account.as_deref(),
+            room_card,
+            Clock::get()?.unix_timestamp,
+        )?;
         let total_bet = bets_total(&bets)?;
+        let contribution = jackpot_contribution(total_bet, &odds)?;
+        let stake = total_bet - contribution;
         pool_transfer_from_user(
             &ctx.accounts.token_program,
             &ctx.accounts.player_token_account,
             &ctx.accounts.pool_token_account,
             &ctx.accounts.player,
-            total_bet,
+            stake,
         )?;
         s.total_pool = s
             .total_pool
-            .checked_add(total_bet)
+            .checked_add(stake)
             .ok_or(ErrorCode::MathOverflow)?;
+        if contribution > 0 {
+            pool_transfer_from_user(
+                &ctx.accounts.token_program,
+                &ctx.accounts.player_token_account,
+                required_jackpot(ctx.accounts.jackpot_token_account.as_ref())?,
+                &ctx.accounts.player,
+                contribution,
+            )?;
+            accrue_jackpot(s, ctx.accounts.player.key(), contribution)?;
+        }
         let vrf = read_vrf_bytes(&ctx.accounts.vrf, s.vrf, s.vrf_result_offset)?;
-        let seed = derive_seed(vrf, Some(ctx.accounts.player.key()), Some(s.nonce), Some(Clock::get()?.slot), None);
+        let seed = derive_seed(vrf, Some(ctx.accounts.player.key()), Some(s.nonce), Some(slot), None);
         s.nonce = s.nonce.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
-        let (payout, _, _, _) = compute_total_payout(seed, &bets, s)?;
+        let mut t = compute_total_payout(seed, &bets, &odds, &lines)?;
+        let payout = t.payout;
         if payout > 0 {
             require!(
                 ctx.accounts.pool_token_account.amount >= payout,
@@ -377,25 +1310,122 @@ pub mod slot_machine {
                 .checked_sub(payout)
                 .ok_or(ErrorCode::MathOverflow)?;
         }
-        apply_agent_commission(s, room_card, total_bet, payout)?;
+        if jackpot_hit(seed, &bets, &t, &odds, &lines)? {
+            t.jackpot = pay_jackpot(
+                s,
+                &ctx.accounts.token_program,
+                ctx.accounts.jackpot_token_account.as_ref(),
+                &ctx.accounts.player_token_account,
+                ctx.accounts.player.key(),
+            )?;
+        }
+        let mut upline = match (room_card, ctx.accounts.agent_account.as_deref()) {
+            (Some(_), Some(a)) => load_upline(s, s.key(), a, ctx.remaining_accounts)?,
+            _ => Vec::new(),
+        };
+        require!(
+            upline.len() == ctx.remaining_accounts.len(),
+            ErrorCode::InvalidUplineAccount
+        );
+        apply_agent_commission(
+            s,
+            ctx.accounts.commission_schedule.as_deref(),
+            ctx.accounts.agent_account.as_deref_mut(),
+            &mut upline,
+            &mut ctx.accounts.player_stats,
+            room_card,
+            total_bet,
+            contribution,
+            payout,
+            Clock::get()?.unix_timestamp,
+        )?;
+        for x in &upline {
+            x.exit(&crate::ID)?;
+        }
+        let profile = &mut ctx.accounts.player_profile;
+        if profile.player == Pubkey::default() {
+            profile.player = player_key;
+            profile.bump = ctx.bumps.player_profile;
+        }
+        profile.last_result = LastResult::new(&t, total_bet, Clock::get()?.slot);
+        ctx.accounts
+            .player_stats
+            .record(total_bet, &t, Clock::get()?.slot)?;
+        set_return_data(&t.try_to_vec()?);
+        emit!(PlayResolved {
+            player: player_key,
+            bets,
+            total_bet,
+            spins: t.spins,
+            doubles: t.doubles,
+            multiplier: t.multiplier,
+            payout,
+            room_card,
+        });
         Ok(())
     }
-    // 两段式玩法：先扣款记录 VRF 前镜像；结算时要求 VRF 结果更新
-    pub fn request_play(ctx: Context<RequestPlay>, bets: [u64; 6], room_card: Option<u64>) -> Result<()> {
-        let s = &mut ctx.accounts.game_state;
-        validate_bets(&bets, s.min_bet)?;
+    // 两段式玩法：扣款后按随机源绑定本局随机数——Switchboard 模式 CPI 请求新 VRF 轮次，
+    // 承诺-揭示模式绑定下一个未使用的种子承诺
+    pub fn request_play(
+        ctx: Context<RequestPlay>,
+        bets: [u64; 6],
+        room_card: Option<u64>,
+        client_seed: [u8; 32],
+        switchboard_state_bump: u8,
+        permission_bump: u8,
+    ) -> Result<()> {
+        let s = &ctx.accounts.game_state;
+        require_not_paused(s, PAUSE_REQUEST_PLAY)?;
+        let slot = Clock::get()?.slot;
+        let (config_version, odds) = current_odds(s, slot)?;
+        let lines = active_lines(&odds, &ctx.accounts.game_config)?;
+        let max_payout = validate_bets(&bets, s, &odds, &lines)?;
         require_keys_eq!(
             ctx.accounts.pool_token_account.key(),
             s.pool_token_account,
             ErrorCode::InvalidPoolAccount
         );
-        require_keys_eq!(ctx.accounts.vrf.key(), s.vrf, ErrorCode::InvalidVrfAccount);
-        if let Some(card) = room_card {
-            require!(
-                find_active_agent_by_room_card(s, card).is_some(),
-                ErrorCode::InvalidRoomCard
-            );
+        let source = s.randomness_source;
+        match source {
+            RandomnessSource::Switchboard => {
+                let vrf = required(&ctx.accounts.vrf)?;
+                let i = vrf_slot(s, vrf.key())?;
+                let lock = s.vrf_pool[i];
+                if lock.pending_play != Pubkey::default() {
+                    // 锁超时后任何请求可接管；原轮次已写回结果时须先结算，避免原玩家借超时退款
+                    let deadline = lock
+                        .request_slot
+                        .checked_add(s.play_timeout_slots)
+                        .ok_or(ErrorCode::MathOverflow)?;
+                    require!(slot >= deadline, ErrorCode::VrfRequestInFlight);
+                    let (counter, result) = read_vrf_round(vrf, lock.vrf, s.key())?;
+                    require!(
+                        counter != lock.counter || result == [0u8; 32],
+                        ErrorCode::PlayAlreadyFulfilled
+                    );
+                }
+            }
+            RandomnessSource::CommitReveal => {
+                require_keys_eq!(
+                    required(&ctx.accounts.commitment)?.pending_play,
+                    Pubkey::default(),
+                    ErrorCode::CommitmentAlreadyBound
+                );
+            }
         }
+        // 统计账户在请求时创建，结算（可能由 keeper 发起）时只需更新
+        let player_key = ctx.accounts.player.key();
+        let stats = &mut ctx.accounts.player_stats;
+        if stats.player == Pubkey::default() {
+            stats.player = player_key;
+            stats.bump = ctx.bumps.player_stats;
+        }
+        let room_card = resolve_room_card(
+            stats,
+            ctx.accounts.agent_account.as_deref(),
+            room_card,
+            Clock::get()?.unix_timestamp,
+        )?;
         let total_bet = bets_total(&bets)?;
         pool_transfer_from_user(
             &ctx.accounts.token_program,
@@ -404,16 +1434,71 @@ pub mod slot_machine {
             &ctx.accounts.player,
             total_bet,
         )?;
+        let mut vrf_key = Pubkey::default();
+        let mut vrf_counter = 0u128;
+        if source == RandomnessSource::Switchboard {
+            let vrf = required(&ctx.accounts.vrf)?;
+            vrf_key = vrf.key();
+            let (counter_before, _) = read_vrf_round(vrf, vrf_key, s.key())?;
+            request_vrf_randomness(ctx.accounts, switchboard_state_bump, permission_bump)?;
+            let (counter, _) = read_vrf_round(vrf, vrf_key, s.key())?;
+            require!(counter > counter_before, ErrorCode::VrfNotUpdated);
+            vrf_counter = counter;
+        }
+
+        let pending_key = ctx.accounts.pending_play.key();
+        let profile = &mut ctx.accounts.player_profile;
+        if profile.player == Pubkey::default() {
+            profile.player = player_key;
+            profile.bump = ctx.bumps.player_profile;
+        }
+        let seq = profile.next_play_seq;
+        profile.next_play_seq = seq.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        profile.open_plays = profile
+            .open_plays
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let s = &mut ctx.accounts.game_state;
         s.total_pool = s
             .total_pool
             .checked_add(total_bet)
             .ok_or(ErrorCode::MathOverflow)?;
-        let before = read_vrf_bytes(&ctx.accounts.vrf, s.vrf, s.vrf_result_offset)?;
+        s.outstanding_liability = s
+            .outstanding_liability
+            .checked_add(max_payout)
+            .ok_or(ErrorCode::MathOverflow)?;
         let nonce = s.nonce;
         s.nonce = s.nonce.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
-        let slot = Clock::get()?.slot;
+        let mut commitment_index = 0u64;
+        match source {
+            RandomnessSource::Switchboard => {
+                let i = vrf_slot(s, vrf_key)?;
+                s.vrf_pool[i] = VrfSlot {
+                    vrf: vrf_key,
+                    pending_play: pending_key,
+                    counter: vrf_counter,
+                    request_slot: slot,
+                };
+            }
+            RandomnessSource::CommitReveal => {
+                commitment_index = s.commit_bound;
+                s.commit_bound = s
+                    .commit_bound
+                    .checked_add(1)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                let c = ctx
+                    .accounts
+                    .commitment
+                    .as_mut()
+                    .ok_or(ErrorCode::MissingRandomnessAccount)?;
+                c.pending_play = pending_key;
+            }
+        }
         let p = &mut ctx.accounts.pending_play;
-        p.player = ctx.accounts.player.key();
+        p.player = player_key;
+        p.seq = seq;
+        p.bump = ctx.bumps.pending_play;
         p.player_token_account = ctx.accounts.player_token_account.key();
         p.pool_token_account = ctx.accounts.pool_token_account.key();
         p.request_nonce = nonce;
@@ -422,17 +1507,292 @@ pub mod slot_machine {
         p.bets = bets;
         p.has_room_card = room_card.is_some();
         p.room_card = room_card.unwrap_or_default();
-        p.vrf_result_before = before;
+        p.agent = match room_card {
+            Some(_) => required_agent(&ctx.accounts.agent_account)?.key(),
+            None => Pubkey::default(),
+        };
+        p.source = source;
+        p.vrf = vrf_key;
+        p.vrf_counter = vrf_counter;
+        p.commitment_index = commitment_index;
+        p.client_seed = client_seed;
+        p.config_version = config_version;
+        p.odds = odds;
+        p.lines = lines;
+        p.jackpot_contribution = jackpot_contribution(total_bet, &odds)?;
+        p.max_payout = max_payout;
+        emit!(PlayRequested {
+            pending_play: pending_key,
+            player: player_key,
+            seq,
+            bets,
+            total_bet,
+            room_card,
+            source,
+            request_slot: slot,
+            config_version,
+        });
+        Ok(())
+    }
+    // 结算：Switchboard 模式只接受本局请求轮次（counter 一致）已写回的结果；
+    // 承诺-揭示模式要求揭示值与绑定承诺的哈希一致，并混合玩家 client seed；
+    // 签名者不是玩家本人时按 keeper_fee_bps 从奖池支付 keeper 奖励
+    // remaining_accounts：有房卡的局依次传入各层上级代理商账户
+    pub fn settle_play<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettlePlay<'info>>,
+        reveal: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let pending_key = ctx.accounts.pending_play.key();
+        let a = &mut *ctx.accounts;
+        let mut upline = match (a.pending_play.has_room_card, a.agent_account.as_deref()) {
+            (true, Some(x)) => {
+                load_upline(&a.game_state, a.game_state.key(), x, ctx.remaining_accounts)?
+            }
+            _ => Vec::new(),
+        };
+        require!(
+            upline.len() == ctx.remaining_accounts.len(),
+            ErrorCode::InvalidUplineAccount
+        );
+        let s = &mut a.game_state;
+        require_not_paused(s, PAUSE_SETTLE)?;
+        require_keys_eq!(
+            a.pool_token_account.key(),
+            s.pool_token_account,
+            ErrorCode::InvalidPoolAccount
+        );
+        let p = &a.pending_play;
+        check_pending_accounts(
+            p,
+            a.player.key(),
+            a.player_token_account.key(),
+            a.pool_token_account.key(),
+        )?;
+        let seed = resolve_pending_seed(
+            s,
+            pending_key,
+            p,
+            a.vrf.as_deref(),
+            a.commitment.as_deref_mut(),
+            reveal,
+        )?;
+        let outcome = settle_pending_payout(
+            s,
+            &a.token_program,
+            &a.pool_token_account,
+            &a.player_token_account,
+            p,
+            seed,
+            a.agent_account.as_mut(),
+            &mut upline,
+            &mut a.player_stats,
+            a.jackpot_token_account.as_ref(),
+            a.commission_schedule.as_deref(),
+        )?;
+        for x in &upline {
+            x.exit(&crate::ID)?;
+        }
+        close_open_play(&mut a.player_profile)?;
+        a.player_profile.last_result = LastResult::new(&outcome, p.total_bet, Clock::get()?.slot);
+        a.player_stats.record(p.total_bet, &outcome, Clock::get()?.slot)?;
+        let transcript = outcome.try_to_vec()?;
+        let mut fee = 0;
+        if a.keeper.key() != p.player {
+            fee = keeper_fee(s, p.total_bet)?;
+        }
+        emit_play_settled(pending_key, p, outcome, a.keeper.key(), fee);
+        if fee > 0 {
+            a.pool_token_account.reload()?;
+            pay_keeper_fee(
+                s,
+                &a.token_program,
+                &a.pool_token_account,
+                a.keeper_token_account.as_ref(),
+                fee,
+            )?;
+        }
+        // 代币 CPI 会清空返回数据，须在最后一次 CPI 之后写入
+        set_return_data(&transcript);
+        Ok(())
+    }
+    // 批量结算：remaining_accounts 按局依次传入
+    // [pending_play, player_profile, player_stats, player, player_token_account,
+    //  (有房卡) agent_account 及各层上级代理商,
+    //  (Switchboard 模式) 本局请求所用的 vrf / (承诺-揭示模式) commitment]，
+    // 承诺-揭示局按顺序消耗 reveals；keeper 奖励按非本人结算的局累加后一次支付
+    pub fn settle_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleMany<'info>>,
+        reveals: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let a = &mut *ctx.accounts;
+        require_not_paused(&a.game_state, PAUSE_SETTLE)?;
+        require_keys_eq!(
+            a.pool_token_account.key(),
+            a.game_state.pool_token_account,
+            ErrorCode::InvalidPoolAccount
+        );
+        let game_key = a.game_state.key();
+        let keeper = a.keeper.key();
+        let mut reveals = reveals.into_iter();
+        let mut fee_total = 0u64;
+        let mut rest = ctx.remaining_accounts;
+        require!(!rest.is_empty(), ErrorCode::InvalidSettleBatch);
+        while !rest.is_empty() {
+            require!(rest.len() >= 5, ErrorCode::InvalidSettleBatch);
+            let pending_info = &rest[0];
+            let pending = Account::<PendingPlay>::try_from(pending_info)?;
+            let p: &PendingPlay = &pending;
+            let expected = Pubkey::create_program_address(
+                &[
+                    PENDING_PLAY_SEED,
+                    game_key.as_ref(),
+                    p.player.as_ref(),
+                    &p.seq.to_le_bytes(),
+                    &[p.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::InvalidSettleBatch))?;
+            require_keys_eq!(pending_info.key(), expected, ErrorCode::InvalidSettleBatch);
+            let mut profile = Account::<PlayerProfile>::try_from(&rest[1])?;
+            let expected = Pubkey::create_program_address(
+                &[
+                    PLAYER_PROFILE_SEED,
+                    game_key.as_ref(),
+                    p.player.as_ref(),
+                    &[profile.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::InvalidSettleBatch))?;
+            require_keys_eq!(rest[1].key(), expected, ErrorCode::InvalidSettleBatch);
+            let mut stats = Account::<PlayerStats>::try_from(&rest[2])?;
+            let expected = Pubkey::create_program_address(
+                &[
+                    PLAYER_STATS_SEED,
+                    game_key.as_ref(),
+                    p.player.as_ref(),
+                    &[stats.bump],
+                ],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::InvalidSettleBatch))?;
+            require_keys_eq!(rest[2].key(), expected, ErrorCode::InvalidSettleBatch);
+            let player = &rest[3];
+            let player_token_account = Account::<TokenAccount>::try_from(&rest[4])?;
+            check_pending_accounts(
+                p,
+                player.key(),
+                player_token_account.key(),
+                a.pool_token_account.key(),
+            )?;
+            let mut used = 5;
+            let mut agent = None;
+            let mut upline = Vec::new();
+            if p.has_room_card {
+                require!(rest.len() > used, ErrorCode::InvalidSettleBatch);
+                let x = Account::<AgentAccount>::try_from(&rest[used])?;
+                used += 1;
+                upline = load_upline(&a.game_state, game_key, &x, &rest[used..])?;
+                used += upline.len();
+                agent = Some(x);
+            }
+            let mut commitment = None;
+            let mut vrf = None;
+            if p.source == RandomnessSource::Switchboard {
+                require!(rest.len() > used, ErrorCode::InvalidSettleBatch);
+                vrf = Some(&rest[used]);
+                used += 1;
+            }
+            if p.source == RandomnessSource::CommitReveal {
+                require!(rest.len() > used, ErrorCode::InvalidSettleBatch);
+                let c = Account::<SeedCommitment>::try_from(&rest[used])?;
+                let expected = Pubkey::create_program_address(
+                    &[
+                        COMMITMENT_SEED,
+                        game_key.as_ref(),
+                        &p.commitment_index.to_le_bytes(),
+                        &[c.bump],
+                    ],
+                    &crate::ID,
+                )
+                .map_err(|_| error!(ErrorCode::InvalidSettleBatch))?;
+                require_keys_eq!(rest[used].key(), expected, ErrorCode::InvalidSettleBatch);
+                commitment = Some(c);
+                used += 1;
+            }
+            let reveal = match p.source {
+                RandomnessSource::Switchboard => None,
+                RandomnessSource::CommitReveal => {
+                    Some(reveals.next().ok_or(ErrorCode::InvalidReveal)?)
+                }
+            };
+            let s = &mut a.game_state;
+            let seed = resolve_pending_seed(
+                s,
+                pending_info.key(),
+                p,
+                vrf,
+                commitment.as_deref_mut(),
+                reveal,
+            )?;
+            let outcome = settle_pending_payout(
+                s,
+                &a.token_program,
+                &a.pool_token_account,
+                &player_token_account,
+                p,
+                seed,
+                agent.as_mut(),
+                &mut upline,
+                &mut stats,
+                a.jackpot_token_account.as_ref(),
+                a.commission_schedule.as_deref(),
+            )?;
+            a.pool_token_account.reload()?;
+            close_open_play(&mut profile)?;
+            profile.last_result = LastResult::new(&outcome, p.total_bet, Clock::get()?.slot);
+            stats.record(p.total_bet, &outcome, Clock::get()?.slot)?;
+            let mut fee = 0;
+            if keeper != p.player {
+                fee = keeper_fee(s, p.total_bet)?;
+                fee_total = fee_total.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+            }
+            emit_play_settled(pending_info.key(), p, outcome, keeper, fee);
+            profile.exit(&crate::ID)?;
+            stats.exit(&crate::ID)?;
+            if let Some(x) = agent {
+                x.exit(&crate::ID)?;
+            }
+            for x in &upline {
+                x.exit(&crate::ID)?;
+            }
+            if let Some(c) = commitment {
+                c.exit(&crate::ID)?;
+            }
+            pending.close(player.clone())?;
+            rest = &rest[used..];
+        }
+        require!(reveals.next().is_none(), ErrorCode::InvalidSettleBatch);
+        pay_keeper_fee(
+            &mut a.game_state,
+            &a.token_program,
+            &a.pool_token_account,
+            a.keeper_token_account.as_ref(),
+            fee_total,
+        )?;
         Ok(())
     }
-    pub fn settle_play(ctx: Context<SettlePlay>) -> Result<()> {
+    // 超时退款：请求后超过 play_timeout_slots 仍未结算，任何人可触发；
+    // 全额退还 total_bet 并关闭 PendingPlay 退还租金，不触及代理佣金
+    pub fn cancel_expired_play(ctx: Context<CancelExpiredPlay>) -> Result<()> {
+        let pending_key = ctx.accounts.pending_play.key();
         let s = &mut ctx.accounts.game_state;
         require_keys_eq!(
             ctx.accounts.pool_token_account.key(),
             s.pool_token_account,
             ErrorCode::InvalidPoolAccount
         );
-        require_keys_eq!(ctx.accounts.vrf.key(), s.vrf, ErrorCode::InvalidVrfAccount);
         let p = &ctx.accounts.pending_play;
         require_keys_eq!(p.player, ctx.accounts.player.key(), ErrorCode::PlayerMismatch);
         require_keys_eq!(
@@ -445,48 +1805,63 @@ pub mod slot_machine {
             ctx.accounts.pool_token_account.key(),
             ErrorCode::InvalidPoolAccount
         );
-        let after = read_vrf_bytes(&ctx.accounts.vrf, s.vrf, s.vrf_result_offset)?;
-        require!(after != p.vrf_result_before, ErrorCode::VrfNotUpdated);
-        let seed = derive_seed(
-            p.vrf_result_before,
-            Some(p.player),
-            Some(p.request_nonce),
-            Some(p.request_slot),
-            Some(after),
-        );
-        let (payout, _, _, _) = compute_total_payout(seed, &p.bets, s)?;
-        if payout > 0 {
+        let deadline = p
+            .request_slot
+            .checked_add(s.play_timeout_slots)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(Clock::get()?.slot >= deadline, ErrorCode::PlayNotExpired);
+        let lock = s.vrf_pool.iter().position(|x| x.pending_play == pending_key);
+        if let (RandomnessSource::Switchboard, Some(i)) = (p.source, lock) {
+            // 本局轮次已写回结果时只能 settle_play，避免看到结果后选择性退款；
+            // 锁已被其他请求接管时原轮次已作废，可直接退款
+            let vrf = required(&ctx.accounts.vrf)?;
+            let (counter, result) = read_vrf_round(vrf, p.vrf, s.key())?;
             require!(
-                ctx.accounts.pool_token_account.amount >= payout,
-                ErrorCode::InsufficientPool
+                counter != p.vrf_counter || result == [0u8; 32],
+                ErrorCode::PlayAlreadyFulfilled
             );
-            require!(s.total_pool >= payout, ErrorCode::InsufficientPool);
-            pool_transfer_signed(
-                &*s,
-                &ctx.accounts.token_program,
-                &ctx.accounts.pool_token_account,
-                &ctx.accounts.player_token_account,
-                payout,
-            )?;
-            s.total_pool = s
-                .total_pool
-                .checked_sub(payout)
-                .ok_or(ErrorCode::MathOverflow)?;
+            s.vrf_pool[i].pending_play = Pubkey::default();
         }
-        apply_agent_commission(
-            s,
-            p.has_room_card.then_some(p.room_card),
-            p.total_bet,
-            payout,
+        let amount = p.total_bet;
+        require!(
+            ctx.accounts.pool_token_account.amount >= amount,
+            ErrorCode::InsufficientPool
+        );
+        require!(s.total_pool >= amount, ErrorCode::InsufficientPool);
+        pool_transfer_signed(
+            &*s,
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool_token_account,
+            &ctx.accounts.player_token_account,
+            amount,
         )?;
+        s.total_pool = s
+            .total_pool
+            .checked_sub(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        release_liability(s, p)?;
+        close_open_play(&mut ctx.accounts.player_profile)?;
+        emit!(PlayCancelled {
+            pending_play: pending_key,
+            player: p.player,
+            seq: p.seq,
+            refund: amount,
+        });
         Ok(())
     }
 }
 
 // 账户
 #[derive(Accounts)]
+#[instruction(game_id: u64)]
 pub struct Initialize<'info> {
-    #[account(init, payer = user, space = 8 + GameState::SPACE, seeds = [GAME_STATE_SEED], bump)]
+    #[account(
+        init,
+        payer = user,
+        space = 8 + GameState::SPACE,
+        seeds = [GAME_STATE_SEED, &game_id_seed(game_id)],
+        bump
+    )]
     pub game_state: Account<'info, GameState>,
     #[account(mut)]
     pub user: Signer<'info>,
@@ -498,14 +1873,94 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>,
 }
 #[derive(Accounts)]
+pub struct ViewGameState<'info> {
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    // 网格布局时传入
+    #[account(seeds = [GAME_CONFIG_SEED, game_state.key().as_ref()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
+}
+#[derive(Accounts)]
+pub struct InitGameConfig<'info> {
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + GameConfig::SPACE,
+        seeds = [GAME_CONFIG_SEED, game_state.key().as_ref()],
+        bump
+    )]
+    pub game_config: Account<'info, GameConfig>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
+pub struct SetGameConfig<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        seeds = [GAME_CONFIG_SEED, game_state.key().as_ref()],
+        bump = game_config.bump
+    )]
+    pub game_config: Account<'info, GameConfig>,
+    // 所有者或配置管理员
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct SetOdds<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(seeds = [GAME_CONFIG_SEED, game_state.key().as_ref()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
+    // 所有者或配置管理员
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct ApplyConfig<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+}
+#[derive(Accounts)]
 pub struct SetOwnerConfig<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    // 所有者或对应角色
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct AcceptOwner<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    pub new_owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct GuardianPause<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    pub authority: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct PostSeedCommitment<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + SeedCommitment::SPACE,
+        seeds = [COMMITMENT_SEED, game_state.key().as_ref(), &game_state.commit_posted.to_le_bytes()],
+        bump
+    )]
+    pub commitment: Account<'info, SeedCommitment>,
+    #[account(mut)]
     pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 #[derive(Accounts)]
 pub struct SetPaymentToken<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
     pub owner: Signer<'info>,
     /// CHECK: 仅用于读取 mint 公钥；并通过 pool_token_account 的 unpack 校验 mint 匹配
@@ -515,14 +1970,14 @@ pub struct SetPaymentToken<'info> {
 }
 #[derive(Accounts)]
 pub struct SyncPoolTotal<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
     pub owner: Signer<'info>,
     pub pool_token_account: Account<'info, TokenAccount>,
 }
 #[derive(Accounts)]
 pub struct WithdrawPool<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -533,8 +1988,44 @@ pub struct WithdrawPool<'info> {
     pub token_program: Program<'info, Token>,
 }
 #[derive(Accounts)]
+pub struct InitJackpot<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [JACKPOT_SEED, game_state.key().as_ref()],
+        bump,
+        token::mint = pool_mint,
+        token::authority = game_state
+    )]
+    pub jackpot_token_account: Account<'info, TokenAccount>,
+    #[account(address = game_state.pool_mint @ ErrorCode::PoolMintMismatch)]
+    pub pool_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
+pub struct SeedJackpot<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(mut, address = game_state.jackpot_token_account @ ErrorCode::InvalidJackpotAccount)]
+    pub jackpot_token_account: Account<'info, TokenAccount>,
+    pub owner: Signer<'info>,
+    #[account(mut)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+#[derive(Accounts)]
 pub struct CloseGame<'info> {
-    #[account(mut, close = owner, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(
+        mut,
+        close = owner,
+        seeds = [GAME_STATE_SEED, &game_state.id_seed()],
+        bump = game_state.bump
+    )]
     pub game_state: Account<'info, GameState>,
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -543,27 +2034,160 @@ pub struct CloseGame<'info> {
     #[account(mut)]
     pub owner_token_account: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
+    // 已创建 jackpot 时必须传入，余额转给所有者后关闭
+    #[account(mut, address = game_state.jackpot_token_account @ ErrorCode::InvalidJackpotAccount)]
+    pub jackpot_token_account: Option<Account<'info, TokenAccount>>,
 }
 #[derive(Accounts)]
 pub struct BecomeAgent<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
+    #[account(
+        init_if_needed,
+        payer = agent,
+        space = 8 + AgentAccount::SPACE,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent.key().as_ref()],
+        bump
+    )]
+    pub agent_account: Account<'info, AgentAccount>,
+    // 已有房卡沿用原卡号，否则为即将分配的 next_room_card
+    #[account(
+        init_if_needed,
+        payer = agent,
+        space = 8 + RoomCardIndex::SPACE,
+        seeds = [
+            ROOM_CARD_SEED,
+            game_state.key().as_ref(),
+            &agent_room_card(&agent_account, &game_state).to_le_bytes()
+        ],
+        bump
+    )]
+    pub room_card_index: Account<'info, RoomCardIndex>,
     #[account(mut)]
     pub agent: Signer<'info>,
     pub system_program: Program<'info, System>,
+    // 代付质押的钱包（缺省由代理商本人出资）
+    #[account(mut)]
+    pub funder: Option<Signer<'info>>,
 }
 #[derive(Accounts)]
 pub struct RedeemAgentStake<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent.key().as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Account<'info, AgentAccount>,
+    #[account(
+        mut,
+        close = agent,
+        seeds = [ROOM_CARD_SEED, game_state.key().as_ref(), &agent_account.room_card.to_le_bytes()],
+        bump = room_card_index.bump
+    )]
+    pub room_card_index: Account<'info, RoomCardIndex>,
     #[account(mut)]
     pub agent: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 #[derive(Accounts)]
+pub struct SetAgentCommission<'info> {
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent_account.agent.as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Account<'info, AgentAccount>,
+    // 所有者或代理管理员
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct InitCommissionSchedule<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + CommissionSchedule::SPACE,
+        seeds = [COMMISSION_SCHEDULE_SEED, game_state.key().as_ref()],
+        bump
+    )]
+    pub commission_schedule: Account<'info, CommissionSchedule>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
+pub struct SetCommissionSchedule<'info> {
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        seeds = [COMMISSION_SCHEDULE_SEED, game_state.key().as_ref()],
+        bump = commission_schedule.bump
+    )]
+    pub commission_schedule: Account<'info, CommissionSchedule>,
+    // 所有者或代理管理员
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct SetAgentParent<'info> {
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent_account.agent.as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Account<'info, AgentAccount>,
+    #[account(
+        seeds = [AGENT_SEED, game_state.key().as_ref(), parent_account.agent.as_ref()],
+        bump = parent_account.bump
+    )]
+    pub parent_account: Option<Account<'info, AgentAccount>>,
+    pub owner: Signer<'info>,
+}
+#[derive(Accounts)]
+pub struct BindRoomCard<'info> {
+    #[account(seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = 8 + PlayerStats::SPACE,
+        seeds = [PLAYER_STATS_SEED, game_state.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub player_stats: Account<'info, PlayerStats>,
+    // 要绑定的房卡所属代理商
+    #[account(
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent_account.agent.as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Account<'info, AgentAccount>,
+    // 当前绑定的代理商：已赎回时可免冷却换绑
+    #[account(
+        seeds = [AGENT_SEED, game_state.key().as_ref(), player_stats.bound_agent.as_ref()],
+        bump = bound_agent_account.bump
+    )]
+    pub bound_agent_account: Option<Account<'info, AgentAccount>>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
 pub struct WithdrawCommission<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent.key().as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Account<'info, AgentAccount>,
     #[account(mut)]
     pub agent: Signer<'info>,
     #[account(mut)]
@@ -573,9 +2197,34 @@ pub struct WithdrawCommission<'info> {
     pub token_program: Program<'info, Token>,
 }
 #[derive(Accounts)]
+pub struct MigrateAgents<'info> {
+    /// CHECK: 可能仍是旧版布局，无法按 GameState 反序列化；指令中识别布局并校验 PDA 地址
+    #[account(mut, owner = crate::ID)]
+    pub game_state: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
 pub struct Play<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = 8 + PlayerProfile::SPACE,
+        seeds = [PLAYER_PROFILE_SEED, game_state.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub player_profile: Account<'info, PlayerProfile>,
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = 8 + PlayerStats::SPACE,
+        seeds = [PLAYER_STATS_SEED, game_state.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub player_stats: Account<'info, PlayerStats>,
     #[account(mut)]
     pub player: Signer<'info>,
     #[account(mut)]
@@ -585,12 +2234,59 @@ pub struct Play<'info> {
     pub token_program: Program<'info, Token>,
     /// CHECK: 通过 read_vrf_bytes 校验 key/owner/offset，并只读取数据
     pub vrf: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // 使用房卡时传入房卡对应的代理商账户
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent_account.agent.as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Option<Account<'info, AgentAccount>>,
+    // 网格布局（Layout::Lines）时必须传入
+    #[account(seeds = [GAME_CONFIG_SEED, game_state.key().as_ref()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
+    // jackpot 代币账户：需划入 jackpot 或本局命中 jackpot 时必须传入
+    #[account(mut, address = game_state.jackpot_token_account @ ErrorCode::InvalidJackpotAccount)]
+    pub jackpot_token_account: Option<Account<'info, TokenAccount>>,
+    // 已创建佣金阶梯时，计佣的局必须传入
+    #[account(
+        seeds = [COMMISSION_SCHEDULE_SEED, game_state.key().as_ref()],
+        bump = commission_schedule.bump
+    )]
+    pub commission_schedule: Option<Account<'info, CommissionSchedule>>,
 }
 #[derive(Accounts)]
 pub struct RequestPlay<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
-    #[account(init, payer = player, space = 8 + PendingPlay::SPACE)]
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = 8 + PlayerProfile::SPACE,
+        seeds = [PLAYER_PROFILE_SEED, game_state.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub player_profile: Account<'info, PlayerProfile>,
+    #[account(
+        init_if_needed,
+        payer = player,
+        space = 8 + PlayerStats::SPACE,
+        seeds = [PLAYER_STATS_SEED, game_state.key().as_ref(), player.key().as_ref()],
+        bump
+    )]
+    pub player_stats: Account<'info, PlayerStats>,
+    #[account(
+        init,
+        payer = player,
+        space = 8 + PendingPlay::SPACE,
+        seeds = [
+            PENDING_PLAY_SEED,
+            game_state.key().as_ref(),
+            player.key().as_ref(),
+            &player_profile.next_play_seq.to_le_bytes()
+        ],
+        bump
+    )]
     pub pending_play: Account<'info, PendingPlay>,
     #[account(mut)]
     pub player: Signer<'info>,
@@ -599,16 +2295,82 @@ pub struct RequestPlay<'info> {
     #[account(mut)]
     pub pool_token_account: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
-    /// CHECK: 通过 read_vrf_bytes 校验 key/owner/offset，并只读取数据
-    pub vrf: UncheckedAccount<'info>,
+    // 以下 Switchboard 账户仅在 RandomnessSource::Switchboard 模式下需要
+    /// CHECK: 须为 game_state.vrf_pool 中的 VRF，通过 read_vrf_round 校验 owner/authority；
+    /// CPI 中由 Switchboard 写入新轮次
+    #[account(mut)]
+    pub vrf: Option<UncheckedAccount<'info>>,
+    /// CHECK: Switchboard 预言机队列，由 Switchboard 程序在 CPI 中校验
+    #[account(mut)]
+    pub oracle_queue: Option<UncheckedAccount<'info>>,
+    /// CHECK: 队列 authority，由 Switchboard 程序在 CPI 中校验
+    pub queue_authority: Option<UncheckedAccount<'info>>,
+    /// CHECK: 队列 data buffer，由 Switchboard 程序在 CPI 中校验
+    pub data_buffer: Option<UncheckedAccount<'info>>,
+    /// CHECK: VRF 在队列上的 permission 账户，由 Switchboard 程序在 CPI 中校验
+    #[account(mut)]
+    pub permission: Option<UncheckedAccount<'info>>,
+    /// VRF 请求费用托管账户（wSOL）
+    #[account(mut)]
+    pub escrow: Option<Account<'info, TokenAccount>>,
+    /// 玩家支付 VRF 请求费用的 wSOL 账户
+    #[account(mut, constraint = payer_wallet.owner == player.key() @ ErrorCode::PlayerTokenMismatch)]
+    pub payer_wallet: Option<Account<'info, TokenAccount>>,
+    /// CHECK: 仅校验地址为 RecentBlockhashes sysvar
+    #[account(address = sysvar::recent_blockhashes::ID)]
+    pub recent_blockhashes: Option<UncheckedAccount<'info>>,
+    /// CHECK: Switchboard program state，由 Switchboard 程序在 CPI 中校验
+    pub program_state: Option<UncheckedAccount<'info>>,
+    /// CHECK: 仅校验地址为 Switchboard V2 程序
+    #[account(address = SWITCHBOARD_V2_PROGRAM_ID)]
+    pub switchboard_program: Option<UncheckedAccount<'info>>,
+    // 承诺-揭示模式：必须是下一个待绑定序号（game_state.commit_bound）的承诺
+    #[account(
+        mut,
+        seeds = [COMMITMENT_SEED, game_state.key().as_ref(), &game_state.commit_bound.to_le_bytes()],
+        bump = commitment.bump
+    )]
+    pub commitment: Option<Account<'info, SeedCommitment>>,
+    // 使用房卡时传入房卡对应的代理商账户
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent_account.agent.as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Option<Account<'info, AgentAccount>>,
+    // 网格布局（Layout::Lines）时必须传入，PendingPlay 会快照其中的布局
+    #[account(seeds = [GAME_CONFIG_SEED, game_state.key().as_ref()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
     pub system_program: Program<'info, System>,
 }
 #[derive(Accounts)]
 pub struct SettlePlay<'info> {
-    #[account(mut, seeds = [GAME_STATE_SEED], bump = game_state.bump)]
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
     pub game_state: Account<'info, GameState>,
-    #[account(mut, close = player)]
+    #[account(
+        mut,
+        close = player,
+        seeds = [
+            PENDING_PLAY_SEED,
+            game_state.key().as_ref(),
+            pending_play.player.as_ref(),
+            &pending_play.seq.to_le_bytes()
+        ],
+        bump = pending_play.bump
+    )]
     pub pending_play: Account<'info, PendingPlay>,
+    #[account(
+        mut,
+        seeds = [PLAYER_PROFILE_SEED, game_state.key().as_ref(), pending_play.player.as_ref()],
+        bump = player_profile.bump
+    )]
+    pub player_profile: Account<'info, PlayerProfile>,
+    #[account(
+        mut,
+        seeds = [PLAYER_STATS_SEED, game_state.key().as_ref(), pending_play.player.as_ref()],
+        bump = player_stats.bump
+    )]
+    pub player_stats: Account<'info, PlayerStats>,
     #[account(mut)]
     /// CHECK: 仅作为 close 目标接收 lamports；并在指令中校验其 pubkey == pending_play.player
     pub player: UncheckedAccount<'info>,
@@ -616,13 +2378,96 @@ pub struct SettlePlay<'info> {
     pub player_token_account: Account<'info, TokenAccount>,
     #[account(mut)]
     pub pool_token_account: Account<'info, TokenAccount>,
+    pub keeper: Signer<'info>,
+    /// keeper 领取结算奖励的代币账户（mint 必须为奖池 mint）
+    #[account(mut)]
+    pub keeper_token_account: Option<Account<'info, TokenAccount>>,
     pub token_program: Program<'info, Token>,
-    /// CHECK: 通过 read_vrf_bytes 校验 key/owner/offset，并只读取数据
-    pub vrf: UncheckedAccount<'info>,
+    /// CHECK: 本局请求所用的 VRF（pending_play.vrf），通过 read_vrf_round 校验 key/owner/authority，
+    /// 并只读取数据（Switchboard 模式）
+    pub vrf: Option<UncheckedAccount<'info>>,
+    // 承诺-揭示模式：本局绑定的承诺
+    #[account(
+        mut,
+        seeds = [COMMITMENT_SEED, game_state.key().as_ref(), &pending_play.commitment_index.to_le_bytes()],
+        bump = commitment.bump
+    )]
+    pub commitment: Option<Account<'info, SeedCommitment>>,
+    // 使用房卡时传入房卡对应的代理商账户
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, game_state.key().as_ref(), agent_account.agent.as_ref()],
+        bump = agent_account.bump
+    )]
+    pub agent_account: Option<Account<'info, AgentAccount>>,
+    // jackpot 代币账户：需划入 jackpot 或本局命中 jackpot 时必须传入
+    #[account(mut, address = game_state.jackpot_token_account @ ErrorCode::InvalidJackpotAccount)]
+    pub jackpot_token_account: Option<Account<'info, TokenAccount>>,
     pub system_program: Program<'info, System>,
+    // 已创建佣金阶梯时，计佣的局必须传入
+    #[account(
+        seeds = [COMMISSION_SCHEDULE_SEED, game_state.key().as_ref()],
+        bump = commission_schedule.bump
+    )]
+    pub commission_schedule: Option<Account<'info, CommissionSchedule>>,
+}
+#[derive(Accounts)]
+pub struct SettleMany<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(mut)]
+    pub pool_token_account: Account<'info, TokenAccount>,
+    pub keeper: Signer<'info>,
+    #[account(mut)]
+    pub keeper_token_account: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    // jackpot 代币账户：需划入 jackpot 或本局命中 jackpot 时必须传入
+    #[account(mut, address = game_state.jackpot_token_account @ ErrorCode::InvalidJackpotAccount)]
+    pub jackpot_token_account: Option<Account<'info, TokenAccount>>,
+    // 已创建佣金阶梯时，计佣的局必须传入
+    #[account(
+        seeds = [COMMISSION_SCHEDULE_SEED, game_state.key().as_ref()],
+        bump = commission_schedule.bump
+    )]
+    pub commission_schedule: Option<Account<'info, CommissionSchedule>>,
+}
+#[derive(Accounts)]
+pub struct CancelExpiredPlay<'info> {
+    #[account(mut, seeds = [GAME_STATE_SEED, &game_state.id_seed()], bump = game_state.bump)]
+    pub game_state: Account<'info, GameState>,
+    #[account(
+        mut,
+        close = player,
+        seeds = [
+            PENDING_PLAY_SEED,
+            game_state.key().as_ref(),
+            pending_play.player.as_ref(),
+            &pending_play.seq.to_le_bytes()
+        ],
+        bump = pending_play.bump
+    )]
+    pub pending_play: Account<'info, PendingPlay>,
+    #[account(
+        mut,
+        seeds = [PLAYER_PROFILE_SEED, game_state.key().as_ref(), pending_play.player.as_ref()],
+        bump = player_profile.bump
+    )]
+    pub player_profile: Account<'info, PlayerProfile>,
+    #[account(mut)]
+    /// CHECK: 仅作为 close 目标接收 lamports；并在指令中校验其 pubkey == pending_play.player
+    pub player: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub player_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub pool_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: Switchboard 模式下通过 read_vrf_round 确认本局轮次尚未写回结果
+    pub vrf: Option<UncheckedAccount<'info>>,
 }
 
 // 状态
+// 前段沿旧版布局的字段顺序（旧版的单组 symbol_weights 扩为按转轴的 reel_weights）；
+// 新字段一律追加在末尾，已部署账户只需按 LegacyGameState 迁移一次
 #[account]
 pub struct GameState {
     pub owner: Pubkey,
@@ -631,6 +2476,7 @@ pub struct GameState {
     pub pool_token_account: Pubkey,
     pub total_pool: u64,
     pub nonce: u64,
+    // 旧版内联代理商，仅供 migrate_agents 迁移（新代理商全部存放在 AgentAccount PDA）
     pub agents: Vec<Agent>,
     pub next_room_card: u64,
     pub commission_rate: u8,
@@ -638,40 +2484,549 @@ pub struct GameState {
     pub settlement_period: u64,
     pub vrf: Pubkey,
     pub vrf_result_offset: u32,
-    pub symbol_weights: [u16; 6],
+    pub reel_weights: [[u16; 6]; 3],
     pub payout_triple: [u16; 6],
     pub payout_double: [u16; 6],
     pub max_auto_spins: u8,
     pub min_bet: u64,
+    pub game_id: u64,
+    pub vrf_pool: [VrfSlot; MAX_VRF_POOL],
+    pub randomness_source: RandomnessSource,
+    pub commit_posted: u64,
+    pub commit_bound: u64,
+    pub play_timeout_slots: u64,
+    pub keeper_fee_bps: u16,
+    pub paused: u8,
+    pub guardian: Pubkey,
+    pub pending_owner: Pubkey,
+    pub config_admin: Pubkey,
+    pub treasurer: Pubkey,
+    pub agent_manager: Pubkey,
+    pub pauser: Pubkey,
+    pub config_version: u64,
+    pub config_delay_slots: u64,
+    pub queued_config: QueuedConfig,
+    pub rtp_min_bps: u16,
+    pub rtp_max_bps: u16,
+    pub max_bet_per_symbol: u64,
+    pub max_bet_per_play: u64,
+    pub max_exposure_bps: u16,
+    pub layout: Layout,
+    pub symbol_roles: [SymbolRole; 6],
+    pub scatter_pays: [u16; 4],
+    pub multipliers: [u8; MAX_SPINS],
+    pub trigger_count: u8,
+    // 累进 jackpot：独立的代币账户与记账，不计入 total_pool
+    pub jackpot_token_account: Pubkey,
+    pub jackpot_total: u64,
+    pub jackpot_bps: u16,
+    pub jackpot_trigger: JackpotTrigger,
+    // 玩家换绑房卡的冷却时间（秒）
+    pub rebind_cooldown: u64,
+    // 单一玩家占代理商推广量的上限（万分比）
+    pub max_referral_share_bps: u16,
+    // 上级代理商按层分成（百分比）与向上分配层数
+    pub upline_rates: [u8; MAX_UPLINE_DEPTH],
+    pub upline_depth: u8,
+    // 已创建 CommissionSchedule：计佣时必须传入
+    pub tiered_commission: bool,
+    // 未结算两段式局的最坏情况派彩之和，风险敞口按扣除此项后的奖池计算
+    pub outstanding_liability: u64,
 }
 impl GameState {
-    pub const SPACE: usize = 4096;
+    // 须大于旧版布局，migrate_agents 据账户长度识别未升级的旧账户，且容得下旧账户的全部内联代理商
+    pub const SPACE: usize = 6144;
+    // initialize 与旧版布局迁移共用的默认参数
+    fn new(
+        owner: Pubkey,
+        bump: u8,
+        game_id: u64,
+        pool_mint: Pubkey,
+        pool_token_account: Pubkey,
+        total_pool: u64,
+    ) -> Self {
+        Self {
+            owner,
+            bump,
+            pool_mint,
+            pool_token_account,
+            total_pool,
+            nonce: 0,
+            agents: Vec::new(),
+            next_room_card: 10_000,
+            commission_rate: 10,
+            stake_threshold: 1_000_000,
+            settlement_period: 86_400,
+            vrf: Pubkey::default(),
+            vrf_result_offset: 0,
+            reel_weights: [[2500, 2500, 250, 1600, 2150, 1000]; REELS],
+            payout_triple: [220, 180, 2000, 360, 450, 0],
+            payout_double: [65, 50, 100, 75, 85, 0],
+            max_auto_spins: 5,
+            min_bet: 100,
+            game_id,
+            vrf_pool: [VrfSlot::default(); MAX_VRF_POOL],
+            randomness_source: RandomnessSource::Switchboard,
+            commit_posted: 0,
+            commit_bound: 0,
+            play_timeout_slots: 1_500,
+            keeper_fee_bps: 0,
+            paused: 0,
+            guardian: Pubkey::default(),
+            pending_owner: Pubkey::default(),
+            config_admin: Pubkey::default(),
+            treasurer: Pubkey::default(),
+            agent_manager: Pubkey::default(),
+            pauser: Pubkey::default(),
+            config_version: 0,
+            config_delay_slots: DEFAULT_CONFIG_DELAY_SLOTS,
+            queued_config: QueuedConfig::default(),
+            rtp_min_bps: 0,
+            rtp_max_bps: DEFAULT_RTP_MAX_BPS,
+            max_bet_per_symbol: 0,
+            max_bet_per_play: 0,
+            max_exposure_bps: DEFAULT_MAX_EXPOSURE_BPS,
+            layout: Layout::Classic,
+            symbol_roles: DEFAULT_SYMBOL_ROLES,
+            scatter_pays: [0; 4],
+            multipliers: DEFAULT_MULTIPLIERS,
+            trigger_count: 1,
+            jackpot_token_account: Pubkey::default(),
+            jackpot_total: 0,
+            jackpot_bps: 0,
+            jackpot_trigger: JackpotTrigger::Disabled,
+            rebind_cooldown: REBIND_NEVER,
+            max_referral_share_bps: DEFAULT_MAX_REFERRAL_SHARE_BPS,
+            upline_rates: [0; MAX_UPLINE_DEPTH],
+            upline_depth: 0,
+            tiered_commission: false,
+            outstanding_liability: 0,
+        }
+    }
+    pub fn id_seed(&self) -> Vec<u8> {
+        game_id_seed(self.game_id)
+    }
+    pub fn odds(&self) -> Odds {
+        Odds {
+            reel_weights: self.reel_weights,
+            payout_triple: self.payout_triple,
+            payout_double: self.payout_double,
+            max_auto_spins: self.max_auto_spins,
+            layout: self.layout,
+            roles: self.symbol_roles,
+            scatter_pays: self.scatter_pays,
+            multipliers: self.multipliers,
+            trigger_count: self.trigger_count,
+            jackpot_bps: self.jackpot_bps,
+            jackpot_trigger: self.jackpot_trigger,
+        }
+    }
 }
+// 多游戏支持之前已部署的 GameState 布局（与新布局同一 discriminator），仅供 migrate_agents 升级
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct Agent {
-    pub pubkey: Pubkey,
-    pub stake: u64,
-    pub room_card: u64,
-    pub commission: i64,
-    pub stake_time: i64,
-    pub last_settlement: i64,
-    pub is_active: bool,
-}
-#[account]
-pub struct PendingPlay {
-    pub player: Pubkey,
-    pub player_token_account: Pubkey,
+pub struct LegacyGameState {
+    pub owner: Pubkey,
+    pub bump: u8,
+    pub pool_mint: Pubkey,
     pub pool_token_account: Pubkey,
-    pub request_nonce: u64,
-    pub request_slot: u64,
+    pub total_pool: u64,
+    pub nonce: u64,
+    pub agents: Vec<Agent>,
+    pub next_room_card: u64,
+    pub commission_rate: u8,
+    pub stake_threshold: u64,
+    pub settlement_period: u64,
+    pub vrf: Pubkey,
+    pub vrf_result_offset: u32,
+    pub symbol_weights: [u16; 6],
+    pub payout_triple: [u16; 6],
+    pub payout_double: [u16; 6],
+    pub max_auto_spins: u8,
+    pub min_bet: u64,
+}
+impl LegacyGameState {
+    pub const SPACE: usize = 4096;
+    // 沿用旧账户的参数与内联代理商（game_id 0、三个转轴同一权重），新增字段取 initialize 的默认值
+    fn upgrade(self) -> GameState {
+        let mut s = GameState::new(
+            self.owner,
+            self.bump,
+            0,
+            self.pool_mint,
+            self.pool_token_account,
+            self.total_pool,
+        );
+        s.nonce = self.nonce;
+        s.agents = self.agents;
+        s.next_room_card = self.next_room_card;
+        s.commission_rate = self.commission_rate;
+        s.stake_threshold = self.stake_threshold;
+        s.settlement_period = self.settlement_period;
+        s.vrf = self.vrf;
+        s.vrf_result_offset = self.vrf_result_offset;
+        s.reel_weights = [self.symbol_weights; REELS];
+        s.payout_triple = self.payout_triple;
+        s.payout_double = self.payout_double;
+        s.max_auto_spins = self.max_auto_spins;
+        s.min_bet = self.min_bet;
+        s
+    }
+}
+// 决定派彩的赔率参数；PendingPlay 按请求时的值快照
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Odds {
+    pub reel_weights: [[u16; 6]; 3],
+    pub payout_triple: [u16; 6],
+    pub payout_double: [u16; 6],
+    pub max_auto_spins: u8,
+    pub layout: Layout,
+    pub roles: [SymbolRole; 6],
+    pub scatter_pays: [u16; 4],
+    // 第 k 次转轮（k = 0 为首次）的乘数；同一次转轮出现至少 trigger_count 个
+    // Multiplier 符号时触发下一次，最多 min(max_auto_spins, MAX_SPINS - 1) 次
+    pub multipliers: [u8; MAX_SPINS],
+    pub trigger_count: u8,
+    // 每局 total_bet 划入 jackpot 的万分比与中奖条件
+    pub jackpot_bps: u16,
+    pub jackpot_trigger: JackpotTrigger,
+}
+impl Odds {
+    pub const SPACE: usize = 2 * 6 * REELS + 2 * 6 * 2 + 1 + 1 + 6 + 2 * 4 + MAX_SPINS + 1
+        + 2
+        + JackpotTrigger::SPACE;
+}
+// jackpot 中奖条件；中奖时派出整个 jackpot
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JackpotTrigger {
+    #[default]
+    Disabled,
+    // 任一次转轮全部格子（Lines 为任一赔付线的全部转轮）均为 symbol，且该符号下注不低于 min_bet
+    Combo { symbol: u8, min_bet: u64 },
+    // 由本局种子派生的独立抽签，中奖概率 1 / one_in；要求总下注不低于 min_bet
+    Draw { one_in: u32, min_bet: u64 },
+}
+impl JackpotTrigger {
+    pub const SPACE: usize = 1 + 8 + 8;
+}
+// 符号角色：Regular 按下注表与赔率表派彩；Wild 在匹配时可替代任意 Regular 符号；
+// Scatter 出现在任意位置即按总下注派彩（scatter_pays[个数 - 2]，2/3/4/5+ 个，百分比）；
+// Multiplier 出现即触发自动转轮且乘数翻倍。仅 Regular 符号可下注
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymbolRole {
+    #[default]
+    Regular,
+    Wild,
+    Scatter,
+    Multiplier,
+}
+// 赔付布局：Classic 为 3 转轮单行、按命中个数（两连/三连）派彩；
+// Lines 使用 GameConfig 中的 N×M 网格与赔付线，自左向右连续命中 3/4/5 个派彩
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    #[default]
+    Classic,
+    Lines,
+}
+// 网格布局：cells 按转轮优先展开（reel * rows + row）；paylines 每条线给出各转轮所在行，
+// 仅前 reels 项有效；line_pays[符号][0..3] 为 3/4/5 连赔率（百分比）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Default)]
+pub struct LineOdds {
+    pub reels: u8,
+    pub rows: u8,
+    pub reel_weights: [[u16; 6]; MAX_REELS],
+    pub paylines: Vec<[u8; MAX_REELS]>,
+    pub line_pays: [[u16; 3]; 6],
+}
+impl LineOdds {
+    pub const SPACE: usize = 1 + 1 + 2 * 6 * MAX_REELS + 4 + MAX_REELS * MAX_PAYLINES + 2 * 3 * 6;
+}
+// 网格布局配置（PDA: [GAME_CONFIG_SEED, game_state]）：Lines 布局的转轮、行数、赔付线与连线赔率，
+// 仅在 Classic 布局下可由 set_line_config 修改，set_layout 切到 Lines 后按此生效
+#[account]
+pub struct GameConfig {
+    pub game_state: Pubkey,
+    pub bump: u8,
+    pub lines: LineOdds,
+}
+impl GameConfig {
+    pub const SPACE: usize = 32 + 1 + LineOdds::SPACE;
+}
+// 佣金分档依据：代理商质押额，或近期推广量（当前与上一统计周期取较大者）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TierBasis {
+    #[default]
+    Stake,
+    Volume,
+}
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommissionTier {
+    pub threshold: u64,
+    pub rate: u8,
+}
+// 佣金阶梯（PDA: [COMMISSION_SCHEDULE_SEED, game_state]）：按 basis 取代理商质押额或近期推广量，
+// 命中门槛的最高档费率；创建后 tiered_commission 置位，计佣时必须传入
+#[account]
+pub struct CommissionSchedule {
+    pub game_state: Pubkey,
+    pub bump: u8,
+    pub basis: TierBasis,
+    pub volume_period: i64,
+    pub tiers: [CommissionTier; MAX_COMMISSION_TIERS],
+    pub tier_count: u8,
+}
+impl CommissionSchedule {
+    pub const SPACE: usize = 32 + 1 + 1 + 8 + 9 * MAX_COMMISSION_TIERS + 1;
+}
+// 排队中的配置：effective_slot 之后由 apply_config 或下一次修改生效
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueuedConfig {
+    pub pending: bool,
+    pub effective_slot: u64,
+    pub odds: Odds,
+    pub config_delay_slots: u64,
+}
+// 管理角色：所有者始终拥有全部角色权限
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    ConfigAdmin,
+    Treasurer,
+    AgentManager,
+    Pauser,
+}
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RandomnessSource {
+    Switchboard,
+    CommitReveal,
+}
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Agent {
+    pub pubkey: Pubkey,
+    pub stake: u64,
+    pub room_card: u64,
+    pub commission: i64,
+    pub stake_time: i64,
+    pub last_settlement: i64,
+    pub is_active: bool,
+}
+// 代理商 PDA：["agent", game_state, agent]，质押 lamports 存放于本账户
+#[account]
+pub struct AgentAccount {
+    pub agent: Pubkey,
+    pub bump: u8,
+    pub stake: u64,
+    pub room_card: u64,
+    pub commission: i64,
+    pub stake_time: i64,
+    pub last_settlement: i64,
+    pub is_active: bool,
+    // 通过 funder 代付过质押的钱包（默认为空）
+    pub stake_funder: Pubkey,
+    // 累计推广下注量（自推广的局不计入）
+    pub referred_volume: u64,
+    // 上级代理商（默认为空）
+    pub parent: Pubkey,
+    // 单独设置的直属佣金率，优先于阶梯与 commission_rate
+    pub commission_override: Option<u8>,
+    // 当前统计周期的起点与推广量、上一周期推广量（按推广量分档时使用）
+    pub period_start: i64,
+    pub period_volume: u64,
+    pub prev_period_volume: u64,
+}
+impl AgentAccount {
+    pub const SPACE: usize = 32 + 1 + 8 * 5 + 1 + 32 + 8 + 32 + 32 + 2 + 8 * 3;
+}
+// 房卡索引 PDA：["room_card", game_state, room_card] → 代理商
+#[account]
+pub struct RoomCardIndex {
+    pub room_card: u64,
+    pub agent: Pubkey,
+    pub bump: u8,
+}
+impl RoomCardIndex {
+    pub const SPACE: usize = 8 + 32 + 1;
+}
+// VRF 池槽位：pending_play 非空表示该 VRF 正服务于一局（counter 为其请求轮次）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VrfSlot {
+    pub vrf: Pubkey,
+    pub pending_play: Pubkey,
+    pub counter: u128,
+    pub request_slot: u64,
+}
+#[account]
+pub struct PendingPlay {
+    pub player: Pubkey,
+    pub seq: u64,
+    pub bump: u8,
+    pub player_token_account: Pubkey,
+    pub pool_token_account: Pubkey,
+    pub request_nonce: u64,
+    pub request_slot: u64,
     pub total_bet: u64,
     pub bets: [u64; 6],
     pub has_room_card: bool,
     pub room_card: u64,
-    pub vrf_result_before: [u8; 32],
+    pub agent: Pubkey,
+    pub source: RandomnessSource,
+    // Switchboard 模式：本局请求所用的 VRF 账户与轮次
+    pub vrf: Pubkey,
+    pub vrf_counter: u128,
+    pub commitment_index: u64,
+    pub client_seed: [u8; 32],
+    pub config_version: u64,
+    pub odds: Odds,
+    pub lines: LineOdds,
+    // 结算时从奖池划入 jackpot 的金额（按请求时的 jackpot_bps 计算）
+    pub jackpot_contribution: u64,
+    // 请求时计入 GameState.outstanding_liability 的最坏情况派彩，结算或退款时释放
+    pub max_payout: u64,
 }
 impl PendingPlay {
-    pub const SPACE: usize = 8 * 6 + 32 * 3 + 8 * 4 + 1 + 32 + 64;
+    pub const SPACE: usize = 8 * 6 + 32 * 5 + 8 + 1 + 8 * 4 + 1 + 1 + 16 + 8 + 32 + 8 + 8 + 8
+        + Odds::SPACE
+        + LineOdds::SPACE
+        + 64;
+}
+// 玩家档案：PendingPlay 按 next_play_seq 递增派生，open_plays 为未结算局数
+#[account]
+pub struct PlayerProfile {
+    pub player: Pubkey,
+    pub bump: u8,
+    pub next_play_seq: u64,
+    pub open_plays: u32,
+    pub last_result: LastResult,
+}
+impl PlayerProfile {
+    pub const SPACE: usize = 32 + 1 + 8 + 4 + LastResult::SPACE + 32;
+}
+// 单次转轮：reels、本次乘数、各符号中奖金额（已乘乘数）与本次合计
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct SpinRecord {
+    // 各格符号，按转轮优先展开（Classic 为 3 格）
+    pub reels: Vec<u8>,
+    pub multiplier: u8,
+    pub wins: [u64; 6],
+    pub total: u64,
+}
+// 完整转轮记录：作为 return data 返回，前端可据此播放自动转轮并复核结果
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct SpinTranscript {
+    pub spins: Vec<SpinRecord>,
+    pub doubles: u8,
+    pub multiplier: u8,
+    pub payout: u64,
+    pub grid_reels: u8,
+    pub grid_rows: u8,
+    // 本局派出的 jackpot（不含在 payout 中）
+    pub jackpot: u64,
+}
+// compute_rtp 返回值：各符号单位下注的期望返还（万分比），min/max 仅统计可下注符号
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RtpReport {
+    pub config_version: u64,
+    pub per_symbol_bps: [u32; 6],
+    pub min_bps: u32,
+    pub max_bps: u32,
+}
+// 玩家最近一局的精简结果（不含各符号明细）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LastResult {
+    pub slot: u64,
+    pub total_bet: u64,
+    pub payout: u64,
+    pub doubles: u8,
+    pub multiplier: u8,
+    pub spin_count: u8,
+    pub grid_reels: u8,
+    pub grid_rows: u8,
+    pub reels: [[u8; MAX_CELLS]; MAX_SPINS],
+    pub spin_totals: [u64; MAX_SPINS],
+}
+impl LastResult {
+    pub const SPACE: usize = 8 * 3 + 5 + MAX_CELLS * MAX_SPINS + 8 * MAX_SPINS;
+    fn new(t: &SpinTranscript, total_bet: u64, slot: u64) -> Self {
+        let mut r = LastResult {
+            slot,
+            total_bet,
+            payout: t.payout,
+            doubles: t.doubles,
+            multiplier: t.multiplier,
+            spin_count: t.spins.len().min(MAX_SPINS) as u8,
+            grid_reels: t.grid_reels,
+            grid_rows: t.grid_rows,
+            ..Default::default()
+        };
+        for (i, sp) in t.spins.iter().take(MAX_SPINS).enumerate() {
+            let n = sp.reels.len().min(MAX_CELLS);
+            r.reels[i][..n].copy_from_slice(&sp.reels[..n]);
+            r.spin_totals[i] = sp.total;
+        }
+        r
+    }
+}
+// 玩家统计：每局结算后累计下注/派彩（含 jackpot）/局数/最大单局派彩，并保留最近 RECENT_RESULTS 局
+#[account]
+pub struct PlayerStats {
+    pub player: Pubkey,
+    pub bump: u8,
+    pub total_wagered: u64,
+    pub total_won: u64,
+    pub play_count: u64,
+    pub biggest_win: u64,
+    // 绑定的房卡与代理商（0 表示未绑定），佣金归属以此为准
+    pub room_card: u64,
+    pub bound_agent: Pubkey,
+    pub bound_at: i64,
+    // 当前绑定下的累计下注量
+    pub bound_volume: u64,
+    // 环形缓冲：下一条写入位置
+    pub recent_head: u8,
+    pub recent: [PlayRecord; RECENT_RESULTS],
+}
+impl PlayerStats {
+    pub const SPACE: usize =
+        32 + 1 + 8 * 5 + 32 + 8 + 8 + 1 + PlayRecord::SPACE * RECENT_RESULTS + 32;
+    fn record(&mut self, total_bet: u64, t: &SpinTranscript, slot: u64) -> Result<()> {
+        let won = t.payout.checked_add(t.jackpot).ok_or(ErrorCode::MathOverflow)?;
+        self.total_wagered = self
+            .total_wagered
+            .checked_add(total_bet)
+            .ok_or(ErrorCode::MathOverflow)?;
+        self.total_won = self.total_won.checked_add(won).ok_or(ErrorCode::MathOverflow)?;
+        self.play_count = self.play_count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.biggest_win = self.biggest_win.max(won);
+        let i = self.recent_head as usize % RECENT_RESULTS;
+        self.recent[i] = PlayRecord {
+            slot,
+            total_bet,
+            payout: t.payout,
+            jackpot: t.jackpot,
+        };
+        self.recent_head = ((i + 1) % RECENT_RESULTS) as u8;
+        Ok(())
+    }
+}
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PlayRecord {
+    pub slot: u64,
+    pub total_bet: u64,
+    pub payout: u64,
+    pub jackpot: u64,
+}
+impl PlayRecord {
+    pub const SPACE: usize = 8 * 4;
+}
+#[account]
+pub struct SeedCommitment {
+    pub index: u64,
+    pub hash: [u8; 32],
+    pub pending_play: Pubkey,
+    pub revealed: bool,
+    pub seed: [u8; 32],
+    pub bump: u8,
+}
+impl SeedCommitment {
+    pub const SPACE: usize = 8 + 32 + 32 + 1 + 32 + 1;
 }
 
 // 工具函数：权限/下注/VRF/派彩/转账
@@ -679,18 +3034,682 @@ fn require_owner(s: &GameState, signer: Pubkey) -> Result<()> {
     require_keys_eq!(s.owner, signer, ErrorCode::Unauthorized);
     Ok(())
 }
-fn validate_bets(b: &[u64; 6], min_bet: u64) -> Result<()> {
-    require!(b[5] == 0, ErrorCode::InvalidBetTable);
+fn require_role(s: &GameState, signer: Pubkey, role: Role) -> Result<()> {
+    if signer == s.owner {
+        return Ok(());
+    }
+    let key = match role {
+        Role::ConfigAdmin => s.config_admin,
+        Role::Treasurer => s.treasurer,
+        Role::AgentManager => s.agent_manager,
+        Role::Pauser => s.pauser,
+    };
+    require!(key != Pubkey::default() && key == signer, ErrorCode::Unauthorized);
+    Ok(())
+}
+fn require_not_paused(s: &GameState, flag: u8) -> Result<()> {
+    require!(s.paused & flag == 0, ErrorCode::Paused);
+    Ok(())
+}
+// 两段式请求使用的 VRF 在池中的槽位
+fn vrf_slot(s: &GameState, vrf: Pubkey) -> Result<usize> {
+    s.vrf_pool
+        .iter()
+        .position(|x| x.vrf == vrf && vrf != Pubkey::default())
+        .ok_or_else(|| error!(ErrorCode::InvalidVrfAccount))
+}
+fn required<T>(a: &Option<T>) -> Result<&T> {
+    a.as_ref().ok_or_else(|| error!(ErrorCode::MissingRandomnessAccount))
+}
+fn close_open_play(profile: &mut PlayerProfile) -> Result<()> {
+    profile.open_plays = profile
+        .open_plays
+        .checked_sub(1)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+fn check_pending_accounts(
+    p: &PendingPlay,
+    player: Pubkey,
+    player_token_account: Pubkey,
+    pool_token_account: Pubkey,
+) -> Result<()> {
+    require_keys_eq!(p.player, player, ErrorCode::PlayerMismatch);
+    require_keys_eq!(
+        p.player_token_account,
+        player_token_account,
+        ErrorCode::PlayerTokenMismatch
+    );
+    require_keys_eq!(
+        p.pool_token_account,
+        pool_token_account,
+        ErrorCode::InvalidPoolAccount
+    );
+    Ok(())
+}
+// 校验并取得本局随机种子（Switchboard 轮次 / 承诺揭示），同时释放本局 VRF 槽位或标记承诺已揭示
+fn resolve_pending_seed(
+    s: &mut GameState,
+    pending_key: Pubkey,
+    p: &PendingPlay,
+    vrf: Option<&AccountInfo>,
+    commitment: Option<&mut SeedCommitment>,
+    reveal: Option<[u8; 32]>,
+) -> Result<[u8; 32]> {
+    match p.source {
+        RandomnessSource::Switchboard => {
+            let i = vrf_slot(s, p.vrf)?;
+            require_keys_eq!(
+                s.vrf_pool[i].pending_play,
+                pending_key,
+                ErrorCode::VrfRequestMismatch
+            );
+            let vrf = vrf.ok_or(ErrorCode::MissingRandomnessAccount)?;
+            let (counter, result) = read_vrf_round(vrf, p.vrf, game_state_address(s)?)?;
+            require!(counter == p.vrf_counter, ErrorCode::VrfRequestMismatch);
+            require!(result != [0u8; 32], ErrorCode::VrfNotUpdated);
+            s.vrf_pool[i].pending_play = Pubkey::default();
+            Ok(derive_seed(
+                result,
+                Some(p.player),
+                Some(p.request_nonce),
+                Some(p.request_slot),
+                Some(pending_key.to_bytes()),
+            ))
+        }
+        RandomnessSource::CommitReveal => {
+            let reveal = reveal.ok_or(ErrorCode::InvalidReveal)?;
+            let c = commitment.ok_or(ErrorCode::MissingRandomnessAccount)?;
+            require_keys_eq!(c.pending_play, pending_key, ErrorCode::CommitmentMismatch);
+            require!(!c.revealed, ErrorCode::CommitmentMismatch);
+            require!(hash(&reveal).to_bytes() == c.hash, ErrorCode::InvalidReveal);
+            c.revealed = true;
+            c.seed = reveal;
+            Ok(derive_seed(
+                reveal,
+                Some(p.player),
+                Some(p.request_nonce),
+                Some(p.request_slot),
+                Some(p.client_seed),
+            ))
+        }
+    }
+}
+// 按种子派彩并累计代理佣金；请求时记录的 jackpot 份额在此从奖池划入 jackpot
+#[allow(clippy::too_many_arguments)]
+fn settle_pending_payout<'info>(
+    s: &mut Account<'info, GameState>,
+    tp: &Program<'info, Token>,
+    pool: &Account<'info, TokenAccount>,
+    player_token_account: &Account<'info, TokenAccount>,
+    p: &PendingPlay,
+    seed: [u8; 32],
+    agent: Option<&mut Account<'info, AgentAccount>>,
+    upline: &mut [Account<'info, AgentAccount>],
+    stats: &mut PlayerStats,
+    jackpot: Option<&Account<'info, TokenAccount>>,
+    schedule: Option<&CommissionSchedule>,
+) -> Result<SpinTranscript> {
+    if p.has_room_card {
+        let a = agent.as_ref().ok_or(ErrorCode::InvalidRoomCard)?;
+        require_keys_eq!(a.key(), p.agent, ErrorCode::InvalidRoomCard);
+    }
+    release_liability(s, p)?;
+    let mut outcome = compute_total_payout(seed, &p.bets, &p.odds, &p.lines)?;
+    let payout = outcome.payout;
+    if payout > 0 {
+        require!(pool.amount >= payout, ErrorCode::InsufficientPool);
+        require!(s.total_pool >= payout, ErrorCode::InsufficientPool);
+        pool_transfer_signed(s, tp, pool, player_token_account, payout)?;
+        s.total_pool = s
+            .total_pool
+            .checked_sub(payout)
+            .ok_or(ErrorCode::MathOverflow)?;
+    }
+    let contribution = p.jackpot_contribution;
+    if contribution > 0 {
+        require!(s.total_pool >= contribution, ErrorCode::InsufficientPool);
+        pool_transfer_signed(s, tp, pool, required_jackpot(jackpot)?, contribution)?;
+        s.total_pool = s
+            .total_pool
+            .checked_sub(contribution)
+            .ok_or(ErrorCode::MathOverflow)?;
+        accrue_jackpot(s, p.player, contribution)?;
+    }
+    if jackpot_hit(seed, &p.bets, &outcome, &p.odds, &p.lines)? {
+        outcome.jackpot = pay_jackpot(s, tp, jackpot, player_token_account, p.player)?;
+    }
+    apply_agent_commission(
+        s,
+        schedule,
+        agent.map(|a| &mut **a),
+        upline,
+        stats,
+        p.has_room_card.then_some(p.room_card),
+        p.total_bet,
+        p.jackpot_contribution,
+        payout,
+        Clock::get()?.unix_timestamp,
+    )?;
+    Ok(outcome)
+}
+// 结算或超时退款时释放该局计入的最坏情况派彩
+fn release_liability(s: &mut GameState, p: &PendingPlay) -> Result<()> {
+    s.outstanding_liability = s
+        .outstanding_liability
+        .checked_sub(p.max_payout)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+fn jackpot_contribution(total_bet: u64, o: &Odds) -> Result<u64> {
+    let c = (total_bet as u128)
+        .checked_mul(o.jackpot_bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / 10_000u128;
+    u64::try_from(c).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+fn required_jackpot<'a, 'info>(
+    a: Option<&'a Account<'info, TokenAccount>>,
+) -> Result<&'a Account<'info, TokenAccount>> {
+    a.ok_or_else(|| error!(ErrorCode::MissingJackpotAccount))
+}
+fn accrue_jackpot(s: &mut GameState, player: Pubkey, amount: u64) -> Result<()> {
+    s.jackpot_total = s
+        .jackpot_total
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+    emit!(JackpotAccrued {
+        player,
+        amount,
+        jackpot_total: s.jackpot_total,
+    });
+    Ok(())
+}
+// 是否命中 jackpot：Combo 检查每次转轮；Draw 使用与转轮无关的独立随机数
+fn jackpot_hit(
+    seed: [u8; 32],
+    bets: &[u64; 6],
+    t: &SpinTranscript,
+    o: &Odds,
+    l: &LineOdds,
+) -> Result<bool> {
+    Ok(match o.jackpot_trigger {
+        JackpotTrigger::Disabled => false,
+        JackpotTrigger::Combo { symbol, min_bet } => {
+            let bet = bets[symbol as usize];
+            let full = |cells: &[u8]| match o.layout {
+                Layout::Classic => cells.iter().all(|x| *x == symbol),
+                Layout::Lines => l.paylines.iter().any(|line| {
+                    (0..l.reels as usize)
+                        .all(|r| cells[r * l.rows as usize + line[r] as usize] == symbol)
+                }),
+            };
+            bet > 0 && bet >= min_bet && t.spins.iter().any(|sp| full(&sp.reels))
+        }
+        JackpotTrigger::Draw { one_in, min_bet } => {
+            let r = hashv(&[b"SLOT_JACKPOT", &seed]).to_bytes();
+            let mut x = [0u8; 8];
+            x.copy_from_slice(&r[..8]);
+            let draw = (u64::from_le_bytes(x) as u128 * one_in as u128) >> 64;
+            bets_total(bets)? >= min_bet.max(1) && draw == 0
+        }
+    })
+}
+// 派出整个 jackpot（按记账值，不含误转入的代币）
+fn pay_jackpot<'info>(
+    s: &mut Account<'info, GameState>,
+    tp: &Program<'info, Token>,
+    jackpot: Option<&Account<'info, TokenAccount>>,
+    to: &Account<'info, TokenAccount>,
+    player: Pubkey,
+) -> Result<u64> {
+    let amount = s.jackpot_total;
+    if amount == 0 {
+        return Ok(0);
+    }
+    pool_transfer_signed(s, tp, required_jackpot(jackpot)?, to, amount)?;
+    s.jackpot_total = 0;
+    emit!(JackpotWon { player, amount });
+    Ok(amount)
+}
+fn emit_play_settled(
+    pending_key: Pubkey,
+    p: &PendingPlay,
+    t: SpinTranscript,
+    keeper: Pubkey,
+    fee: u64,
+) {
+    emit!(PlaySettled {
+        pending_play: pending_key,
+        player: p.player,
+        seq: p.seq,
+        bets: p.bets,
+        total_bet: p.total_bet,
+        spins: t.spins,
+        doubles: t.doubles,
+        multiplier: t.multiplier,
+        payout: t.payout,
+        room_card: p.has_room_card.then_some(p.room_card),
+        keeper,
+        keeper_fee: fee,
+    });
+}
+fn keeper_fee(s: &GameState, total_bet: u64) -> Result<u64> {
+    let fee = (total_bet as u128)
+        .checked_mul(s.keeper_fee_bps as u128)
+        .ok_or(ErrorCode::MathOverflow)?
+        / 10_000u128;
+    u64::try_from(fee).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+// keeper 奖励由奖池（庄家）承担，不影响玩家派彩与代理佣金
+fn pay_keeper_fee<'info>(
+    s: &mut Account<'info, GameState>,
+    tp: &Program<'info, Token>,
+    pool: &Account<'info, TokenAccount>,
+    keeper_token_account: Option<&Account<'info, TokenAccount>>,
+    fee: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+    let to = keeper_token_account.ok_or(ErrorCode::MissingKeeperTokenAccount)?;
+    require_keys_eq!(to.mint, s.pool_mint, ErrorCode::InvalidTokenAccount);
+    require!(pool.amount >= fee, ErrorCode::InsufficientPool);
+    require!(s.total_pool >= fee, ErrorCode::InsufficientPool);
+    pool_transfer_signed(s, tp, pool, to, fee)?;
+    s.total_pool = s
+        .total_pool
+        .checked_sub(fee)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(())
+}
+// game_id 为 0 时种子为空，PDA 与旧版单例 [GAME_STATE_SEED] 相同（仅地址不变；
+// 账户布局已变，已部署的账户须先经 migrate_agents 升级才能读取）
+pub fn game_id_seed(game_id: u64) -> Vec<u8> {
+    if game_id == 0 {
+        Vec::new()
+    } else {
+        game_id.to_le_bytes().to_vec()
+    }
+}
+fn game_state_address(s: &GameState) -> Result<Pubkey> {
+    Pubkey::create_program_address(&[GAME_STATE_SEED, &s.id_seed(), &[s.bump]], &crate::ID)
+        .map_err(|_| error!(ErrorCode::InvalidVrfAuthority))
+}
+// 旧版布局的 game_state（只可能是 game_id 0）：校验 PDA 后扩容到新布局大小，调用者补足租金差额
+fn upgrade_legacy_game_state<'info>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    sys: &AccountInfo<'info>,
+) -> Result<()> {
+    let legacy = {
+        let data = info.try_borrow_data()?;
+        require!(
+            data[..8] == <GameState as anchor_lang::Discriminator>::DISCRIMINATOR,
+            ErrorCode::InvalidMigrationAccount
+        );
+        LegacyGameState::deserialize(&mut &data[8..])?
+    };
+    let expected = Pubkey::create_program_address(&[GAME_STATE_SEED, &[legacy.bump]], &crate::ID)
+        .map_err(|_| error!(ErrorCode::InvalidMigrationAccount))?;
+    require_keys_eq!(info.key(), expected, ErrorCode::InvalidMigrationAccount);
+    let len = 8 + GameState::SPACE;
+    let rent = Rent::get()?;
+    let top_up = rent
+        .minimum_balance(len)
+        .saturating_sub(rent.minimum_balance(info.data_len()));
+    if top_up > 0 {
+        invoke_sol_transfer(payer, info, sys, top_up)?;
+    }
+    info.realloc(len, true)?;
+    legacy
+        .upgrade()
+        .try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+// 返回本局最坏情况派彩，供两段式请求计入未结算负债
+fn validate_bets(b: &[u64; 6], s: &GameState, o: &Odds, l: &LineOdds) -> Result<u64> {
+    let min_bet = s.min_bet;
+    // 仅 Regular 符号可下注
+    require!(
+        b.iter().zip(o.roles.iter()).all(|(v, r)| *v == 0 || *r == SymbolRole::Regular),
+        ErrorCode::InvalidBetTable
+    );
     let total = bets_total(b)?;
     require!(total > 0, ErrorCode::InvalidAmount);
     if min_bet > 0 {
-        for i in 0..5 { let v = b[i]; if v > 0 {
-                require!(v >= min_bet, ErrorCode::BetBelowMinimum);
+        require!(
+            b.iter().all(|v| *v == 0 || *v >= min_bet),
+            ErrorCode::BetBelowMinimum
+        );
+    }
+    if s.max_bet_per_symbol > 0 {
+        require!(
+            b.iter().all(|v| *v <= s.max_bet_per_symbol),
+            ErrorCode::BetAboveMaximum
+        );
+    }
+    if s.max_bet_per_play > 0 {
+        require!(total <= s.max_bet_per_play, ErrorCode::BetAboveMaximum);
+    }
+    let worst = worst_case_payout(b, o, l)?;
+    if s.max_exposure_bps > 0 {
+        // 未结算局的最坏派彩已占用奖池，只按剩余部分计算敞口上限
+        let free = s.total_pool.saturating_sub(s.outstanding_liability);
+        let limit = (free as u128) * (s.max_exposure_bps as u128) / 10_000u128;
+        require!(worst <= limit, ErrorCode::ExposureTooHigh);
+    }
+    Ok(u64::try_from(worst).unwrap_or(u64::MAX))
+}
+// 最坏情况派彩：Classic 每次触发自动转轮的转轮至少一格为 Multiplier，只能命中两连；
+// 最后一次（乘数 multipliers[D]）可命中三连，D = min(max_auto_spins, 4)。
+// Lines 按每次转轮所有赔付线都命中最高赔率、乘数合计 Σ multipliers[0..=D] 保守估计。
+// 存在 Wild 时多个符号可同时命中，按各符号之和而非最大值估计；每次转轮另加 Scatter 最高赔付
+fn worst_case_payout(b: &[u64; 6], o: &Odds, l: &LineOdds) -> Result<u128> {
+    let has_wild = o.roles.contains(&SymbolRole::Wild);
+    let combine = |acc: u128, x: u128| if has_wild { acc + x } else { acc.max(x) };
+    let max_scatter = o.scatter_pays.iter().copied().max().unwrap_or_default();
+    let scatter = bets_total(b)? as u128 * max_scatter as u128;
+    let d = auto_spin_cap(o);
+    let muls: u128 = o.multipliers[..=d].iter().map(|m| *m as u128).sum();
+    if o.layout == Layout::Lines {
+        let mut max_line = 0u128;
+        for (sym, bet) in b.iter().enumerate() {
+            let rate = l.line_pays[sym].iter().copied().max().unwrap_or_default();
+            max_line = combine(max_line, *bet as u128 * rate as u128);
+        }
+        let worst = max_line
+            .checked_mul(l.paylines.len() as u128)
+            .and_then(|x| x.checked_add(scatter))
+            .and_then(|x| x.checked_mul(muls))
+            .ok_or(ErrorCode::MathOverflow)?;
+        return Ok(worst / 100);
+    }
+    let mut max_double = 0u128;
+    let mut max_final = 0u128;
+    for (sym, bet) in b.iter().enumerate() {
+        let bet = *bet as u128;
+        let double = bet * o.payout_double[sym] as u128;
+        let triple = bet * o.payout_triple[sym] as u128;
+        max_double = combine(max_double, double);
+        max_final = combine(max_final, triple.max(double));
+    }
+    let last = o.multipliers[d] as u128;
+    let worst = (muls - last)
+        .checked_mul(max_double)
+        .and_then(|x| x.checked_add(last.checked_mul(max_final)?))
+        .and_then(|x| x.checked_add(muls.checked_mul(scatter)?))
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(worst / 100)
+}
+// 排队修改配置：已有排队项时在其基础上修改并重新计时；传入 lines 时同时校验网格 RTP
+fn queue_config(
+    s: &mut GameState,
+    admin: Pubkey,
+    slot: u64,
+    lines: Option<&LineOdds>,
+    f: impl FnOnce(&mut QueuedConfig),
+) -> Result<()> {
+    apply_due_config(s, slot)?;
+    if !s.queued_config.pending {
+        s.queued_config = QueuedConfig {
+            pending: true,
+            effective_slot: 0,
+            odds: s.odds(),
+            config_delay_slots: s.config_delay_slots,
+        };
+    }
+    f(&mut s.queued_config);
+    let odds = s.queued_config.odds;
+    validate_rtp(s, &odds)?;
+    if let Some(l) = lines {
+        validate_line_rtp(s, &odds, l)?;
+    }
+    let q = &mut s.queued_config;
+    q.effective_slot = slot
+        .checked_add(s.config_delay_slots)
+        .ok_or(ErrorCode::MathOverflow)?;
+    emit!(ConfigQueued {
+        admin,
+        version: s.config_version.checked_add(1).ok_or(ErrorCode::MathOverflow)?,
+        effective_slot: q.effective_slot,
+        odds: q.odds,
+        config_delay_slots: q.config_delay_slots,
+    });
+    apply_due_config(s, slot)?;
+    Ok(())
+}
+fn apply_due_config(s: &mut GameState, slot: u64) -> Result<bool> {
+    let q = s.queued_config;
+    if !q.pending || q.effective_slot > slot {
+        return Ok(false);
+    }
+    s.reel_weights = q.odds.reel_weights;
+    s.payout_triple = q.odds.payout_triple;
+    s.payout_double = q.odds.payout_double;
+    s.max_auto_spins = q.odds.max_auto_spins;
+    s.layout = q.odds.layout;
+    s.symbol_roles = q.odds.roles;
+    s.scatter_pays = q.odds.scatter_pays;
+    s.multipliers = q.odds.multipliers;
+    s.trigger_count = q.odds.trigger_count;
+    s.jackpot_bps = q.odds.jackpot_bps;
+    s.jackpot_trigger = q.odds.jackpot_trigger;
+    s.config_delay_slots = q.config_delay_slots;
+    s.config_version = s.config_version.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    s.queued_config.pending = false;
+    emit!(ConfigApplied {
+        version: s.config_version,
+        odds: q.odds,
+        config_delay_slots: q.config_delay_slots,
+    });
+    Ok(true)
+}
+// 当前 slot 实际生效的赔率（已到期但尚未 apply 的排队项视为已生效）
+fn current_odds(s: &GameState, slot: u64) -> Result<(u64, Odds)> {
+    let q = &s.queued_config;
+    if q.pending && q.effective_slot <= slot {
+        let version = s.config_version.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        return Ok((version, q.odds));
+    }
+    Ok((s.config_version, s.odds()))
+}
+// 各符号 RTP（万分比），按 RTP_SCALE 定点计算。各格独立按所在转轮权重取符号
+// （转轮 r 出现符号 i 的概率 p_r,i），Wild 可替代，故转轮 r 命中符号 i 的概率
+// m_r = p_r,i + Σ p_r,wild。
+// Classic：E = (P3·triple + P2·double) / 100，P3 = m0·m1·m2，P2 = 恰好两个转轮命中；
+// Lines：P(连续命中 ≥ k) = Π_{r<k} m_r，E_line = Σ_k P(恰好 k 连)·line_pays[k-3] / 100，
+// 各线期望相同，E = 赔付线数 × E_line。
+// Scatter 按全部格子的命中个数分布（逐格递推）计算期望 S，按总下注派彩，
+// 每单位下注贡献相同，计入每个 Regular 符号。单次转轮出现至少 trigger_count 个
+// Multiplier 的概率为 q 时，第 k 次自动转轮（乘数 multipliers[k]）的到达概率为 q^k，
+// 最多 min(max_auto_spins, 4) 次，故 RTP = (E + S) · Σ multipliers[k]·q^k，
+// 与 compute_total_payout 的循环规则一致。
+// 非 Regular 符号不可下注，RTP 记为 0
+fn rtp_bps(o: &Odds, l: &LineOdds) -> Result<[u32; 6]> {
+    let (reels, rows, weights) = match o.layout {
+        Layout::Classic => (REELS, 1, &o.reel_weights[..]),
+        Layout::Lines => (l.reels as usize, l.rows, &l.reel_weights[..l.reels as usize]),
+    };
+    let mut p = [[0u128; 6]; MAX_REELS];
+    for (pr, weights) in p.iter_mut().zip(weights.iter()) {
+        let total: u128 = weights.iter().map(|w| *w as u128).sum();
+        require!(total > 0, ErrorCode::InvalidSymbolWeights);
+        for (x, w) in pr.iter_mut().zip(weights.iter()) {
+            *x = *w as u128 * RTP_SCALE / total;
+        }
+    }
+    // role_p[r] = 转轮 r 出现该角色符号的概率
+    let role_p = |role: SymbolRole| -> [u128; MAX_REELS] {
+        core::array::from_fn(|r| {
+            let mut x = 0u128;
+            for (sym, pr) in p[r].iter().enumerate() {
+                if o.roles[sym] == role {
+                    x += *pr;
+                }
             }
+            x
+        })
+    };
+    let wild = role_p(SymbolRole::Wild);
+    let scatter_p = role_p(SymbolRole::Scatter);
+    let n = reels * rows as usize;
+    let trigger = count_dist(&role_p(SymbolRole::Multiplier), reels, rows);
+    let q: u128 = trigger.iter().take(n + 1).skip(o.trigger_count as usize).sum();
+    let factor = auto_spin_factor(q.min(RTP_SCALE), o);
+    let mut scatter = 0u128;
+    let dist = count_dist(&scatter_p, reels, rows);
+    for (c, d) in dist.iter().enumerate().take(n + 1).skip(2) {
+        scatter += d * o.scatter_pays[c.min(5) - 2] as u128;
+    }
+    let mut out = [0u32; 6];
+    for (sym, r) in out.iter_mut().enumerate() {
+        if o.roles[sym] != SymbolRole::Regular {
+            continue;
+        }
+        let m: [u128; MAX_REELS] = core::array::from_fn(|r| p[r][sym] + wild[r]);
+        let hit = match o.layout {
+            Layout::Classic => {
+                let n: [u128; REELS] = core::array::from_fn(|r| RTP_SCALE - m[r]);
+                let three = m[0] * m[1] / RTP_SCALE * m[2] / RTP_SCALE;
+                let two = (m[0] * m[1] / RTP_SCALE * n[2]
+                    + m[0] * n[1] / RTP_SCALE * m[2]
+                    + n[0] * m[1] / RTP_SCALE * m[2])
+                    / RTP_SCALE;
+                three * o.payout_triple[sym] as u128 + two * o.payout_double[sym] as u128
+            }
+            Layout::Lines => {
+                // ge[k] = P(前 k 个转轮均命中该符号)
+                let mut ge = [0u128; MAX_REELS + 2];
+                ge[0] = RTP_SCALE;
+                for k in 1..=reels {
+                    ge[k] = ge[k - 1] * m[k - 1] / RTP_SCALE;
+                }
+                let mut e = 0u128;
+                for k in 3..=reels {
+                    e += (ge[k] - ge[k + 1]) * l.line_pays[sym][k - 3] as u128;
+                }
+                e * l.paylines.len() as u128
+            }
+        };
+        let e = (hit + scatter) / 100;
+        let bps = e
+            .checked_mul(factor)
+            .and_then(|x| x.checked_mul(10_000))
+            .ok_or(ErrorCode::MathOverflow)?
+            / RTP_SCALE
+            / RTP_SCALE;
+        *r = u32::try_from(bps).map_err(|_| error!(ErrorCode::MathOverflow))?;
+    }
+    Ok(out)
+}
+// 各格独立、格子命中概率按转轮给出时，命中个数的分布（RTP_SCALE 定点，逐格递推）
+fn count_dist(p: &[u128; MAX_REELS], reels: usize, rows: u8) -> [u128; MAX_CELLS + 1] {
+    let mut dist = [0u128; MAX_CELLS + 1];
+    dist[0] = RTP_SCALE;
+    let mut n = 0usize;
+    for pr in p.iter().take(reels) {
+        for _ in 0..rows {
+            n += 1;
+            for c in (1..=n).rev() {
+                dist[c] = (dist[c] * (RTP_SCALE - pr) + dist[c - 1] * pr) / RTP_SCALE;
+            }
+            dist[0] = dist[0] * (RTP_SCALE - pr) / RTP_SCALE;
+        }
+    }
+    dist
+}
+// 自动转轮的期望乘数和 Σ multipliers[k]·q^k（RTP_SCALE 定点），q 为单次转轮触发的概率
+fn auto_spin_factor(q: u128, o: &Odds) -> u128 {
+    let mut reach = RTP_SCALE;
+    let mut factor = 0u128;
+    for m in o.multipliers.iter().take(auto_spin_cap(o) + 1) {
+        factor += reach * *m as u128;
+        reach = reach * q / RTP_SCALE;
+    }
+    factor
+}
+// 最多自动转轮次数
+fn auto_spin_cap(o: &Odds) -> usize {
+    (o.max_auto_spins as usize).min(MAX_SPINS - 1)
+}
+// 仅校验可下注（Regular）符号；划入 jackpot 的比例终将派给玩家，计入返还率
+fn check_rtp_bounds(s: &GameState, o: &Odds, rtp: &[u32; 6]) -> Result<()> {
+    for (r, role) in rtp.iter().zip(o.roles.iter()) {
+        if *role != SymbolRole::Regular {
+            continue;
+        }
+        let r = *r + o.jackpot_bps as u32;
+        require!(
+            r >= s.rtp_min_bps as u32 && r <= s.rtp_max_bps as u32,
+            ErrorCode::RtpOutOfBounds
+        );
+    }
+    Ok(())
+}
+// 经典赔率表始终校验；网格布局在修改网格配置、切换布局或符号角色时另行校验
+fn validate_rtp(s: &GameState, o: &Odds) -> Result<()> {
+    let o = Odds { layout: Layout::Classic, ..*o };
+    check_rtp_bounds(s, &o, &rtp_bps(&o, &LineOdds::default())?)
+}
+fn validate_line_rtp(s: &GameState, o: &Odds, l: &LineOdds) -> Result<()> {
+    let o = Odds { layout: Layout::Lines, ..*o };
+    check_rtp_bounds(s, &o, &rtp_bps(&o, l)?)
+}
+fn validate_lines(l: &LineOdds) -> Result<()> {
+    let reels = l.reels as usize;
+    let rows = l.rows as usize;
+    require!(
+        (3..=MAX_REELS).contains(&reels) && (1..=MAX_ROWS).contains(&rows),
+        ErrorCode::InvalidLineConfig
+    );
+    require!(
+        !l.paylines.is_empty() && l.paylines.len() <= MAX_PAYLINES,
+        ErrorCode::InvalidLineConfig
+    );
+    for line in l.paylines.iter() {
+        for (r, row) in line.iter().enumerate() {
+            let ok = if r < reels { (*row as usize) < rows } else { *row == 0 };
+            require!(ok, ErrorCode::InvalidLineConfig);
+        }
+    }
+    for (r, w) in l.reel_weights.iter().enumerate() {
+        if r < reels {
+            validate_weights(w)?;
+        } else {
+            require!(w.iter().all(|x| *x == 0), ErrorCode::InvalidLineConfig);
+        }
+    }
+    // 转轮数不足时对应的 4/5 连赔率必须为 0
+    for pays in l.line_pays.iter() {
+        for (i, rate) in pays.iter().enumerate() {
+            require!(i + 3 <= reels || *rate == 0, ErrorCode::InvalidLineConfig);
         }
     }
     Ok(())
 }
+// 网格布局生效或排队生效时，修改共用赔率参数需同时校验网格 RTP
+fn lines_to_validate<'a>(
+    s: &GameState,
+    c: &'a Option<Account<GameConfig>>,
+) -> Result<Option<&'a LineOdds>> {
+    let q = &s.queued_config;
+    if s.layout == Layout::Lines || (q.pending && q.odds.layout == Layout::Lines) {
+        let c = c.as_ref().ok_or(ErrorCode::MissingGameConfig)?;
+        return Ok(Some(&c.lines));
+    }
+    Ok(None)
+}
+// 按赔率布局取本局使用的网格配置（Classic 返回空配置）
+fn active_lines(o: &Odds, c: &Option<Account<GameConfig>>) -> Result<LineOdds> {
+    match o.layout {
+        Layout::Classic => Ok(LineOdds::default()),
+        Layout::Lines => {
+            let c = c.as_ref().ok_or(ErrorCode::MissingGameConfig)?;
+            Ok(c.lines.clone())
+        }
+    }
+}
 fn validate_weights(w: &[u16; 6]) -> Result<()> {
     let mut sum = 0u32;
     for x in w.iter() {
@@ -727,6 +3746,79 @@ fn read_vrf_bytes(vrf: &UncheckedAccount, expected: Pubkey, offset: u32) -> Resu
     out.copy_from_slice(&data[s..e]);
     Ok(out)
 }
+// 读取 Switchboard VRF 当前轮次（counter, result）；要求 VRF authority 为 game_state PDA
+fn read_vrf_round(
+    vrf: &AccountInfo,
+    expected: Pubkey,
+    authority: Pubkey,
+) -> Result<(u128, [u8; 32])> {
+    require_keys_eq!(vrf.key(), expected, ErrorCode::InvalidVrfAccount);
+    require!(vrf.owner == &SWITCHBOARD_V2_PROGRAM_ID, ErrorCode::InvalidVrfOwner);
+    let data = vrf.try_borrow_data()?;
+    let v = VrfAccountData::new_from_bytes(&data).map_err(|_| error!(ErrorCode::InvalidVrfData))?;
+    require_keys_eq!(v.authority, authority, ErrorCode::InvalidVrfAuthority);
+    Ok((v.counter, v.current_round.result))
+}
+// CPI：Switchboard V2 vrf_request_randomness，game_state PDA 作为 VRF authority 签名，玩家支付请求费用
+fn request_vrf_randomness(
+    a: &RequestPlay,
+    switchboard_state_bump: u8,
+    permission_bump: u8,
+) -> Result<()> {
+    let vrf = required(&a.vrf)?;
+    let oracle_queue = required(&a.oracle_queue)?;
+    let queue_authority = required(&a.queue_authority)?;
+    let data_buffer = required(&a.data_buffer)?;
+    let permission = required(&a.permission)?;
+    let escrow = required(&a.escrow)?;
+    let payer_wallet = required(&a.payer_wallet)?;
+    let recent_blockhashes = required(&a.recent_blockhashes)?;
+    let program_state = required(&a.program_state)?;
+    let switchboard_program = required(&a.switchboard_program)?;
+    let metas = vec![
+        AccountMeta::new_readonly(a.game_state.key(), true),
+        AccountMeta::new(vrf.key(), false),
+        AccountMeta::new(oracle_queue.key(), false),
+        AccountMeta::new_readonly(queue_authority.key(), false),
+        AccountMeta::new_readonly(data_buffer.key(), false),
+        AccountMeta::new(permission.key(), false),
+        AccountMeta::new(escrow.key(), false),
+        AccountMeta::new(payer_wallet.key(), false),
+        AccountMeta::new_readonly(a.player.key(), true),
+        AccountMeta::new_readonly(recent_blockhashes.key(), false),
+        AccountMeta::new_readonly(program_state.key(), false),
+        AccountMeta::new_readonly(a.token_program.key(), false),
+    ];
+    let mut data = hash(b"global:vrf_request_randomness").to_bytes()[..8].to_vec();
+    data.extend_from_slice(&[permission_bump, switchboard_state_bump]);
+    let ix = Instruction {
+        program_id: SWITCHBOARD_V2_PROGRAM_ID,
+        accounts: metas,
+        data,
+    };
+    let id_seed = a.game_state.id_seed();
+    let seeds = &[GAME_STATE_SEED, &id_seed, &[a.game_state.bump]];
+    invoke_signed(
+        &ix,
+        &[
+            a.game_state.to_account_info(),
+            vrf.to_account_info(),
+            oracle_queue.to_account_info(),
+            queue_authority.to_account_info(),
+            data_buffer.to_account_info(),
+            permission.to_account_info(),
+            escrow.to_account_info(),
+            payer_wallet.to_account_info(),
+            a.player.to_account_info(),
+            recent_blockhashes.to_account_info(),
+            program_state.to_account_info(),
+            a.token_program.to_account_info(),
+            switchboard_program.to_account_info(),
+        ],
+        &[&seeds[..]],
+    )?;
+    Ok(())
+}
 fn derive_seed(
     vrf: [u8; 32],
     player: Option<Pubkey>,
@@ -751,7 +3843,10 @@ fn pick_symbol(seed: [u8; 32], w: &[u16; 6]) -> Result<u8> {
         sum = sum.checked_add(*x as u32).ok_or(ErrorCode::MathOverflow)?;
     }
     require!(sum > 0, ErrorCode::InvalidSymbolWeights);
-    let mut r = (u16::from_le_bytes([seed[0], seed[1]]) as u32) % sum;
+    // 取 8 字节做 64 位乘移位映射到 [0, sum)，避免取模偏差（偏差 < sum / 2^64）
+    let mut x = [0u8; 8];
+    x.copy_from_slice(&seed[..8]);
+    let mut r = ((u64::from_le_bytes(x) as u128 * sum as u128) >> 64) as u32;
     for (i, &ww) in w.iter().enumerate() {
         let v = ww as u32;
         if r < v {
@@ -761,90 +3856,359 @@ fn pick_symbol(seed: [u8; 32], w: &[u16; 6]) -> Result<u8> {
     }
     Ok((SYMBOLS - 1) as u8)
 }
-fn compute_spin_payout(b: &[u64; 6], reels: [u8; 3], s: &GameState) -> Result<u64> {
-    let mut total = 0u64;
+// 单次转轮各符号的中奖金额（未乘乘数）：仅 Regular 符号按下注表派彩，Wild 可替代；
+// Scatter 按出现总个数与总下注派彩，计入第一个 Scatter 符号
+fn compute_spin_payout(b: &[u64; 6], cells: &[u8], s: &Odds, l: &LineOdds) -> Result<[u64; 6]> {
+    let mut wins = [0u64; 6];
+    let role = |x: u8| s.roles[x as usize];
     for sym in 0..SYMBOLS {
         let bet = b[sym];
-        if bet == 0 {
+        if bet == 0 || s.roles[sym] != SymbolRole::Regular {
             continue;
         }
-        let m = (reels[0] == sym as u8) as u8
-            + (reels[1] == sym as u8) as u8
-            + (reels[2] == sym as u8) as u8;
-        let rate = if m == 3 {
-            s.payout_triple[sym]
-        } else if m == 2 {
-            s.payout_double[sym]
-        } else {
-            0
+        let hit = |x: u8| x == sym as u8 || role(x) == SymbolRole::Wild;
+        let rate = match s.layout {
+            Layout::Classic => {
+                let m = cells.iter().filter(|x| hit(**x)).count();
+                if m == 3 {
+                    s.payout_triple[sym] as u128
+                } else if m == 2 {
+                    s.payout_double[sym] as u128
+                } else {
+                    0
+                }
+            }
+            Layout::Lines => {
+                let mut rate = 0u128;
+                for line in l.paylines.iter() {
+                    let run = (0..l.reels as usize)
+                        .take_while(|r| hit(cells[r * l.rows as usize + line[*r] as usize]))
+                        .count();
+                    if run >= 3 {
+                        rate += l.line_pays[sym][run - 3] as u128;
+                    }
+                }
+                rate
+            }
         };
         if rate == 0 {
             continue;
         }
         let win = (bet as u128)
-            .checked_mul(rate as u128)
+            .checked_mul(rate)
             .ok_or(ErrorCode::MathOverflow)?
             / 100u128;
-        total = total
-            .checked_add(u64::try_from(win).map_err(|_| error!(ErrorCode::MathOverflow))?)
-            .ok_or(ErrorCode::MathOverflow)?;
+        wins[sym] = u64::try_from(win).map_err(|_| error!(ErrorCode::MathOverflow))?;
     }
-    Ok(total)
+    let scatters = cells.iter().filter(|x| role(**x) == SymbolRole::Scatter).count();
+    if scatters >= 2 {
+        let sym = s.roles.iter().position(|r| *r == SymbolRole::Scatter).unwrap_or_default();
+        let win = (bets_total(b)? as u128)
+            .checked_mul(s.scatter_pays[scatters.min(5) - 2] as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / 100u128;
+        wins[sym] = u64::try_from(win).map_err(|_| error!(ErrorCode::MathOverflow))?;
+    }
+    Ok(wins)
 }
 fn compute_total_payout(
     seed: [u8; 32],
     bets: &[u64; 6],
-    s: &GameState,
-) -> Result<(u64, u8, u8, [u8; 3])> {
+    s: &Odds,
+    l: &LineOdds,
+) -> Result<SpinTranscript> {
+    // 各格使用的权重表：Classic 为 3 转轮单行，Lines 按转轮优先展开
+    let (grid_reels, grid_rows) = match s.layout {
+        Layout::Classic => (REELS as u8, 1u8),
+        Layout::Lines => (l.reels, l.rows),
+    };
+    let mut cell_weights = Vec::with_capacity(grid_reels as usize * grid_rows as usize);
+    for r in 0..grid_reels as usize {
+        for _ in 0..grid_rows {
+            cell_weights.push(match s.layout {
+                Layout::Classic => &s.reel_weights[r],
+                Layout::Lines => &l.reel_weights[r],
+            });
+        }
+    }
     let mut cur = seed;
     let mut c = 0u64;
-    let mut mul = 1u8;
+    let mut mul = s.multipliers[0];
     let mut doubles = 0u8;
     let mut total = 0u64;
-    let mut last: [u8; 3];
+    let mut spins = Vec::with_capacity(MAX_SPINS);
     loop {
-        let mut reels = [0u8; 3];
-        for i in 0..3 {
+        let mut reels = Vec::with_capacity(cell_weights.len());
+        for w in cell_weights.iter() {
             cur = next_seed(cur, c);
             c = c.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
-            reels[i] = pick_symbol(cur, &s.symbol_weights)?;
+            reels.push(pick_symbol(cur, w)?);
         }
-        last = reels;
-        let payout = compute_spin_payout(bets, reels, s)?;
-        let scaled = (payout as u128)
-            .checked_mul(mul as u128)
-            .ok_or(ErrorCode::MathOverflow)?;
-        total = total
-            .checked_add(u64::try_from(scaled).map_err(|_| error!(ErrorCode::MathOverflow))?)
-            .ok_or(ErrorCode::MathOverflow)?;
-        if !reels.iter().any(|x| *x == 5) {
+        let mut wins = compute_spin_payout(bets, &reels, s, l)?;
+        let mut spin_total = 0u64;
+        for w in wins.iter_mut() {
+            *w = w.checked_mul(mul as u64).ok_or(ErrorCode::MathOverflow)?;
+            spin_total = spin_total.checked_add(*w).ok_or(ErrorCode::MathOverflow)?;
+        }
+        total = total.checked_add(spin_total).ok_or(ErrorCode::MathOverflow)?;
+        let hits = reels.iter().filter(|x| s.roles[**x as usize] == SymbolRole::Multiplier).count();
+        let triggered = hits >= s.trigger_count as usize;
+        spins.push(SpinRecord {
+            reels,
+            multiplier: mul,
+            wins,
+            total: spin_total,
+        });
+        if !triggered {
             break;
         }
-        if doubles >= s.max_auto_spins || mul >= 16 {
+        if doubles as usize >= auto_spin_cap(s) {
             break;
         }
-        mul = mul.checked_mul(2).ok_or(ErrorCode::MathOverflow)?;
         doubles = doubles.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        mul = s.multipliers[doubles as usize];
+    }
+    Ok(SpinTranscript {
+        spins,
+        doubles,
+        multiplier: mul,
+        payout: total,
+        grid_reels,
+        grid_rows,
+        jackpot: 0,
+    })
+}
+fn agent_room_card(a: &AgentAccount, s: &GameState) -> u64 {
+    if a.room_card > 0 {
+        a.room_card
+    } else {
+        s.next_room_card
     }
-    Ok((total, doubles, mul, last))
 }
-fn find_active_agent_by_room_card<'a>(s: &'a GameState, card: u64) -> Option<&'a Agent> {
-    s.agents.iter().find(|a| a.room_card == card && a.room_card > 0)
+fn required_agent<'a, 'info>(
+    a: &'a Option<Account<'info, AgentAccount>>,
+) -> Result<&'a Account<'info, AgentAccount>> {
+    a.as_ref().ok_or_else(|| error!(ErrorCode::InvalidRoomCard))
 }
+// 房卡必须属于传入的、处于激活状态的代理商
+fn check_room_card_agent(a: Option<&AgentAccount>, card: Option<u64>) -> Result<()> {
+    let Some(card) = card else { return Ok(()); };
+    let a = a.ok_or(ErrorCode::InvalidRoomCard)?;
+    require!(
+        card > 0 && a.is_active && a.room_card == card,
+        ErrorCode::InvalidRoomCard
+    );
+    Ok(())
+}
+fn bind_player(
+    stats: &mut PlayerStats,
+    agent: &AgentAccount,
+    room_card: u64,
+    now: i64,
+) -> Result<()> {
+    require!(!self_referral(stats.player, agent), ErrorCode::SelfReferral);
+    stats.room_card = room_card;
+    stats.bound_agent = agent.agent;
+    stats.bound_at = now;
+    stats.bound_volume = 0;
+    emit!(RoomCardBound {
+        player: stats.player,
+        agent: agent.agent,
+        room_card,
+    });
+    Ok(())
+}
+// 绑定的代理商已赎回或换卡时绑定失效
+fn binding_lapsed(stats: &PlayerStats, a: &AgentAccount) -> Result<bool> {
+    require_keys_eq!(a.agent, stats.bound_agent, ErrorCode::InvalidRoomCard);
+    Ok(!a.is_active || a.room_card != stats.room_card)
+}
+// 本局佣金归属：已绑定时必须传入绑定的代理商并以绑定房卡为准（参数不得与之不符），
+// 绑定失效则解除；未绑定且传入房卡时首次绑定
+fn resolve_room_card(
+    stats: &mut PlayerStats,
+    agent: Option<&AgentAccount>,
+    card: Option<u64>,
+    now: i64,
+) -> Result<Option<u64>> {
+    if stats.room_card != 0 {
+        let a = agent.ok_or(ErrorCode::InvalidRoomCard)?;
+        if !binding_lapsed(stats, a)? {
+            require!(
+                card.unwrap_or(stats.room_card) == stats.room_card,
+                ErrorCode::RoomCardBindingMismatch
+            );
+            return Ok(Some(stats.room_card));
+        }
+        stats.room_card = 0;
+        stats.bound_agent = Pubkey::default();
+        stats.bound_at = 0;
+        stats.bound_volume = 0;
+    }
+    let Some(c) = card else { return Ok(None); };
+    check_room_card_agent(agent, Some(c))?;
+    let a = agent.ok_or(ErrorCode::InvalidRoomCard)?;
+    bind_player(stats, a, c, now)?;
+    Ok(Some(c))
+}
+// 玩家为代理商本人或其质押代付人时视为自推广
+fn self_referral(player: Pubkey, a: &AgentAccount) -> bool {
+    player == a.agent || player == a.stake_funder
+}
+// 佣金率上限：最坏情况下（整注输光）返佣也不得吃掉全部庄家优势，
+// 即 rate% + rtp_max_bps + jackpot_bps + keeper_fee_bps 严格小于 100%
+fn commission_cap(rtp_max_bps: u16, jackpot_bps: u16, keeper_fee_bps: u16) -> u8 {
+    let edge = MAX_RTP_BPS
+        .saturating_sub(rtp_max_bps)
+        .saturating_sub(jackpot_bps)
+        .saturating_sub(keeper_fee_bps);
+    (edge.saturating_sub(1) / 100).min(100) as u8
+}
+// 生效中与排队中 jackpot 划入比例的较大者，修改佣金率时按此预留
+fn jackpot_bps_ceiling(s: &GameState) -> u16 {
+    let q = &s.queued_config;
+    if q.pending {
+        s.jackpot_bps.max(q.odds.jackpot_bps)
+    } else {
+        s.jackpot_bps
+    }
+}
+// 修改佣金率时的上限（按当前 RTP 上限、jackpot 预留与 keeper 奖励）
+fn config_commission_cap(s: &GameState) -> u16 {
+    commission_cap(s.rtp_max_bps, jackpot_bps_ceiling(s), s.keeper_fee_bps) as u16
+}
+// 直属代理商与各层上级的佣金率之和
+fn total_commission_rate(rate: u8, upline: &[u8; MAX_UPLINE_DEPTH], depth: u8) -> u16 {
+    rate as u16 + upline[..depth as usize].iter().map(|r| *r as u16).sum::<u16>()
+}
+// 按 parent 指针加载上级代理商链（最多 upline_depth 层），infos 须依次为各层 AgentAccount；
+// 遇到环时停止
+fn load_upline<'info>(
+    s: &GameState,
+    game_key: Pubkey,
+    agent: &AgentAccount,
+    infos: &'info [AccountInfo<'info>],
+) -> Result<Vec<Account<'info, AgentAccount>>> {
+    let mut chain: Vec<Account<'info, AgentAccount>> = Vec::new();
+    let mut parent = agent.parent;
+    while parent != Pubkey::default() && chain.len() < s.upline_depth as usize {
+        if parent == agent.agent || chain.iter().any(|x| x.agent == parent) {
+            break;
+        }
+        let info = infos.get(chain.len()).ok_or(ErrorCode::InvalidUplineAccount)?;
+        let a = Account::<AgentAccount>::try_from(info)?;
+        let expected = Pubkey::create_program_address(
+            &[AGENT_SEED, game_key.as_ref(), parent.as_ref(), &[a.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| error!(ErrorCode::InvalidUplineAccount))?;
+        require_keys_eq!(info.key(), expected, ErrorCode::InvalidUplineAccount);
+        parent = a.parent;
+        chain.push(a);
+    }
+    Ok(chain)
+}
+// 自推广（玩家为链上任一代理商本人或其质押代付人）不计佣金；
+// 单一玩家在直属代理商推广量中占比超过 max_referral_share_bps 时该局不计佣金；
+// 直属代理商按 agent_commission_rate、各层上级按 upline_rates 分成，总费率按 commission_cap 封顶
+#[allow(clippy::too_many_arguments)]
 fn apply_agent_commission(
-    s: &mut GameState,
+    s: &GameState,
+    schedule: Option<&CommissionSchedule>,
+    agent: Option<&mut AgentAccount>,
+    upline: &mut [Account<'_, AgentAccount>],
+    stats: &mut PlayerStats,
     card: Option<u64>,
     total_bet: u64,
+    contribution: u64,
     payout: u64,
+    now: i64,
 ) -> Result<()> {
     let Some(card) = card else { return Ok(()); };
-    let a = s
-        .agents
-        .iter_mut()
-        .find(|x| x.is_active && x.room_card == card)
-        .ok_or(ErrorCode::InvalidRoomCard)?;
-    let net = payout as i128 - total_bet as i128;
-    let rate = s.commission_rate as i128;
+    require!(
+        schedule.is_some() || !s.tiered_commission,
+        ErrorCode::CommissionScheduleRequired
+    );
+    check_room_card_agent(agent.as_deref(), Some(card))?;
+    let a = agent.ok_or(ErrorCode::InvalidRoomCard)?;
+    if self_referral(stats.player, a) || upline.iter().any(|x| self_referral(stats.player, x)) {
+        return Ok(());
+    }
+    a.referred_volume = a
+        .referred_volume
+        .checked_add(total_bet)
+        .ok_or(ErrorCode::MathOverflow)?;
+    if let Some(c) = schedule {
+        roll_volume_period(a, c.volume_period, now);
+    }
+    a.period_volume = a
+        .period_volume
+        .checked_add(total_bet)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let mut player_volume = total_bet;
+    if stats.bound_agent == a.agent {
+        stats.bound_volume = stats
+            .bound_volume
+            .checked_add(total_bet)
+            .ok_or(ErrorCode::MathOverflow)?;
+        player_volume = stats.bound_volume;
+    }
+    if player_volume as u128 * 10_000
+        > a.referred_volume as u128 * s.max_referral_share_bps as u128
+    {
+        return Ok(());
+    }
+    // 划入 jackpot 的份额不归庄家，不参与计佣
+    let net = payout as i128 - (total_bet - contribution) as i128;
+    let mut budget = commission_cap(s.rtp_max_bps, s.jackpot_bps, s.keeper_fee_bps);
+    let rate = agent_commission_rate(s, schedule, a).min(budget);
+    budget -= rate;
+    accrue_commission(a, rate, net)?;
+    for (x, r) in upline.iter_mut().zip(s.upline_rates) {
+        let rate = r.min(budget);
+        budget -= rate;
+        // 已赎回的上级不再分成，其份额不转给更上层
+        if x.is_active && x.room_card > 0 {
+            accrue_commission(x, rate, net)?;
+        }
+    }
+    Ok(())
+}
+// 直属代理商的佣金率：单独设置的覆盖值优先，其次取阶梯中达到门槛的最高档，否则为 commission_rate
+fn agent_commission_rate(
+    s: &GameState,
+    schedule: Option<&CommissionSchedule>,
+    a: &AgentAccount,
+) -> u8 {
+    if let Some(rate) = a.commission_override {
+        return rate;
+    }
+    let Some(c) = schedule else { return s.commission_rate; };
+    let metric = match c.basis {
+        TierBasis::Stake => a.stake,
+        TierBasis::Volume => a.period_volume.max(a.prev_period_volume),
+    };
+    c.tiers[..c.tier_count as usize]
+        .iter()
+        .rev()
+        .find(|t| metric >= t.threshold)
+        .map_or(s.commission_rate, |t| t.rate)
+}
+// 推广量统计周期按 period 对齐滚动；跨过不止一个周期时上一周期推广量为 0
+fn roll_volume_period(a: &mut AgentAccount, period: i64, now: i64) {
+    if period <= 0 || now < a.period_start.saturating_add(period) {
+        return;
+    }
+    a.prev_period_volume = if now < a.period_start.saturating_add(period.saturating_mul(2)) {
+        a.period_volume
+    } else {
+        0
+    };
+    a.period_volume = 0;
+    a.period_start = now - now.rem_euclid(period);
+}
+// 玩家输钱按费率累积佣金；玩家赢钱按费率扣减，余额为 0 时跳过、不足时归零
+fn accrue_commission(a: &mut AgentAccount, rate: u8, net: i128) -> Result<()> {
     if rate == 0 {
         return Ok(());
     }
@@ -868,6 +4232,25 @@ fn apply_agent_commission(
     }
     Ok(())
 }
+// 由程序 PDA 签名创建账户（用于迁移时按需创建数量不定的账户）
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    target: &AccountInfo<'info>,
+    sys: &AccountInfo<'info>,
+    space: usize,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let lamports = Rent::get()?.minimum_balance(space);
+    let ix = anchor_lang::solana_program::system_instruction::create_account(
+        payer.key,
+        target.key,
+        lamports,
+        space as u64,
+        &crate::ID,
+    );
+    invoke_signed(&ix, &[payer.clone(), target.clone(), sys.clone()], &[seeds])?;
+    Ok(())
+}
 fn pool_transfer_signed<'info>(
     gs: &Account<'info, GameState>,
     tp: &Program<'info, Token>,
@@ -875,7 +4258,8 @@ fn pool_transfer_signed<'info>(
     to: &Account<'info, TokenAccount>,
     amount: u64,
 ) -> Result<()> {
-    let seeds = &[GAME_STATE_SEED, &[gs.bump]];
+    let id_seed = gs.id_seed();
+    let seeds = &[GAME_STATE_SEED, &id_seed, &[gs.bump]];
     let signer = &[&seeds[..]];
     let cpi = CpiContext::new_with_signer(
         tp.to_account_info(),
@@ -888,6 +4272,26 @@ fn pool_transfer_signed<'info>(
     );
     token::transfer(cpi, amount)
 }
+fn close_token_account_signed<'info>(
+    gs: &Account<'info, GameState>,
+    tp: &Program<'info, Token>,
+    account: &Account<'info, TokenAccount>,
+    destination: &Signer<'info>,
+) -> Result<()> {
+    let id_seed = gs.id_seed();
+    let seeds = &[GAME_STATE_SEED, &id_seed, &[gs.bump]];
+    let signer = &[&seeds[..]];
+    let cpi = CpiContext::new_with_signer(
+        tp.to_account_info(),
+        CloseAccount {
+            account: account.to_account_info(),
+            destination: destination.to_account_info(),
+            authority: gs.to_account_info(),
+        },
+        signer,
+    );
+    token::close_account(cpi)
+}
 fn pool_transfer_from_user<'info>(
     tp: &Program<'info, Token>,
     from: &Account<'info, TokenAccount>,
@@ -906,6 +4310,171 @@ fn pool_transfer_from_user<'info>(
     token::transfer(cpi, amount)
 }
 
+// 事件
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum ConfigChange {
+    CommissionRate(u8),
+    StakeThreshold(u64),
+    MinBet(u64),
+    Vrf { vrf: Pubkey, offset: u32 },
+    PlayTimeout(u64),
+    KeeperFee(u16),
+    RandomnessSource(RandomnessSource),
+    SeedCommitment { index: u64, hash: [u8; 32] },
+    PaymentToken { mint: Pubkey, pool_token_account: Pubkey },
+    PoolSynced(u64),
+    Paused(u8),
+    Guardian(Pubkey),
+    Role { role: Role, key: Pubkey },
+    OwnerProposed(Pubkey),
+    OwnerChanged(Pubkey),
+    ConfigDelay(u64),
+    RtpBounds { min_bps: u16, max_bps: u16 },
+    LineConfig { reels: u8, rows: u8, paylines: u8 },
+    BetLimits { max_bet_per_symbol: u64, max_bet_per_play: u64, max_exposure_bps: u16 },
+    JackpotAccount(Pubkey),
+    RebindCooldown(u64),
+    ReferralShare(u16),
+    UplineCommission { rates: [u8; 4], depth: u8 },
+    AgentCommission { agent: Pubkey, rate: Option<u8> },
+    CommissionSchedule {
+        basis: TierBasis,
+        volume_period: i64,
+        tiers: [CommissionTier; 4],
+        tier_count: u8,
+    },
+    VrfPool(Vec<Pubkey>),
+}
+#[event]
+pub struct ConfigChanged {
+    pub admin: Pubkey,
+    pub change: ConfigChange,
+}
+#[event]
+pub struct PlayResolved {
+    pub player: Pubkey,
+    pub bets: [u64; 6],
+    pub total_bet: u64,
+    pub spins: Vec<SpinRecord>,
+    pub doubles: u8,
+    pub multiplier: u8,
+    pub payout: u64,
+    pub room_card: Option<u64>,
+}
+#[event]
+pub struct PlayRequested {
+    pub pending_play: Pubkey,
+    pub player: Pubkey,
+    pub seq: u64,
+    pub bets: [u64; 6],
+    pub total_bet: u64,
+    pub room_card: Option<u64>,
+    pub source: RandomnessSource,
+    pub request_slot: u64,
+    pub config_version: u64,
+}
+#[event]
+pub struct ConfigQueued {
+    pub admin: Pubkey,
+    pub version: u64,
+    pub effective_slot: u64,
+    pub odds: Odds,
+    pub config_delay_slots: u64,
+}
+#[event]
+pub struct ConfigApplied {
+    pub version: u64,
+    pub odds: Odds,
+    pub config_delay_slots: u64,
+}
+#[event]
+pub struct PlaySettled {
+    pub pending_play: Pubkey,
+    pub player: Pubkey,
+    pub seq: u64,
+    pub bets: [u64; 6],
+    pub total_bet: u64,
+    pub spins: Vec<SpinRecord>,
+    pub doubles: u8,
+    pub multiplier: u8,
+    pub payout: u64,
+    pub room_card: Option<u64>,
+    pub keeper: Pubkey,
+    pub keeper_fee: u64,
+}
+#[event]
+pub struct PlayCancelled {
+    pub pending_play: Pubkey,
+    pub player: Pubkey,
+    pub seq: u64,
+    pub refund: u64,
+}
+#[event]
+pub struct AgentStaked {
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub total_stake: u64,
+    pub room_card: u64,
+}
+#[event]
+pub struct AgentRedeemed {
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub room_card: u64,
+}
+#[event]
+pub struct AgentMigrated {
+    pub agent: Pubkey,
+    pub stake: u64,
+    pub room_card: u64,
+}
+#[event]
+pub struct CommissionWithdrawn {
+    pub agent: Pubkey,
+    pub amount: u64,
+    pub total_pool: u64,
+}
+#[event]
+pub struct PoolWithdrawn {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub total_pool: u64,
+    pub closed: bool,
+}
+#[event]
+pub struct JackpotSeeded {
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub jackpot_total: u64,
+}
+#[event]
+pub struct JackpotAccrued {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub jackpot_total: u64,
+}
+#[event]
+pub struct JackpotWon {
+    pub player: Pubkey,
+    pub amount: u64,
+}
+#[event]
+pub struct JackpotWithdrawn {
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+#[event]
+pub struct RoomCardBound {
+    pub player: Pubkey,
+    pub agent: Pubkey,
+    pub room_card: u64,
+}
+#[event]
+pub struct AgentParentSet {
+    pub agent: Pubkey,
+    pub parent: Pubkey,
+}
+
 // 错误码
 #[error_code]
 pub enum ErrorCode {
@@ -936,6 +4505,803 @@ pub enum ErrorCode {
     #[msg("Invalid VRF owner")] InvalidVrfOwner,
     #[msg("Invalid VRF data")] InvalidVrfData,
     #[msg("VRF not updated")] VrfNotUpdated,
+    #[msg("Invalid VRF authority")] InvalidVrfAuthority,
+    #[msg("VRF request in flight")] VrfRequestInFlight,
+    #[msg("VRF request mismatch")] VrfRequestMismatch,
+    #[msg("Randomness source mismatch")] RandomnessSourceMismatch,
+    #[msg("Missing randomness account")] MissingRandomnessAccount,
+    #[msg("Commitment already bound")] CommitmentAlreadyBound,
+    #[msg("Commitment mismatch")] CommitmentMismatch,
+    #[msg("Invalid reveal")] InvalidReveal,
+    #[msg("Play not expired")] PlayNotExpired,
+    #[msg("Play already fulfilled")] PlayAlreadyFulfilled,
     #[msg("Player mismatch")] PlayerMismatch,
     #[msg("Player token mismatch")] PlayerTokenMismatch,
+    #[msg("Invalid keeper fee")] InvalidKeeperFee,
+    #[msg("Missing keeper token account")] MissingKeeperTokenAccount,
+    #[msg("Invalid settle batch")] InvalidSettleBatch,
+    #[msg("Invalid migration account")] InvalidMigrationAccount,
+    #[msg("Paused")] Paused,
+    #[msg("Invalid pause mask")] InvalidPauseMask,
+    #[msg("Not pending owner")] NotPendingOwner,
+    #[msg("Invalid config delay")] InvalidConfigDelay,
+    #[msg("Config not ready")] ConfigNotReady,
+    #[msg("Invalid RTP bounds")] InvalidRtpBounds,
+    #[msg("RTP out of bounds")] RtpOutOfBounds,
+    #[msg("Bet above maximum")] BetAboveMaximum,
+    #[msg("Exposure too high")] ExposureTooHigh,
+    #[msg("Invalid exposure limit")] InvalidExposureLimit,
+    #[msg("Missing game config")] MissingGameConfig,
+    #[msg("Invalid line config")] InvalidLineConfig,
+    #[msg("Line config locked")] LineConfigLocked,
+    #[msg("Invalid symbol roles")] InvalidSymbolRoles,
+    #[msg("Invalid auto spin config")] InvalidAutoSpin,
+    #[msg("Invalid jackpot config")] InvalidJackpotConfig,
+    #[msg("Missing jackpot account")] MissingJackpotAccount,
+    #[msg("Invalid jackpot account")] InvalidJackpotAccount,
+    #[msg("Room card differs from the player's binding")] RoomCardBindingMismatch,
+    #[msg("Room card rebind cooldown not elapsed")] RebindCooldown,
+    #[msg("Players cannot refer themselves")] SelfReferral,
+    #[msg("Commission rate would exceed the house edge")] CommissionExceedsEdge,
+    #[msg("Invalid referral share")] InvalidReferralShare,
+    #[msg("Invalid upline commission config")] InvalidUplineConfig,
+    #[msg("Invalid agent parent")] InvalidAgentParent,
+    #[msg("Invalid upline agent account")] InvalidUplineAccount,
+    #[msg("Invalid commission schedule")] InvalidCommissionSchedule,
+    #[msg("Commission schedule account required")] CommissionScheduleRequired,
+    #[msg("Invalid VRF pool")] InvalidVrfPool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 沿 next_seed 链确定性抽样，统计各符号频率
+    fn sample(w: &[u16; 6], n: u64) -> [u64; 6] {
+        let mut cur = hash(b"pick_symbol_stats").to_bytes();
+        let mut counts = [0u64; 6];
+        for c in 0..n {
+            cur = next_seed(cur, c);
+            counts[pick_symbol(cur, w).unwrap() as usize] += 1;
+        }
+        counts
+    }
+
+    fn assert_matches_weights(w: &[u16; 6], n: u64) {
+        let sum: u64 = w.iter().map(|x| *x as u64).sum();
+        let counts = sample(w, n);
+        for (i, c) in counts.iter().enumerate() {
+            let expected = n as f64 * w[i] as f64 / sum as f64;
+            let sigma = (expected * (1.0 - w[i] as f64 / sum as f64)).sqrt();
+            // 5σ 容差；链是确定性的，结果可复现
+            assert!(
+                (*c as f64 - expected).abs() <= 5.0 * sigma + 1.0,
+                "symbol {i}: got {c}, expected {expected:.0}"
+            );
+        }
+    }
+
+    #[test]
+    fn frequencies_match_default_weights() {
+        assert_matches_weights(&[2500, 2500, 250, 1600, 2150, 1000], 200_000);
+    }
+
+    #[test]
+    fn frequencies_match_skewed_weights() {
+        assert_matches_weights(&[1, 9_000, 1, 997, 1, 0], 200_000);
+    }
+
+    // 逐转轮权重：解析 RTP 与按 compute_total_payout 模拟的平均返还一致
+    #[test]
+    fn per_reel_rtp_matches_simulation() {
+        let odds = Odds {
+            reel_weights: [
+                [3000, 2000, 500, 1500, 2000, 1000],
+                [2000, 3000, 250, 1750, 2000, 1000],
+                [2500, 2500, 100, 1900, 2500, 500],
+            ],
+            payout_triple: [220, 180, 2000, 360, 450, 0],
+            payout_double: [65, 50, 100, 75, 85, 0],
+            max_auto_spins: 5,
+            layout: Layout::Classic,
+            roles: DEFAULT_SYMBOL_ROLES,
+            scatter_pays: [0; 4],
+            multipliers: DEFAULT_MULTIPLIERS,
+            trigger_count: 1,
+            jackpot_bps: 0,
+            jackpot_trigger: JackpotTrigger::Disabled,
+        };
+        let rtp = rtp_bps(&odds, &LineOdds::default()).unwrap();
+        let n = 100_000u64;
+        let bet = 1_000_000u64;
+        for sym in [0usize, 1, 4] {
+            let mut bets = [0u64; 6];
+            bets[sym] = bet;
+            let mut seed = hash(b"per_reel_rtp").to_bytes();
+            let mut paid = 0u128;
+            for c in 0..n {
+                seed = next_seed(seed, c);
+                paid += compute_total_payout(seed, &bets, &odds, &LineOdds::default())
+                    .unwrap()
+                    .payout as u128;
+            }
+            let simulated = (paid * 10_000 / (bet as u128 * n as u128)) as f64;
+            let expected = rtp[sym] as f64;
+            assert!(
+                (simulated - expected).abs() <= expected * 0.03,
+                "symbol {sym}: simulated {simulated}, analytic {expected}"
+            );
+        }
+    }
+
+    fn five_by_three() -> LineOdds {
+        LineOdds {
+            reels: 5,
+            rows: 3,
+            reel_weights: [[2500, 2500, 250, 1600, 2150, 1000]; MAX_REELS],
+            paylines: vec![[1; 5], [0; 5], [2; 5], [0, 1, 2, 1, 0], [2, 1, 0, 1, 2]],
+            line_pays: [
+                [20, 60, 200],
+                [15, 50, 150],
+                [500, 2000, 10_000],
+                [30, 100, 400],
+                [40, 120, 500],
+                [0, 0, 0],
+            ],
+        }
+    }
+
+    // 网格布局：解析 RTP 与模拟一致，且派彩只来自自左向右的连续命中
+    #[test]
+    fn line_rtp_matches_simulation() {
+        let lines = five_by_three();
+        validate_lines(&lines).unwrap();
+        let odds = Odds {
+            reel_weights: [[2500, 2500, 250, 1600, 2150, 1000]; REELS],
+            payout_triple: [0; 6],
+            payout_double: [0; 6],
+            max_auto_spins: 1,
+            layout: Layout::Lines,
+            roles: DEFAULT_SYMBOL_ROLES,
+            scatter_pays: [0; 4],
+            multipliers: DEFAULT_MULTIPLIERS,
+            trigger_count: 1,
+            jackpot_bps: 0,
+            jackpot_trigger: JackpotTrigger::Disabled,
+        };
+        let rtp = rtp_bps(&odds, &lines).unwrap();
+        let n = 60_000u64;
+        let bet = 1_000_000u64;
+        for sym in [0usize, 4] {
+            let mut bets = [0u64; 6];
+            bets[sym] = bet;
+            let mut seed = hash(b"line_rtp").to_bytes();
+            let mut paid = 0u128;
+            for c in 0..n {
+                seed = next_seed(seed, c);
+                let t = compute_total_payout(seed, &bets, &odds, &lines).unwrap();
+                assert_eq!((t.grid_reels, t.grid_rows), (5, 3));
+                assert!(t.spins.iter().all(|sp| sp.reels.len() == 15));
+                paid += t.payout as u128;
+            }
+            let simulated = (paid * 10_000 / (bet as u128 * n as u128)) as f64;
+            let expected = rtp[sym] as f64;
+            assert!(
+                (simulated - expected).abs() <= expected * 0.05,
+                "symbol {sym}: simulated {simulated}, analytic {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn line_payout_counts_left_to_right_runs() {
+        let lines = five_by_three();
+        let odds = Odds {
+            layout: Layout::Lines,
+            ..Default::default()
+        };
+        // 中间行：0 0 0 1 0 → 3 连；第一行：1 0 0 0 0 → 不中
+        let mut cells = [3u8; 15];
+        for (r, sym) in [0u8, 0, 0, 1, 0].iter().enumerate() {
+            cells[r * 3 + 1] = *sym;
+        }
+        cells[0] = 1;
+        for r in 1..5 {
+            cells[r * 3] = 0;
+        }
+        let wins = compute_spin_payout(&[100, 0, 0, 0, 0, 0], &cells, &odds, &lines).unwrap();
+        assert_eq!(wins[0], 20);
+        let wins = compute_spin_payout(&[0, 0, 0, 100, 0, 0], &cells, &odds, &lines).unwrap();
+        // 第三行全为 3：5 连
+        assert_eq!(wins[3], 400);
+    }
+
+    fn wild_scatter_odds() -> Odds {
+        Odds {
+            reel_weights: [[2500, 2500, 500, 1600, 1900, 1000]; REELS],
+            payout_triple: [220, 180, 0, 360, 0, 0],
+            payout_double: [40, 30, 0, 50, 0, 0],
+            max_auto_spins: 3,
+            layout: Layout::Classic,
+            roles: [
+                SymbolRole::Regular,
+                SymbolRole::Regular,
+                SymbolRole::Wild,
+                SymbolRole::Regular,
+                SymbolRole::Scatter,
+                SymbolRole::Multiplier,
+            ],
+            scatter_pays: [20, 300, 0, 0],
+            multipliers: DEFAULT_MULTIPLIERS,
+            trigger_count: 1,
+            jackpot_bps: 0,
+            jackpot_trigger: JackpotTrigger::Disabled,
+        }
+    }
+
+    // Wild 替代命中、Scatter 不论位置按总下注派彩、Multiplier 按角色触发自动转轮
+    #[test]
+    fn wild_and_scatter_follow_roles() {
+        let odds = wild_scatter_odds();
+        let l = LineOdds::default();
+        let bets = [100, 0, 0, 100, 0, 0];
+        // 0 + Wild + 0 → 三连
+        assert_eq!(compute_spin_payout(&bets, &[0, 2, 0], &odds, &l).unwrap()[0], 220);
+        // Wild + Wild + 3：符号 0 两连、符号 3 三连
+        let wins = compute_spin_payout(&bets, &[2, 2, 3], &odds, &l).unwrap();
+        assert_eq!((wins[0], wins[3]), (40, 360));
+        // 两个 Scatter：总下注 200 × 20%，计入 Scatter 符号
+        let wins = compute_spin_payout(&bets, &[4, 1, 4], &odds, &l).unwrap();
+        assert_eq!(wins, [0, 0, 0, 0, 40, 0]);
+        // 改变角色后，符号 5 不再触发自动转轮
+        let mut plain = odds;
+        plain.roles[5] = SymbolRole::Regular;
+        let mut seed = hash(b"roles").to_bytes();
+        for c in 0..2_000 {
+            seed = next_seed(seed, c);
+            let t = compute_total_payout(seed, &bets, &plain, &l).unwrap();
+            assert_eq!(t.spins.len(), 1);
+        }
+    }
+
+    #[test]
+    fn wild_scatter_rtp_matches_simulation() {
+        let odds = wild_scatter_odds();
+        let rtp = rtp_bps(&odds, &LineOdds::default()).unwrap();
+        assert_eq!((rtp[2], rtp[4], rtp[5]), (0, 0, 0));
+        let n = 100_000u64;
+        let bet = 1_000_000u64;
+        for sym in [0usize, 3] {
+            let mut bets = [0u64; 6];
+            bets[sym] = bet;
+            let mut seed = hash(b"wild_scatter_rtp").to_bytes();
+            let mut paid = 0u128;
+            for c in 0..n {
+                seed = next_seed(seed, c);
+                paid += compute_total_payout(seed, &bets, &odds, &LineOdds::default())
+                    .unwrap()
+                    .payout as u128;
+            }
+            let simulated = (paid * 10_000 / (bet as u128 * n as u128)) as f64;
+            let expected = rtp[sym] as f64;
+            assert!(
+                (simulated - expected).abs() <= expected * 0.03,
+                "symbol {sym}: simulated {simulated}, analytic {expected}"
+            );
+        }
+    }
+
+    // 自定义乘数阶梯与触发个数：转轮记录按阶梯取乘数，解析 RTP 与模拟一致
+    #[test]
+    fn multiplier_ladder_matches_simulation() {
+        let odds = Odds {
+            reel_weights: [[2500, 2500, 250, 1600, 1150, 2000]; REELS],
+            payout_triple: [220, 180, 2000, 360, 450, 0],
+            payout_double: [65, 50, 100, 75, 85, 0],
+            max_auto_spins: 3,
+            layout: Layout::Classic,
+            roles: DEFAULT_SYMBOL_ROLES,
+            scatter_pays: [0; 4],
+            multipliers: [1, 2, 3, 5, 10],
+            trigger_count: 2,
+            jackpot_bps: 0,
+            jackpot_trigger: JackpotTrigger::Disabled,
+        };
+        let rtp = rtp_bps(&odds, &LineOdds::default()).unwrap();
+        let n = 100_000u64;
+        let bet = 1_000_000u64;
+        let bets = [bet, 0, 0, 0, 0, 0];
+        let mut seed = hash(b"multiplier_ladder").to_bytes();
+        let mut paid = 0u128;
+        for c in 0..n {
+            seed = next_seed(seed, c);
+            let t = compute_total_payout(seed, &bets, &odds, &LineOdds::default()).unwrap();
+            assert!(t.spins.len() <= 4);
+            for (k, sp) in t.spins.iter().enumerate() {
+                assert_eq!(sp.multiplier, odds.multipliers[k]);
+                let doubles = sp.reels.iter().filter(|x| **x == 5).count();
+                assert!(k + 1 == t.spins.len() || doubles >= 2);
+            }
+            paid += t.payout as u128;
+        }
+        let simulated = (paid * 10_000 / (bet as u128 * n as u128)) as f64;
+        let expected = rtp[0] as f64;
+        assert!(
+            (simulated - expected).abs() <= expected * 0.03,
+            "simulated {simulated}, analytic {expected}"
+        );
+    }
+
+    #[test]
+    fn jackpot_combo_requires_full_line_and_min_bet() {
+        let mut odds = Odds {
+            jackpot_trigger: JackpotTrigger::Combo { symbol: 2, min_bet: 500 },
+            ..Default::default()
+        };
+        let spin = |reels: Vec<u8>| SpinTranscript {
+            spins: vec![SpinRecord { reels, ..Default::default() }],
+            ..Default::default()
+        };
+        let l = LineOdds::default();
+        let seed = [0u8; 32];
+        let hit = |bets, t: &SpinTranscript, o: &Odds| jackpot_hit(seed, bets, t, o, &l).unwrap();
+        assert!(hit(&[0, 0, 500, 0, 0, 0], &spin(vec![2, 2, 2]), &odds));
+        assert!(!hit(&[0, 0, 499, 0, 0, 0], &spin(vec![2, 2, 2]), &odds));
+        assert!(!hit(&[0, 0, 500, 0, 0, 0], &spin(vec![2, 2, 3]), &odds));
+        // 网格布局：任一赔付线贯穿全部转轮
+        let lines = five_by_three();
+        odds.layout = Layout::Lines;
+        let mut cells = vec![0u8; 15];
+        for r in 0..5 {
+            cells[r * 3 + [0, 1, 2, 1, 0][r]] = 2;
+        }
+        let t = spin(cells);
+        assert!(jackpot_hit(seed, &[0, 0, 500, 0, 0, 0], &t, &odds, &lines).unwrap());
+    }
+
+    #[test]
+    fn jackpot_draw_matches_configured_odds() {
+        let odds = Odds {
+            jackpot_trigger: JackpotTrigger::Draw { one_in: 100, min_bet: 1 },
+            ..Default::default()
+        };
+        let t = SpinTranscript::default();
+        let l = LineOdds::default();
+        let mut seed = hash(b"jackpot_draw").to_bytes();
+        let mut hits = 0u64;
+        for c in 0..100_000 {
+            seed = next_seed(seed, c);
+            if jackpot_hit(seed, &[1, 0, 0, 0, 0, 0], &t, &odds, &l).unwrap() {
+                hits += 1;
+            }
+        }
+        // 期望 1000 次，5σ ≈ 157
+        assert!((850..=1150).contains(&hits), "hits {hits}");
+        assert!(!jackpot_hit(seed, &[0; 6], &t, &odds, &l).unwrap());
+    }
+
+    #[test]
+    fn jackpot_share_counts_toward_rtp_and_commission_edge() {
+        let d = Pubkey::default();
+        let mut gs = GameState::new(d, 0, 0, d, d, 0);
+        let rtp = rtp_bps(&gs.odds(), &LineOdds::default()).unwrap();
+        let top = *rtp.iter().max().unwrap() as u16;
+        // 赔率 RTP + jackpot_bps 不得超过 rtp_max_bps
+        let fits = Odds { jackpot_bps: gs.rtp_max_bps - top, ..gs.odds() };
+        assert!(validate_rtp(&gs, &fits).is_ok());
+        let over = Odds { jackpot_bps: gs.rtp_max_bps - top + 1, ..gs.odds() };
+        assert!(validate_rtp(&gs, &over).is_err());
+        // 默认 rtp_max 88% + 佣金 10%：jackpot 1% 仍留有边际，2% 时恰好吃满 100% 被拒绝
+        let rate = total_commission_rate(gs.commission_rate, &gs.upline_rates, gs.upline_depth);
+        assert!(rate <= commission_cap(gs.rtp_max_bps, 100, 0) as u16);
+        assert!(rate > commission_cap(gs.rtp_max_bps, 200, 0) as u16);
+        // 排队中的 jackpot 比例同样计入预留
+        gs.queued_config = QueuedConfig {
+            pending: true,
+            odds: Odds { jackpot_bps: 200, ..gs.odds() },
+            ..Default::default()
+        };
+        assert_eq!(jackpot_bps_ceiling(&gs), 200);
+        assert!(rate > config_commission_cap(&gs));
+    }
+
+    #[test]
+    fn extreme_seeds_map_to_first_and_last_symbol() {
+        let w = [2500, 2500, 250, 1600, 2150, 1000];
+        assert_eq!(pick_symbol([0u8; 32], &w).unwrap(), 0);
+        assert_eq!(pick_symbol([0xffu8; 32], &w).unwrap(), 5);
+        assert!(pick_symbol([0u8; 32], &[0; 6]).is_err());
+    }
+
+    fn empty_stats() -> PlayerStats {
+        PlayerStats {
+            player: Pubkey::default(),
+            bump: 0,
+            total_wagered: 0,
+            total_won: 0,
+            play_count: 0,
+            biggest_win: 0,
+            room_card: 0,
+            bound_agent: Pubkey::default(),
+            bound_at: 0,
+            bound_volume: 0,
+            recent_head: 0,
+            recent: [PlayRecord::default(); RECENT_RESULTS],
+        }
+    }
+
+    #[test]
+    fn player_stats_accumulate_and_keep_recent_results() {
+        let mut st = empty_stats();
+        let n = RECENT_RESULTS as u64 + 3;
+        for i in 1..=n {
+            let t = SpinTranscript {
+                payout: i * 10,
+                jackpot: if i == 2 { 1_000 } else { 0 },
+                ..Default::default()
+            };
+            st.record(100, &t, i).unwrap();
+        }
+        assert_eq!(st.play_count, n);
+        assert_eq!(st.total_wagered, 100 * n);
+        assert_eq!(st.total_won, 10 * n * (n + 1) / 2 + 1_000);
+        assert_eq!(st.biggest_win, 1_020);
+        // 环形缓冲只保留最近 RECENT_RESULTS 局，head 指向最旧一条
+        let head = st.recent_head as usize;
+        assert_eq!(head, 3);
+        assert_eq!(st.recent[head].slot, 4);
+        assert_eq!(st.recent[head - 1].slot, n);
+        assert_eq!(st.recent[head - 1].payout, n * 10);
+    }
+
+    #[test]
+    fn room_card_binding_is_sticky_until_agent_redeems() {
+        let agent = |card: u64| AgentAccount {
+            agent: Pubkey::new_from_array([card as u8; 32]),
+            bump: 0,
+            stake: 1,
+            room_card: card,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::default(),
+            referred_volume: 0,
+            parent: Pubkey::default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        let a = agent(7);
+        let b = agent(8);
+        let mut st = PlayerStats {
+            player: Pubkey::new_unique(),
+            ..empty_stats()
+        };
+        // 未绑定且未带房卡：无归属
+        assert_eq!(resolve_room_card(&mut st, None, None, 0).unwrap(), None);
+        // 首次带房卡即绑定
+        assert_eq!(resolve_room_card(&mut st, Some(&a), Some(7), 5).unwrap(), Some(7));
+        assert_eq!((st.room_card, st.bound_agent, st.bound_at), (7, a.agent, 5));
+        // 省略房卡仍按绑定归属；换成其他代理商或房卡被拒绝
+        assert_eq!(resolve_room_card(&mut st, Some(&a), None, 6).unwrap(), Some(7));
+        assert!(resolve_room_card(&mut st, None, None, 6).is_err());
+        assert!(resolve_room_card(&mut st, Some(&b), Some(8), 6).is_err());
+        assert!(resolve_room_card(&mut st, Some(&a), Some(8), 6).is_err());
+        // 代理商赎回后绑定失效
+        let mut redeemed = agent(7);
+        redeemed.room_card = 0;
+        assert_eq!(resolve_room_card(&mut st, Some(&redeemed), None, 7).unwrap(), None);
+        assert_eq!(st.room_card, 0);
+        assert_eq!(resolve_room_card(&mut st, Some(&b), Some(8), 8).unwrap(), Some(8));
+        assert_eq!(st.bound_agent, b.agent);
+    }
+
+    #[test]
+    fn commission_skips_self_referral_and_dominant_wallets() {
+        // 全零数据即各字段默认值（含 8 字节 discriminator 占位）
+        let mut gs =
+            GameState::try_deserialize_unchecked(&mut &[0u8; 8 + GameState::SPACE][..]).unwrap();
+        gs.commission_rate = 10;
+        gs.rtp_max_bps = DEFAULT_RTP_MAX_BPS;
+        gs.max_referral_share_bps = 10_000;
+        let mut a = AgentAccount {
+            agent: Pubkey::new_unique(),
+            bump: 0,
+            stake: 1,
+            room_card: 7,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::new_unique(),
+            referred_volume: 0,
+            parent: Pubkey::default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        let player = |key: Pubkey| PlayerStats {
+            player: key,
+            ..empty_stats()
+        };
+        // 代理商本人与质押代付人不计佣金，也不计入推广量
+        for key in [a.agent, a.stake_funder] {
+            let mut st = player(key);
+            apply_agent_commission(
+                &gs,
+                None,
+                Some(&mut a),
+                &mut [],
+                &mut st,
+                Some(7),
+                1_000,
+                0,
+                0,
+                0,
+            )
+            .unwrap();
+            assert_eq!((a.commission, a.referred_volume), (0, 0));
+        }
+        let mut x = player(Pubkey::new_unique());
+        let mut y = player(Pubkey::new_unique());
+        x.bound_agent = a.agent;
+        y.bound_agent = a.agent;
+        apply_agent_commission(&gs, None, Some(&mut a), &mut [], &mut x, Some(7), 1_000, 0, 0, 0)
+            .unwrap();
+        assert_eq!(a.commission, 100);
+        // 单一玩家占比上限 60%：x 独占时不计佣金，y 加入后恢复
+        gs.max_referral_share_bps = 6_000;
+        apply_agent_commission(&gs, None, Some(&mut a), &mut [], &mut x, Some(7), 1_000, 0, 0, 0)
+            .unwrap();
+        assert_eq!(a.commission, 100);
+        apply_agent_commission(&gs, None, Some(&mut a), &mut [], &mut y, Some(7), 2_000, 0, 0, 0)
+            .unwrap();
+        assert_eq!(a.commission, 300);
+        assert_eq!((a.referred_volume, x.bound_volume, y.bound_volume), (4_000, 2_000, 2_000));
+        // 佣金率按 RTP 上限封顶：rate% + rtp 必须严格低于 100%
+        assert_eq!(commission_cap(8_800, 0, 0), 11);
+        assert_eq!(commission_cap(9_000, 0, 0), 9);
+        assert_eq!(commission_cap(MAX_RTP_BPS, 0, 0), 0);
+        // jackpot 与 keeper 奖励同样占用庄家优势：88% + 1% + 1% 后只剩 9%
+        assert_eq!(commission_cap(8_800, 100, 100), 9);
+        assert_eq!(commission_cap(8_800, 0, 100), 10);
+        gs.rtp_max_bps = 9_500;
+        apply_agent_commission(&gs, None, Some(&mut a), &mut [], &mut y, Some(7), 1_000, 0, 0, 0)
+            .unwrap();
+        assert_eq!(a.commission, 340);
+        // 划入 jackpot 的份额不计佣金，推广量仍按全额下注计
+        apply_agent_commission(&gs, None, Some(&mut a), &mut [], &mut x, Some(7), 1_000, 500, 0, 0)
+            .unwrap();
+        assert_eq!((a.commission, a.referred_volume), (360, 6_000));
+    }
+
+    #[test]
+    fn commission_tiers_by_stake_or_trailing_volume() {
+        let mut gs =
+            GameState::try_deserialize_unchecked(&mut &[0u8; 8 + GameState::SPACE][..]).unwrap();
+        gs.commission_rate = 3;
+        gs.rtp_max_bps = DEFAULT_RTP_MAX_BPS;
+        gs.max_referral_share_bps = 10_000;
+        gs.tiered_commission = true;
+        let tier = |threshold: u64, rate: u8| CommissionTier { threshold, rate };
+        let mut c = CommissionSchedule {
+            game_state: Pubkey::default(),
+            bump: 0,
+            basis: TierBasis::Stake,
+            volume_period: 100,
+            tiers: [tier(1_000, 5), tier(5_000, 10), tier(0, 0), tier(0, 0)],
+            tier_count: 2,
+        };
+        let mut a = AgentAccount {
+            agent: Pubkey::new_unique(),
+            bump: 0,
+            stake: 999,
+            room_card: 7,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::default(),
+            referred_volume: 0,
+            parent: Pubkey::default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        // 低于所有门槛取 commission_rate，达到门槛取最高一档
+        assert_eq!(agent_commission_rate(&gs, Some(&c), &a), 3);
+        a.stake = 5_000;
+        assert_eq!(agent_commission_rate(&gs, Some(&c), &a), 10);
+        assert_eq!(agent_commission_rate(&gs, None, &a), 3);
+        // 单独设置的佣金率优先于阶梯
+        a.commission_override = Some(1);
+        assert_eq!(agent_commission_rate(&gs, Some(&c), &a), 1);
+        a.commission_override = None;
+        // 已创建阶梯时计佣必须传入
+        let mut st = PlayerStats {
+            player: Pubkey::new_unique(),
+            ..empty_stats()
+        };
+        assert!(
+            apply_agent_commission(&gs, None, Some(&mut a), &mut [], &mut st, Some(7), 1, 0, 0, 0)
+                .is_err()
+        );
+        // 按推广量分档：取当前与上一统计周期的较大者，跨过两个周期后清零
+        c.basis = TierBasis::Volume;
+        apply_agent_commission(
+            &gs,
+            Some(&c),
+            Some(&mut a),
+            &mut [],
+            &mut st,
+            Some(7),
+            1_000,
+            0,
+            0,
+            50,
+        )
+        .unwrap();
+        assert_eq!(a.commission, 50);
+        apply_agent_commission(
+            &gs,
+            Some(&c),
+            Some(&mut a),
+            &mut [],
+            &mut st,
+            Some(7),
+            4_000,
+            0,
+            0,
+            60,
+        )
+        .unwrap();
+        assert_eq!(a.commission, 450);
+        apply_agent_commission(
+            &gs,
+            Some(&c),
+            Some(&mut a),
+            &mut [],
+            &mut st,
+            Some(7),
+            100,
+            0,
+            0,
+            150,
+        )
+        .unwrap();
+        assert_eq!((a.period_start, a.period_volume, a.prev_period_volume), (100, 100, 5_000));
+        assert_eq!(a.commission, 460);
+        apply_agent_commission(
+            &gs,
+            Some(&c),
+            Some(&mut a),
+            &mut [],
+            &mut st,
+            Some(7),
+            100,
+            0,
+            0,
+            420,
+        )
+        .unwrap();
+        assert_eq!((a.period_start, a.period_volume, a.prev_period_volume), (400, 100, 0));
+        assert_eq!(a.commission, 463);
+    }
+
+    #[test]
+    fn upline_commission_walks_chain_within_cap() {
+        let mut gs =
+            GameState::try_deserialize_unchecked(&mut &[0u8; 8 + GameState::SPACE][..]).unwrap();
+        gs.commission_rate = 5;
+        gs.upline_rates = [3, 2, 1, 0];
+        gs.upline_depth = 2;
+        gs.rtp_max_bps = DEFAULT_RTP_MAX_BPS;
+        gs.max_referral_share_bps = 10_000;
+        let game_key = Pubkey::new_unique();
+        let keys: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        // keys[0] 为直属代理商，逐级向上 keys[1]、keys[2]、keys[3]
+        let agent = |i: usize| AgentAccount {
+            agent: keys[i],
+            bump: Pubkey::find_program_address(
+                &[AGENT_SEED, game_key.as_ref(), keys[i].as_ref()],
+                &crate::ID,
+            )
+            .1,
+            stake: 1,
+            room_card: 7 + i as u64,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::default(),
+            referred_volume: 0,
+            parent: keys.get(i + 1).copied().unwrap_or_default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        let addrs: Vec<Pubkey> = (1..4)
+            .map(|i| {
+                Pubkey::find_program_address(&[AGENT_SEED, game_key.as_ref(), keys[i].as_ref()], &crate::ID).0
+            })
+            .collect();
+        let mut lamports = [1u64; 3];
+        let mut data: Vec<Vec<u8>> = (1..4)
+            .map(|i| {
+                let mut d = Vec::new();
+                agent(i).try_serialize(&mut d).unwrap();
+                d
+            })
+            .collect();
+        let infos: Vec<AccountInfo> = addrs
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((k, l), d)| AccountInfo::new(k, false, true, l, d, &crate::ID, false, 0))
+            .collect();
+        let mut direct = agent(0);
+        // 只向上加载 upline_depth 层
+        let mut upline = load_upline(&gs, game_key, &direct, &infos).unwrap();
+        assert_eq!(upline.len(), 2);
+        let mut st = PlayerStats {
+            player: Pubkey::new_unique(),
+            ..empty_stats()
+        };
+        apply_agent_commission(
+            &gs,
+            None,
+            Some(&mut direct),
+            &mut upline,
+            &mut st,
+            Some(7),
+            1_000,
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!((direct.commission, upline[0].commission, upline[1].commission), (50, 30, 20));
+        // 总费率按 RTP 上限封顶（92% → 7%），超出部分从最上层开始削减
+        gs.rtp_max_bps = 9_200;
+        apply_agent_commission(
+            &gs,
+            None,
+            Some(&mut direct),
+            &mut upline,
+            &mut st,
+            Some(7),
+            1_000,
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!((direct.commission, upline[0].commission, upline[1].commission), (100, 50, 20));
+        // 玩家是链上任一代理商本人时整局不计佣金
+        let mut own = PlayerStats {
+            player: keys[2],
+            ..empty_stats()
+        };
+        apply_agent_commission(
+            &gs,
+            None,
+            Some(&mut direct),
+            &mut upline,
+            &mut own,
+            Some(7),
+            1_000,
+            0,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!((direct.commission, upline[0].commission, upline[1].commission), (100, 50, 20));
+        // 上级账户地址不符被拒绝
+        assert!(load_upline(&gs, game_key, &direct, &infos[1..]).is_err());
+    }
 }
@@ -0,0 +1,151 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn agent_parent_links_and_upline_rates_are_validated() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let agents = [Keypair::new(), Keypair::new()];
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+
+    for who in [owner.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let mut agent_accounts = Vec::new();
+    for (i, agent) in agents.iter().enumerate() {
+        let (agent_account, agent_bump) = Pubkey::find_program_address(
+            &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+            &program_id,
+        );
+        let agent_state = slot_machine::AgentAccount {
+            agent: agent.pubkey(),
+            bump: agent_bump,
+            stake: 1_000_000,
+            room_card: 10_000 + i as u64,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::default(),
+            referred_volume: 0,
+            parent: Pubkey::default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        add_program_account(
+            &mut program_test,
+            agent_account,
+            slot_machine::AgentAccount::SPACE,
+            &agent_state,
+        );
+        agent_accounts.push(agent_account);
+    }
+
+    let mut state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    state.commission_rate = 5;
+    state.rtp_max_bps = 8_800;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let parent_ix = |agent_account: Pubkey, parent_account: Option<Pubkey>| {
+        solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::SetAgentParent {
+                game_state,
+                agent_account,
+                parent_account,
+                owner: owner.pubkey(),
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::SetAgentParent {}.data(),
+        }
+    };
+    let upline_ix = |rates: [u8; 4], depth: u8| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetUplineCommission { rates, depth }.data(),
+    };
+    let read_agent = |data: Vec<u8>| {
+        let mut slice: &[u8] = &data;
+        slot_machine::AgentAccount::try_deserialize(&mut slice).unwrap()
+    };
+
+    // 代理商不能以自己为上级
+    let tx = Transaction::new_signed_with_payer(
+        &[parent_ix(agent_accounts[0], Some(agent_accounts[0]))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[parent_ix(agent_accounts[0], Some(agent_accounts[1]))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(agent_accounts[0]).await.unwrap().unwrap();
+    assert_eq!(read_agent(acc.data).parent, agents[1].pubkey());
+
+    // 互为上级的环被拒绝
+    let tx = Transaction::new_signed_with_payer(
+        &[parent_ix(agent_accounts[1], Some(agent_accounts[0]))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // RTP 上限 88% 时总佣金不得超过 11%：直属 5% + 3% + 2% 可行，5% + 5% + 5% 超出
+    let tx = Transaction::new_signed_with_payer(
+        &[upline_ix([5, 5, 0, 0], 2)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[upline_ix([3, 2, 0, 0], 5)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[upline_ix([3, 2, 0, 0], 2)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut slice: &[u8] = &acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut slice).unwrap();
+    assert_eq!(state.upline_rates, [3, 2, 0, 0]);
+    assert_eq!(state.upline_depth, 2);
+}
@@ -0,0 +1,192 @@
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn auto_spin_ladder_is_validated_and_snapshotted() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
+    let (commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &0u64.to_le_bytes()],
+        &program_id,
+    );
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 100_000_000);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.total_pool = 100_000_000;
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seed = [42u8; 32];
+    let post_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::PostSeedCommitment {
+            game_state,
+            commitment,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::PostSeedCommitment {
+            seed_hash: hash(&house_seed).to_bytes(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let request_ix = |bets: [u64; 6]| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RequestPlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+            oracle_queue: None,
+            queue_authority: None,
+            data_buffer: None,
+            permission: None,
+            escrow: None,
+            payer_wallet: None,
+            recent_blockhashes: None,
+            program_state: None,
+            switchboard_program: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            game_config: None,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [9u8; 32],
+            switchboard_state_bump: 0,
+            permission_bump: 0,
+        }
+        .data(),
+    };
+
+    let auto_spin_ix = |multipliers: [u8; 5], max_auto_spins: u8, trigger_count: u8| {
+        solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::SetOdds {
+                game_state,
+                game_config: None,
+                owner: owner.pubkey(),
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::SetAutoSpin {
+                multipliers,
+                max_auto_spins,
+                trigger_count,
+            }
+            .data(),
+        }
+    };
+
+    // 乘数不可为 0、次数不超过 4、触发个数至少为 1
+    for (multipliers, max_auto_spins, trigger_count) in [
+        ([1, 0, 3, 5, 10], 4, 1),
+        ([1, 2, 3, 5, 10], 5, 1),
+        ([1, 2, 3, 5, 10], 4, 0),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[auto_spin_ix(multipliers, max_auto_spins, trigger_count)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &owner],
+            context.last_blockhash,
+        );
+        assert!(context.banks_client.process_transaction(tx).await.is_err());
+    }
+
+    // 阶梯过陡，RTP 超过 100%
+    let tx = Transaction::new_signed_with_payer(
+        &[auto_spin_ix([1, 20, 50, 100, 200], 4, 1)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // 延迟为 0，立即生效
+    let tx = Transaction::new_signed_with_payer(
+        &[auto_spin_ix([1, 2, 3, 5, 10], 4, 2)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.multipliers, [1, 2, 3, 5, 10]);
+    assert_eq!(state.max_auto_spins, 4);
+    assert_eq!(state.trigger_count, 2);
+    assert_eq!(state.config_version, 1);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix([100_000, 0, 0, 0, 0, 0])],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap().unwrap();
+    let mut pending_slice: &[u8] = &pending_acc.data;
+    let pending = slot_machine::PendingPlay::try_deserialize(&mut pending_slice).unwrap();
+    assert_eq!(pending.odds.multipliers, [1, 2, 3, 5, 10]);
+    assert_eq!(pending.odds.trigger_count, 2);
+    assert_eq!(pending.config_version, 1);
+}
@@ -0,0 +1,203 @@
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    account::Account as SolanaAccount,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn request_play_enforces_bet_limits_and_exposure() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
+    let (commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &0u64.to_le_bytes()],
+        &program_id,
+    );
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 100_000_000);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.total_pool = 100_000_000;
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    state.max_bet_per_play = 500_000;
+    state.max_exposure_bps = 1_000;
+    state.max_auto_spins = 1;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seed = [42u8; 32];
+    let post_ix = |commitment: Pubkey| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::PostSeedCommitment {
+            game_state,
+            commitment,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::PostSeedCommitment {
+            seed_hash: hash(&house_seed).to_bytes(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix(commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let request_ix = |bets: [u64; 6], pending_play: Pubkey, commitment: Pubkey| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RequestPlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+            oracle_queue: None,
+            queue_authority: None,
+            data_buffer: None,
+            permission: None,
+            escrow: None,
+            payer_wallet: None,
+            recent_blockhashes: None,
+            program_state: None,
+            switchboard_program: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            game_config: None,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [9u8; 32],
+            switchboard_state_bump: 0,
+            permission_bump: 0,
+        }
+        .data(),
+    };
+
+    // 超过单局上限 500_000
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix([300_000, 300_000, 0, 0, 0, 0], pending_play, commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // 符号 2 最坏派彩 = (1×100 + 2×2000)% × 400_000 = 16_400_000，超过奖池 10%
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix([0, 0, 400_000, 0, 0, 0], pending_play, commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // 符号 0 最坏派彩 = (1×65 + 2×220)% × 400_000 = 2_020_000，允许
+    let bet: u64 = 400_000;
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix([bet, 0, 0, 0, 0, 0], pending_play, commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap().unwrap();
+    let mut pending_slice: &[u8] = &pending_acc.data;
+    let pending = slot_machine::PendingPlay::try_deserialize(&mut pending_slice).unwrap();
+    assert_eq!(pending.total_bet, bet);
+
+    let player_acc = context.banks_client.get_account(player_token_account).await.unwrap().unwrap();
+    let player_token = TokenAccount::unpack(&player_acc.data).unwrap();
+    assert_eq!(player_token.amount, player_amount_before - bet);
+
+    // 未结算局的最坏派彩计入负债，敞口上限按扣除负债后的奖池计算
+    assert_eq!(pending.max_payout, 2_020_000);
+    let read_state = |acc: SolanaAccount| {
+        slot_machine::GameState::try_deserialize(&mut acc.data.as_slice()).unwrap()
+    };
+    let state = read_state(context.banks_client.get_account(game_state).await.unwrap().unwrap());
+    assert_eq!((state.total_pool, state.outstanding_liability), (100_400_000, 2_020_000));
+
+    let second_pending = pending_play_address(&program_id, &game_state, &player.pubkey(), 1);
+    let (second_commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &1u64.to_le_bytes()],
+        &program_id,
+    );
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix(second_commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // 符号 2 下注 242_000 最坏派彩 9_922_000：低于奖池 10%（10_040_000），
+    // 但超过扣除负债后的上限 (100_400_000 - 2_020_000) × 10% = 9_838_000
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix([0, 0, 242_000, 0, 0, 0], second_pending, second_commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix([0, 0, 200_000, 0, 0, 0], second_pending, second_commitment)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let state = read_state(context.banks_client.get_account(game_state).await.unwrap().unwrap());
+    assert_eq!(state.outstanding_liability, 2_020_000 + 8_200_000);
+}
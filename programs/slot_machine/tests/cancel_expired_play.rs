@@ -0,0 +1,209 @@
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn cancel_expired_play_refunds_bet_and_releases_pending_play() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
+    let (commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &0u64.to_le_bytes()],
+        &program_id,
+    );
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 0);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    state.play_timeout_slots = 100;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seed = [42u8; 32];
+    let post_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::PostSeedCommitment {
+            game_state,
+            commitment,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::PostSeedCommitment {
+            seed_hash: hash(&house_seed).to_bytes(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let bet: u64 = 1_000_000;
+    let bets: [u64; 6] = [bet, 0, 0, 0, 0, 0];
+    let request_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RequestPlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+            oracle_queue: None,
+            queue_authority: None,
+            data_buffer: None,
+            permission: None,
+            escrow: None,
+            payer_wallet: None,
+            recent_blockhashes: None,
+            program_state: None,
+            switchboard_program: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            game_config: None,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [9u8; 32],
+            switchboard_state_bump: 0,
+            permission_bump: 0,
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let cancel_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::CancelExpiredPlay {
+            game_state,
+            player_profile,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::CancelExpiredPlay {}.data(),
+    };
+
+    // 未到超时 slot 不允许退款
+    let tx = Transaction::new_signed_with_payer(
+        &[cancel_ix.clone()],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap().unwrap();
+    let mut pending_slice: &[u8] = &pending_acc.data;
+    let pending = slot_machine::PendingPlay::try_deserialize(&mut pending_slice).unwrap();
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let state = slot_machine::GameState::try_deserialize(&mut state_acc.data.as_slice()).unwrap();
+    assert!(pending.max_payout > 0);
+    assert_eq!(state.outstanding_liability, pending.max_payout);
+    context.warp_to_slot(pending.request_slot + 101).unwrap();
+    context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+
+    let player_lamports_before = context
+        .banks_client
+        .get_account(player.pubkey())
+        .await
+        .unwrap()
+        .unwrap()
+        .lamports;
+    let tx = Transaction::new_signed_with_payer(
+        &[cancel_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap();
+    assert!(pending_acc.is_none());
+    let player_lamports_after = context
+        .banks_client
+        .get_account(player.pubkey())
+        .await
+        .unwrap()
+        .unwrap()
+        .lamports;
+    assert!(player_lamports_after > player_lamports_before);
+
+    let player_acc = context.banks_client.get_account(player_token_account).await.unwrap().unwrap();
+    let player_token = TokenAccount::unpack(&player_acc.data).unwrap();
+    assert_eq!(player_token.amount, player_amount_before);
+
+    let pool_acc = context.banks_client.get_account(pool_token_account).await.unwrap().unwrap();
+    let pool_token = TokenAccount::unpack(&pool_acc.data).unwrap();
+    assert_eq!(pool_token.amount, 0);
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.total_pool, 0);
+    // 退款后释放该局计入的未结算负债
+    assert_eq!(state.outstanding_liability, 0);
+}
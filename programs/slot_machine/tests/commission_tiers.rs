@@ -0,0 +1,171 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn commission_schedule_and_agent_overrides_are_validated() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let agents = [Keypair::new()];
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (commission_schedule, _) =
+        Pubkey::find_program_address(&[b"commission_schedule", game_state.as_ref()], &program_id);
+
+    for who in [owner.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let mut agent_accounts = Vec::new();
+    for (i, agent) in agents.iter().enumerate() {
+        let (agent_account, agent_bump) = Pubkey::find_program_address(
+            &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+            &program_id,
+        );
+        let agent_state = slot_machine::AgentAccount {
+            agent: agent.pubkey(),
+            bump: agent_bump,
+            stake: 1_000_000,
+            room_card: 10_000 + i as u64,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::default(),
+            referred_volume: 0,
+            parent: Pubkey::default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        add_program_account(
+            &mut program_test,
+            agent_account,
+            slot_machine::AgentAccount::SPACE,
+            &agent_state,
+        );
+        agent_accounts.push(agent_account);
+    }
+
+    let mut state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    state.commission_rate = 5;
+    state.rtp_max_bps = 8_800;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let schedule_ix = |tiers: [slot_machine::CommissionTier; 4], tier_count: u8| {
+        solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::SetCommissionSchedule {
+                game_state,
+                commission_schedule,
+                owner: owner.pubkey(),
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::SetCommissionSchedule {
+                basis: slot_machine::TierBasis::Stake,
+                volume_period: 0,
+                tiers,
+                tier_count,
+            }
+            .data(),
+        }
+    };
+    let override_ix = |rate: Option<u8>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetAgentCommission {
+            game_state,
+            agent_account: agent_accounts[0],
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetAgentCommission { rate }.data(),
+    };
+    let tier = |threshold: u64, rate: u8| slot_machine::CommissionTier { threshold, rate };
+
+    let init_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::InitCommissionSchedule {
+            game_state,
+            commission_schedule,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::InitCommissionSchedule {}.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[init_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut slice: &[u8] = &acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut slice).unwrap();
+    assert!(state.tiered_commission);
+
+    // 门槛须严格递增；RTP 上限 88% 时单档费率不得超过 11%
+    for (tiers, count) in [
+        ([tier(5_000_000, 5), tier(1_000_000, 10), tier(0, 0), tier(0, 0)], 2),
+        ([tier(1_000_000, 5), tier(5_000_000, 12), tier(0, 0), tier(0, 0)], 2),
+        ([tier(1_000_000, 5); 4], 5),
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[schedule_ix(tiers, count)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &owner],
+            context.last_blockhash,
+        );
+        assert!(context.banks_client.process_transaction(tx).await.is_err());
+    }
+    let tx = Transaction::new_signed_with_payer(
+        &[schedule_ix([tier(1_000_000, 5), tier(5_000_000, 10), tier(0, 0), tier(0, 0)], 2)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(commission_schedule).await.unwrap().unwrap();
+    let mut slice: &[u8] = &acc.data;
+    let schedule = slot_machine::CommissionSchedule::try_deserialize(&mut slice).unwrap();
+    assert_eq!(schedule.tier_count, 2);
+    assert_eq!((schedule.tiers[1].threshold, schedule.tiers[1].rate), (5_000_000, 10));
+
+    // 单独设置的佣金率同样受上限约束
+    let tx = Transaction::new_signed_with_payer(
+        &[override_ix(Some(12))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[override_ix(Some(8))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(agent_accounts[0]).await.unwrap().unwrap();
+    let mut slice: &[u8] = &acc.data;
+    let agent = slot_machine::AgentAccount::try_deserialize(&mut slice).unwrap();
+    assert_eq!(agent.commission_override, Some(8));
+}
@@ -0,0 +1,228 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn commit_reveal_settles_only_with_matching_reveal() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+    let keeper_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
+    let (commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &0u64.to_le_bytes()],
+        &program_id,
+    );
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 0);
+    add_token_account(&mut program_test, keeper_token_account, mint, Pubkey::new_unique(), 0);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    state.keeper_fee_bps = 100;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seed = [42u8; 32];
+    let post_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::PostSeedCommitment {
+            game_state,
+            commitment,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::PostSeedCommitment {
+            seed_hash: hash(&house_seed).to_bytes(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let bet: u64 = 1_000_000;
+    let bets: [u64; 6] = [bet, 0, 0, 0, 0, 0];
+    let request_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RequestPlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+            oracle_queue: None,
+            queue_authority: None,
+            data_buffer: None,
+            permission: None,
+            escrow: None,
+            payer_wallet: None,
+            recent_blockhashes: None,
+            program_state: None,
+            switchboard_program: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            game_config: None,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [9u8; 32],
+            switchboard_state_bump: 0,
+            permission_bump: 0,
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let commitment_acc = context.banks_client.get_account(commitment).await.unwrap().unwrap();
+    let mut commitment_slice: &[u8] = &commitment_acc.data;
+    let c = slot_machine::SeedCommitment::try_deserialize(&mut commitment_slice).unwrap();
+    assert_eq!(c.pending_play, pending_play);
+    assert!(!c.revealed);
+
+    let settle_ix = |reveal: [u8; 32]| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SettlePlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            keeper: context.payer.pubkey(),
+            keeper_token_account: Some(keeper_token_account),
+            token_program: spl_token::id(),
+            vrf: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            jackpot_token_account: None,
+            system_program: system_program::id(),
+            commission_schedule: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SettlePlay { reveal: Some(reveal) }.data(),
+    };
+
+    let tx = Transaction::new_signed_with_payer(
+        &[settle_ix([1u8; 32])],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[settle_ix(house_seed)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction_with_metadata(tx).await.unwrap();
+    result.result.unwrap();
+    let metadata = result.metadata.unwrap();
+    // PlaySettled 事件以 "Program data: <base64>" 形式写入日志
+    assert!(metadata.log_messages.iter().any(|l| l.starts_with("Program data: ")));
+    // 支付 keeper 奖励的代币 CPI 之后仍须返回本程序写入的 SpinTranscript
+    let return_data = metadata.return_data.unwrap();
+    assert_eq!(return_data.program_id, program_id);
+    let transcript = slot_machine::SpinTranscript::try_from_slice(&return_data.data).unwrap();
+    assert!(!transcript.spins.is_empty());
+    assert_eq!(transcript.payout, 0);
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap();
+    assert!(pending_acc.is_none());
+
+    let profile_acc = context.banks_client.get_account(player_profile).await.unwrap().unwrap();
+    let mut profile_slice: &[u8] = &profile_acc.data;
+    let profile = slot_machine::PlayerProfile::try_deserialize(&mut profile_slice).unwrap();
+    assert_eq!(profile.player, player.pubkey());
+    assert_eq!(profile.next_play_seq, 1);
+    assert_eq!(profile.open_plays, 0);
+    assert_eq!(profile.last_result.total_bet, bet);
+    assert_eq!(profile.last_result.spin_count as usize, transcript.spins.len());
+    assert_eq!((profile.last_result.grid_reels, profile.last_result.grid_rows), (3, 1));
+    assert_eq!(profile.last_result.reels[0][..3], transcript.spins[0].reels[..]);
+
+    let commitment_acc = context.banks_client.get_account(commitment).await.unwrap().unwrap();
+    let mut commitment_slice: &[u8] = &commitment_acc.data;
+    let c = slot_machine::SeedCommitment::try_deserialize(&mut commitment_slice).unwrap();
+    assert!(c.revealed);
+    assert_eq!(c.seed, house_seed);
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.commit_posted, 1);
+    assert_eq!(state.commit_bound, 1);
+    // 非玩家本人结算：keeper 奖励 1% 由奖池承担
+    let fee = bet / 100;
+    assert_eq!(state.total_pool, bet - fee);
+
+    let pool_acc = context.banks_client.get_account(pool_token_account).await.unwrap().unwrap();
+    let pool_token = TokenAccount::unpack(&pool_acc.data).unwrap();
+    assert_eq!(pool_token.amount, bet - fee);
+
+    let keeper_acc = context.banks_client.get_account(keeper_token_account).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&keeper_acc.data).unwrap().amount, fee);
+}
@@ -0,0 +1,286 @@
+// 集成测试共用的 ProgramTest 搭建：处理器、SPL 账户打包、常用 PDA 与 GameState 夹具
+#![allow(dead_code)]
+
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountSerialize, Discriminator};
+use solana_program::{entrypoint::ProgramResult, program_option::COption, program_pack::Pack};
+use solana_program_test::*;
+use solana_sdk::{account::Account as SolanaAccount, system_program};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+pub fn slot_machine_process<'a, 'b, 'c, 'd>(
+    program_id: &'a Pubkey,
+    accounts: &'b [AccountInfo<'c>],
+    data: &'d [u8],
+) -> ProgramResult {
+    let accounts: &'c [AccountInfo<'c>] = unsafe { std::mem::transmute(accounts) };
+    slot_machine::entry(program_id, accounts, data)
+}
+
+// 加载 slot_machine 与 spl_token 的 ProgramTest
+pub fn new_program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new(
+        "slot_machine",
+        slot_machine::id(),
+        processor!(slot_machine_process),
+    );
+    program_test.add_program(
+        "spl_token",
+        spl_token::id(),
+        processor!(spl_token::processor::Processor::process),
+    );
+    program_test
+}
+
+pub fn pack_mint(mint_authority: Pubkey, decimals: u8) -> Vec<u8> {
+    let mint = Mint {
+        mint_authority: COption::Some(mint_authority),
+        supply: 0,
+        decimals,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    };
+    let mut data = vec![0u8; Mint::LEN];
+    Mint::pack(mint, &mut data).unwrap();
+    data
+}
+
+pub fn pack_token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
+    let token = TokenAccount {
+        mint,
+        owner,
+        amount,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    };
+    let mut data = vec![0u8; TokenAccount::LEN];
+    TokenAccount::pack(token, &mut data).unwrap();
+    data
+}
+
+pub fn add_wallet(program_test: &mut ProgramTest, address: Pubkey) {
+    program_test.add_account(
+        address,
+        SolanaAccount {
+            lamports: 5_000_000_000,
+            data: vec![],
+            owner: system_program::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn add_mint(program_test: &mut ProgramTest, address: Pubkey) {
+    program_test.add_account(
+        address,
+        SolanaAccount {
+            lamports: 1_000_000_000,
+            data: pack_mint(Pubkey::new_unique(), 6),
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn add_token_account(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+) {
+    program_test.add_account(
+        address,
+        SolanaAccount {
+            lamports: 1_000_000_000,
+            data: pack_token_account(mint, owner, amount),
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+// 以 space 字节（不含 discriminator）写入程序账户
+pub fn add_program_account<T: AccountSerialize>(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    space: usize,
+    state: &T,
+) {
+    let mut data = vec![0u8; 8 + space];
+    state.try_serialize(&mut &mut data[..]).unwrap();
+    program_test.add_account(
+        address,
+        SolanaAccount {
+            lamports: 1_000_000_000,
+            data,
+            owner: slot_machine::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+}
+
+pub fn add_game_state(
+    program_test: &mut ProgramTest,
+    address: Pubkey,
+    state: &slot_machine::GameState,
+) {
+    add_program_account(program_test, address, slot_machine::GameState::SPACE, state);
+}
+
+// game_id 0 的 GameState：无延迟、不限 RTP 与敞口，各测试只覆盖与场景相关的字段
+pub fn game_state_fixture(
+    owner: Pubkey,
+    bump: u8,
+    pool_mint: Pubkey,
+    pool_token_account: Pubkey,
+) -> slot_machine::GameState {
+    slot_machine::GameState {
+        owner,
+        bump,
+        pool_mint,
+        pool_token_account,
+        total_pool: 0,
+        nonce: 0,
+        agents: vec![],
+        next_room_card: 10000,
+        commission_rate: 10,
+        stake_threshold: 1_000_000,
+        settlement_period: 86_400,
+        vrf: Pubkey::default(),
+        vrf_result_offset: 0,
+        reel_weights: [[2500, 2500, 250, 1600, 2150, 1000]; 3],
+        payout_triple: [220, 180, 2000, 360, 450, 0],
+        payout_double: [65, 50, 100, 75, 85, 0],
+        max_auto_spins: 5,
+        min_bet: 1,
+        game_id: 0,
+        vrf_pool: [slot_machine::VrfSlot::default(); slot_machine::MAX_VRF_POOL],
+        randomness_source: slot_machine::RandomnessSource::Switchboard,
+        commit_posted: 0,
+        commit_bound: 0,
+        play_timeout_slots: 1_500,
+        keeper_fee_bps: 0,
+        paused: 0,
+        guardian: Pubkey::default(),
+        pending_owner: Pubkey::default(),
+        config_admin: Pubkey::default(),
+        treasurer: Pubkey::default(),
+        agent_manager: Pubkey::default(),
+        pauser: Pubkey::default(),
+        config_version: 0,
+        config_delay_slots: 0,
+        queued_config: slot_machine::QueuedConfig::default(),
+        rtp_min_bps: 0,
+        rtp_max_bps: 10_000,
+        max_bet_per_symbol: 0,
+        max_bet_per_play: 0,
+        max_exposure_bps: 0,
+        layout: slot_machine::Layout::Classic,
+        symbol_roles: slot_machine::DEFAULT_SYMBOL_ROLES,
+        scatter_pays: [0; 4],
+        multipliers: slot_machine::DEFAULT_MULTIPLIERS,
+        trigger_count: 1,
+        jackpot_token_account: Pubkey::default(),
+        jackpot_total: 0,
+        jackpot_bps: 0,
+        jackpot_trigger: slot_machine::JackpotTrigger::Disabled,
+        rebind_cooldown: slot_machine::REBIND_NEVER,
+        max_referral_share_bps: 10_000,
+        upline_rates: [0; 4],
+        upline_depth: 0,
+        tiered_commission: false,
+        outstanding_liability: 0,
+    }
+}
+
+pub const LEGACY_WEIGHTS: [u16; 6] = [3000, 2000, 500, 1500, 2000, 1000];
+
+// 按多游戏支持之前已部署的 GameState 字段顺序逐字段编码，账户长度为旧版 8 + 4096
+#[allow(clippy::too_many_arguments)]
+pub fn legacy_game_state_data(
+    owner: Pubkey,
+    bump: u8,
+    mint: Pubkey,
+    pool_token_account: Pubkey,
+    total_pool: u64,
+    vrf: Pubkey,
+    agent: Pubkey,
+    stake: u64,
+) -> Vec<u8> {
+    let mut d = <slot_machine::GameState as Discriminator>::DISCRIMINATOR.to_vec();
+    d.extend_from_slice(owner.as_ref());
+    d.push(bump);
+    d.extend_from_slice(mint.as_ref());
+    d.extend_from_slice(pool_token_account.as_ref());
+    d.extend_from_slice(&total_pool.to_le_bytes());
+    d.extend_from_slice(&42u64.to_le_bytes()); // nonce
+    d.extend_from_slice(&1u32.to_le_bytes()); // agents.len()
+    d.extend_from_slice(agent.as_ref());
+    d.extend_from_slice(&stake.to_le_bytes());
+    d.extend_from_slice(&10000u64.to_le_bytes()); // room_card
+    d.extend_from_slice(&777i64.to_le_bytes()); // commission
+    d.extend_from_slice(&5i64.to_le_bytes()); // stake_time
+    d.extend_from_slice(&6i64.to_le_bytes()); // last_settlement
+    d.push(1); // is_active
+    d.extend_from_slice(&10001u64.to_le_bytes()); // next_room_card
+    d.push(10); // commission_rate
+    d.extend_from_slice(&1_000_000u64.to_le_bytes()); // stake_threshold
+    d.extend_from_slice(&86_400u64.to_le_bytes()); // settlement_period
+    d.extend_from_slice(vrf.as_ref());
+    d.extend_from_slice(&0u32.to_le_bytes()); // vrf_result_offset
+    for w in LEGACY_WEIGHTS {
+        d.extend_from_slice(&w.to_le_bytes()); // symbol_weights
+    }
+    for p in [220u16, 180, 2000, 360, 450, 0] {
+        d.extend_from_slice(&p.to_le_bytes()); // payout_triple
+    }
+    for p in [65u16, 50, 100, 75, 85, 0] {
+        d.extend_from_slice(&p.to_le_bytes()); // payout_double
+    }
+    d.push(5); // max_auto_spins
+    d.extend_from_slice(&1u64.to_le_bytes()); // min_bet
+    d.resize(8 + 4096, 0);
+    d
+}
+
+pub fn player_profile_address(program_id: &Pubkey, game_state: &Pubkey, player: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"player_profile", game_state.as_ref(), player.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+pub fn player_stats_address(program_id: &Pubkey, game_state: &Pubkey, player: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"player_stats", game_state.as_ref(), player.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+pub fn pending_play_address(
+    program_id: &Pubkey,
+    game_state: &Pubkey,
+    player: &Pubkey,
+    seq: u64,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"pending_play",
+            game_state.as_ref(),
+            player.as_ref(),
+            &seq.to_le_bytes(),
+        ],
+        program_id,
+    )
+    .0
+}
@@ -3,7 +3,6 @@ use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
 use solana_program::{
     account_info::next_account_info,
     entrypoint::ProgramResult,
-    program_option::COption,
     program_pack::Pack,
     pubkey::Pubkey,
 };
@@ -14,16 +13,11 @@ use solana_sdk::{
     system_program,
     transaction::Transaction,
 };
-use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use spl_token::state::Account as TokenAccount;
+use switchboard_solana::VrfAccountData;
 
-fn slot_machine_process<'a, 'b, 'c, 'd>(
-    program_id: &'a Pubkey,
-    accounts: &'b [AccountInfo<'c>],
-    data: &'d [u8],
-) -> ProgramResult {
-    let accounts: &'c [AccountInfo<'c>] = unsafe { std::mem::transmute(accounts) };
-    slot_machine::entry(program_id, accounts, data)
-}
+mod common;
+use common::*;
 
 fn mock_switchboard_process<'a, 'b, 'c, 'd>(
     program_id: &'a Pubkey,
@@ -32,56 +26,58 @@ fn mock_switchboard_process<'a, 'b, 'c, 'd>(
 ) -> ProgramResult {
     let accounts: &'c [AccountInfo<'c>] = unsafe { std::mem::transmute(accounts) };
     let accounts_iter = &mut accounts.iter();
+    // 测试直接写入 VRF 结果：data = offset(u32) + 32 bytes
+    if data.len() == 4 + 32 {
+        let vrf = next_account_info(accounts_iter)?;
+        if vrf.owner != program_id {
+            return Err(solana_program::program_error::ProgramError::IncorrectProgramId);
+        }
+        let offset = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let mut vrf_data = vrf.try_borrow_mut_data()?;
+        if vrf_data.len() < offset + 32 {
+            return Err(solana_program::program_error::ProgramError::InvalidAccountData);
+        }
+        vrf_data[offset..offset + 32].copy_from_slice(&data[4..]);
+        return Ok(());
+    }
+    // 其余视为 vrf_request_randomness CPI：authority 必须签名；counter+1 并清空本轮结果
+    let authority = next_account_info(accounts_iter)?;
     let vrf = next_account_info(accounts_iter)?;
+    if !authority.is_signer {
+        return Err(solana_program::program_error::ProgramError::MissingRequiredSignature);
+    }
     if vrf.owner != program_id {
         return Err(solana_program::program_error::ProgramError::IncorrectProgramId);
     }
-    if data.len() != 4 + 32 {
-        return Err(solana_program::program_error::ProgramError::InvalidInstructionData);
-    }
-    let offset = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
     let mut vrf_data = vrf.try_borrow_mut_data()?;
-    if vrf_data.len() < offset + 32 {
-        return Err(solana_program::program_error::ProgramError::InvalidAccountData);
-    }
-    vrf_data[offset..offset + 32].copy_from_slice(&data[4..]);
+    let c = vrf_counter_offset();
+    let counter = u128::from_le_bytes(vrf_data[c..c + 16].try_into().unwrap()) + 1;
+    vrf_data[c..c + 16].copy_from_slice(&counter.to_le_bytes());
+    let r = vrf_result_offset();
+    vrf_data[r..r + 32].fill(0);
     Ok(())
 }
 
-fn pack_mint(mint_authority: Pubkey, decimals: u8) -> Vec<u8> {
-    let mint = Mint {
-        mint_authority: COption::Some(mint_authority),
-        supply: 0,
-        decimals,
-        is_initialized: true,
-        freeze_authority: COption::None,
-    };
-    let mut data = vec![0u8; Mint::LEN];
-    Mint::pack(mint, &mut data).unwrap();
-    data
+fn vrf_counter_offset() -> usize {
+    8 + std::mem::offset_of!(VrfAccountData, counter)
 }
 
-fn pack_token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
-    let token = TokenAccount {
-        mint,
-        owner,
-        amount,
-        delegate: COption::None,
-        state: AccountState::Initialized,
-        is_native: COption::None,
-        delegated_amount: 0,
-        close_authority: COption::None,
-    };
-    let mut data = vec![0u8; TokenAccount::LEN];
-    TokenAccount::pack(token, &mut data).unwrap();
+fn vrf_result_offset() -> usize {
+    8 + std::mem::offset_of!(VrfAccountData, current_round.result)
+}
+
+fn pack_vrf(authority: Pubkey) -> Vec<u8> {
+    let mut data = vec![0u8; 8 + std::mem::size_of::<VrfAccountData>()];
+    data[..8].copy_from_slice(&<VrfAccountData as anchor_lang::Discriminator>::DISCRIMINATOR);
+    let a = 8 + std::mem::offset_of!(VrfAccountData, authority);
+    data[a..a + 32].copy_from_slice(authority.as_ref());
     data
 }
 
 #[tokio::test]
 async fn request_play_rejects_using_non_pool_account() {
     let program_id = slot_machine::id();
-    let mut program_test = ProgramTest::new("slot_machine", program_id, processor!(slot_machine_process));
-    program_test.add_program("spl_token", spl_token::id(), processor!(spl_token::processor::Processor::process));
+    let mut program_test = new_program_test();
     program_test.add_program(
         "switchboard_v2_mock",
         slot_machine::SWITCHBOARD_V2_PROGRAM_ID,
@@ -98,99 +94,42 @@ async fn request_play_rejects_using_non_pool_account() {
 
     let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
 
-    program_test.add_account(
-        mint,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_mint(Pubkey::new_unique(), 6),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
-
-    program_test.add_account(
-        player.pubkey(),
-        SolanaAccount {
-            lamports: 2_000_000_000,
-            data: vec![],
-            owner: system_program::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
+    add_mint(&mut program_test, mint);
+    add_wallet(&mut program_test, player.pubkey());
 
     let player_amount_before: u64 = 10_000_000;
-    program_test.add_account(
+    add_token_account(
+        &mut program_test,
         player_token_account,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_token_account(mint, player.pubkey(), player_amount_before),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
-
-    program_test.add_account(
-        real_pool_token_account,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_token_account(mint, game_state, 0),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
+        mint,
+        player.pubkey(),
+        player_amount_before,
     );
 
-    let mut game_state_data = vec![0u8; 8 + 4096];
-    {
-        let mut cursor = std::io::Cursor::new(&mut game_state_data[..]);
-        let state = slot_machine::GameState {
-            owner: owner.pubkey(),
-            bump,
-            pool_mint: mint,
-            pool_token_account: real_pool_token_account,
-            total_pool: 0,
-            nonce: 0,
-            agents: vec![slot_machine::Agent {
-                pubkey: agent.pubkey(),
-                stake: 0,
-                room_card: 10000,
-                commission: 0,
-                stake_time: 0,
-                last_settlement: 0,
-                is_active: true,
-            }],
-            next_room_card: 10001,
-            commission_rate: 10,
-            stake_threshold: 1_000_000,
-            settlement_period: 86_400,
-            vrf: Pubkey::default(),
-            vrf_result_offset: 0,
-            symbol_weights: [2500, 2500, 250, 1600, 2150, 1000],
-            payout_triple: [0, 0, 0, 0, 0, 0],
-            payout_double: [0, 0, 0, 0, 0, 0],
-            max_auto_spins: 1,
-            min_bet: 100,
-        };
-        state.try_serialize(&mut cursor).unwrap();
-    }
-
-    program_test.add_account(
-        game_state,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: game_state_data,
-            owner: program_id,
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
+    add_token_account(&mut program_test, real_pool_token_account, mint, game_state, 0);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, real_pool_token_account);
+    state.agents = vec![slot_machine::Agent {
+        pubkey: agent.pubkey(),
+        stake: 0,
+        room_card: 10000,
+        commission: 0,
+        stake_time: 0,
+        last_settlement: 0,
+        is_active: true,
+    }];
+    state.next_room_card = 10001;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    state.min_bet = 100;
+    add_game_state(&mut program_test, game_state, &state);
 
     let mut context = program_test.start_with_context().await;
 
-    let pending_play = Keypair::new();
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
     let bet: u64 = 1_000_000;
     let bets: [u64; 6] = [bet, 0, 0, 0, 0, 0];
 
@@ -198,22 +137,43 @@ async fn request_play_rejects_using_non_pool_account() {
         program_id,
         accounts: slot_machine::accounts::RequestPlay {
             game_state,
-            pending_play: pending_play.pubkey(),
+            player_profile,
+            player_stats,
+            pending_play,
             player: player.pubkey(),
             player_token_account,
             pool_token_account: player_token_account,
             token_program: spl_token::id(),
-            vrf: Pubkey::new_unique(),
+            vrf: Some(Pubkey::new_unique()),
+            oracle_queue: Some(Pubkey::new_unique()),
+            queue_authority: Some(Pubkey::new_unique()),
+            data_buffer: Some(Pubkey::new_unique()),
+            permission: Some(Pubkey::new_unique()),
+            escrow: Some(player_token_account),
+            payer_wallet: Some(player_token_account),
+            recent_blockhashes: Some(solana_sdk::sysvar::recent_blockhashes::id()),
+            program_state: Some(Pubkey::new_unique()),
+            switchboard_program: Some(slot_machine::SWITCHBOARD_V2_PROGRAM_ID),
+            commitment: None,
+            agent_account: None,
+            game_config: None,
             system_program: system_program::id(),
         }
         .to_account_metas(None),
-        data: slot_machine::instruction::RequestPlay { bets, room_card: None }.data(),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [0u8; 32],
+            switchboard_state_bump: 255,
+            permission_bump: 255,
+        }
+        .data(),
     };
 
     let tx = Transaction::new_signed_with_payer(
         &[ix],
         Some(&context.payer.pubkey()),
-        &[&context.payer, &player, &pending_play],
+        &[&context.payer, &player],
         context.last_blockhash,
     );
     assert!(context.banks_client.process_transaction(tx).await.is_err());
@@ -237,10 +197,9 @@ async fn request_play_rejects_using_non_pool_account() {
 }
 
 #[tokio::test]
-async fn request_settle_requires_vrf_update_and_transfers_bet_once() {
+async fn request_settle_binds_vrf_round_and_transfers_bet_once() {
     let program_id = slot_machine::id();
-    let mut program_test = ProgramTest::new("slot_machine", program_id, processor!(slot_machine_process));
-    program_test.add_program("spl_token", spl_token::id(), processor!(spl_token::processor::Processor::process));
+    let mut program_test = new_program_test();
     program_test.add_program(
         "switchboard_v2_mock",
         slot_machine::SWITCHBOARD_V2_PROGRAM_ID,
@@ -250,143 +209,137 @@ async fn request_settle_requires_vrf_update_and_transfers_bet_once() {
     let owner = Keypair::new();
     let player = Keypair::new();
     let agent = Keypair::new();
-    let pending_play = Keypair::new();
 
     let mint = Pubkey::new_unique();
+    let wsol_mint = Pubkey::new_unique();
     let vrf = Pubkey::new_unique();
     let real_pool_token_account = Pubkey::new_unique();
     let player_token_account = Pubkey::new_unique();
+    let escrow = Pubkey::new_unique();
+    let payer_wallet = Pubkey::new_unique();
+    let program_state = Pubkey::new_unique();
 
     let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
 
-    program_test.add_account(
-        mint,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_mint(Pubkey::new_unique(), 6),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
-
-    program_test.add_account(
-        player.pubkey(),
-        SolanaAccount {
-            lamports: 5_000_000_000,
-            data: vec![],
-            owner: system_program::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
+    add_mint(&mut program_test, mint);
+    add_wallet(&mut program_test, player.pubkey());
 
     let player_amount_before: u64 = 10_000_000;
-    program_test.add_account(
+    add_token_account(
+        &mut program_test,
         player_token_account,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_token_account(mint, player.pubkey(), player_amount_before),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
+        mint,
+        player.pubkey(),
+        player_amount_before,
     );
 
-    program_test.add_account(
-        real_pool_token_account,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_token_account(mint, game_state, 0),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
+    add_token_account(&mut program_test, real_pool_token_account, mint, game_state, 0);
 
     program_test.add_account(
         vrf,
         SolanaAccount {
             lamports: 1_000_000_000,
-            data: vec![0u8; 64],
+            data: pack_vrf(game_state),
             owner: slot_machine::SWITCHBOARD_V2_PROGRAM_ID,
             executable: false,
             rent_epoch: 0,
         },
     );
 
-    let mut game_state_data = vec![0u8; 8 + 4096];
-    {
-        let mut cursor = std::io::Cursor::new(&mut game_state_data[..]);
-        let state = slot_machine::GameState {
-            owner: owner.pubkey(),
-            bump,
-            pool_mint: mint,
-            pool_token_account: real_pool_token_account,
-            total_pool: 0,
-            nonce: 0,
-            agents: vec![slot_machine::Agent {
-                pubkey: agent.pubkey(),
-                stake: 0,
-                room_card: 10000,
-                commission: 0,
-                stake_time: 0,
-                last_settlement: 0,
-                is_active: true,
-            }],
-            next_room_card: 10001,
-            commission_rate: 10,
-            stake_threshold: 1_000_000,
-            settlement_period: 86_400,
-            vrf,
-            vrf_result_offset: 0,
-            symbol_weights: [2500, 2500, 250, 1600, 2150, 1000],
-            payout_triple: [0, 0, 0, 0, 0, 0],
-            payout_double: [0, 0, 0, 0, 0, 0],
-            max_auto_spins: 1,
-            min_bet: 1,
-        };
-        state.try_serialize(&mut cursor).unwrap();
-    }
-    program_test.add_account(
-        game_state,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: game_state_data,
-            owner: program_id,
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
+    add_token_account(&mut program_test, escrow, wsol_mint, program_state, 0);
+    add_token_account(&mut program_test, payer_wallet, wsol_mint, player.pubkey(), 1_000_000);
+
+    let mut vrf_pool = [slot_machine::VrfSlot::default(); slot_machine::MAX_VRF_POOL];
+    vrf_pool[0].vrf = vrf;
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, real_pool_token_account);
+    state.agents = vec![slot_machine::Agent {
+        pubkey: agent.pubkey(),
+        stake: 0,
+        room_card: 10000,
+        commission: 0,
+        stake_time: 0,
+        last_settlement: 0,
+        is_active: true,
+    }];
+    state.next_room_card = 10001;
+    state.vrf = vrf;
+    state.vrf_pool = vrf_pool;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    add_game_state(&mut program_test, game_state, &state);
 
     let mut context = program_test.start_with_context().await;
 
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
     let bet: u64 = 1_000_000;
     let bets: [u64; 6] = [bet, 0, 0, 0, 0, 0];
-    let request_ix = solana_sdk::instruction::Instruction {
+    let request_ix = |pending: Pubkey| solana_sdk::instruction::Instruction {
         program_id,
         accounts: slot_machine::accounts::RequestPlay {
             game_state,
-            pending_play: pending_play.pubkey(),
+            player_profile,
+            player_stats,
+            pending_play: pending,
             player: player.pubkey(),
             player_token_account,
             pool_token_account: real_pool_token_account,
             token_program: spl_token::id(),
-            vrf,
+            vrf: Some(vrf),
+            oracle_queue: Some(Pubkey::new_unique()),
+            queue_authority: Some(Pubkey::new_unique()),
+            data_buffer: Some(Pubkey::new_unique()),
+            permission: Some(Pubkey::new_unique()),
+            escrow: Some(escrow),
+            payer_wallet: Some(payer_wallet),
+            recent_blockhashes: Some(solana_sdk::sysvar::recent_blockhashes::id()),
+            program_state: Some(program_state),
+            switchboard_program: Some(slot_machine::SWITCHBOARD_V2_PROGRAM_ID),
+            commitment: None,
+            agent_account: None,
+            game_config: None,
             system_program: system_program::id(),
         }
         .to_account_metas(None),
-        data: slot_machine::instruction::RequestPlay { bets, room_card: None }.data(),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [0u8; 32],
+            switchboard_state_bump: 255,
+            permission_bump: 255,
+        }
+        .data(),
     };
 
     let tx = Transaction::new_signed_with_payer(
-        &[request_ix],
+        &[request_ix(pending_play)],
         Some(&context.payer.pubkey()),
-        &[&context.payer, &player, &pending_play],
+        &[&context.payer, &player],
         context.last_blockhash,
     );
     context.banks_client.process_transaction(tx).await.unwrap();
 
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap().unwrap();
+    let mut pending_slice: &[u8] = &pending_acc.data;
+    let pending = slot_machine::PendingPlay::try_deserialize(&mut pending_slice).unwrap();
+    assert_eq!(pending.vrf_counter, 1);
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.vrf_pool[0].pending_play, pending_play);
+
+    // 同一 VRF 轮次未结算前，第二局请求应被拒绝
+    let second_pending = pending_play_address(&program_id, &game_state, &player.pubkey(), 1);
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix(second_pending)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
     let player_acc = context.banks_client.get_account(player_token_account).await.unwrap().unwrap();
     let player_token = TokenAccount::unpack(&player_acc.data).unwrap();
     assert_eq!(player_token.amount, player_amount_before - bet);
@@ -404,16 +357,24 @@ async fn request_settle_requires_vrf_update_and_transfers_bet_once() {
         program_id,
         accounts: slot_machine::accounts::SettlePlay {
             game_state,
-            pending_play: pending_play.pubkey(),
+            player_profile,
+            player_stats,
+            pending_play,
             player: player.pubkey(),
             player_token_account,
             pool_token_account: real_pool_token_account,
+            keeper: context.payer.pubkey(),
+            keeper_token_account: None,
             token_program: spl_token::id(),
-            vrf,
+            vrf: Some(vrf),
+            commitment: None,
+            agent_account: None,
+            jackpot_token_account: None,
             system_program: system_program::id(),
+            commission_schedule: None,
         }
         .to_account_metas(None),
-        data: slot_machine::instruction::SettlePlay {}.data(),
+        data: slot_machine::instruction::SettlePlay { reveal: None }.data(),
     };
     let tx = Transaction::new_signed_with_payer(
         &[settle_ix.clone()],
@@ -426,7 +387,7 @@ async fn request_settle_requires_vrf_update_and_transfers_bet_once() {
     context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
 
     let mut update_data = Vec::with_capacity(4 + 32);
-    update_data.extend_from_slice(&0u32.to_le_bytes());
+    update_data.extend_from_slice(&(vrf_result_offset() as u32).to_le_bytes());
     update_data.extend_from_slice(&[7u8; 32]);
     let vrf_update_ix = solana_sdk::instruction::Instruction {
         program_id: slot_machine::SWITCHBOARD_V2_PROGRAM_ID,
@@ -442,7 +403,7 @@ async fn request_settle_requires_vrf_update_and_transfers_bet_once() {
     context.banks_client.process_transaction(tx).await.unwrap();
 
     let vrf_acc = context.banks_client.get_account(vrf).await.unwrap().unwrap();
-    assert_eq!(&vrf_acc.data[0..32], &[7u8; 32]);
+    assert_eq!(&vrf_acc.data[vrf_result_offset()..vrf_result_offset() + 32], &[7u8; 32]);
 
     context.last_blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
 
@@ -454,6 +415,10 @@ async fn request_settle_requires_vrf_update_and_transfers_bet_once() {
     );
     context.banks_client.process_transaction(tx).await.unwrap();
 
-    let pending_acc = context.banks_client.get_account(pending_play.pubkey()).await.unwrap();
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap();
     assert!(pending_acc.is_none());
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.vrf_pool[0].pending_play, Pubkey::default());
 }
@@ -0,0 +1,268 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn lines_layout_snapshots_grid_and_settles_5x3() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
+    let (game_config, _) = Pubkey::find_program_address(&[b"game_config", game_state.as_ref()], &program_id);
+    let (commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &0u64.to_le_bytes()],
+        &program_id,
+    );
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 1_000_000_000);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.total_pool = 1_000_000_000;
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seed = [42u8; 32];
+    let post_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::PostSeedCommitment {
+            game_state,
+            commitment,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::PostSeedCommitment {
+            seed_hash: hash(&house_seed).to_bytes(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let config_ix = |data: Vec<u8>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetGameConfig {
+            game_state,
+            game_config,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data,
+    };
+    let lines = slot_machine::LineOdds {
+        reels: 5,
+        rows: 3,
+        reel_weights: [[2500, 2500, 250, 1600, 2150, 1000]; 5],
+        paylines: vec![[1; 5], [0; 5], [2; 5], [0, 1, 2, 1, 0], [2, 1, 0, 1, 2]],
+        line_pays: [
+            [20, 60, 200],
+            [15, 50, 150],
+            [500, 2000, 10_000],
+            [30, 100, 400],
+            [40, 120, 500],
+            [0, 0, 0],
+        ],
+    };
+    let init_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::InitGameConfig {
+            game_state,
+            game_config,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::InitGameConfig {}.data(),
+    };
+    // config_delay_slots 为 0，切换布局立即生效
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            init_ix,
+            config_ix(slot_machine::instruction::SetLineConfig { lines: lines.clone() }.data()),
+            config_ix(
+                slot_machine::instruction::SetLayout {
+                    layout: slot_machine::Layout::Lines,
+                }
+                .data(),
+            ),
+        ],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert!(state.layout == slot_machine::Layout::Lines);
+
+    // 网格布局生效期间不能修改布局配置
+    let mut changed = lines.clone();
+    changed.paylines.pop();
+    let tx = Transaction::new_signed_with_payer(
+        &[config_ix(slot_machine::instruction::SetLineConfig { lines: changed }.data())],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let bet: u64 = 1_000_000;
+    let bets: [u64; 6] = [bet, 0, 0, 0, 0, 0];
+    let request_ix = |game_config: Option<Pubkey>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RequestPlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+            oracle_queue: None,
+            queue_authority: None,
+            data_buffer: None,
+            permission: None,
+            escrow: None,
+            payer_wallet: None,
+            recent_blockhashes: None,
+            program_state: None,
+            switchboard_program: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            game_config,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RequestPlay {
+            bets,
+            room_card: None,
+            client_seed: [9u8; 32],
+            switchboard_state_bump: 0,
+            permission_bump: 0,
+        }
+        .data(),
+    };
+    // 网格布局下缺少 GameConfig 被拒绝
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix(None)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[request_ix(Some(game_config))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap().unwrap();
+    let mut pending_slice: &[u8] = &pending_acc.data;
+    let pending = slot_machine::PendingPlay::try_deserialize(&mut pending_slice).unwrap();
+    assert!(pending.odds.layout == slot_machine::Layout::Lines);
+    assert!(pending.lines == lines);
+
+    let settle_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SettlePlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            keeper: context.payer.pubkey(),
+            keeper_token_account: None,
+            token_program: spl_token::id(),
+            vrf: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            jackpot_token_account: None,
+            system_program: system_program::id(),
+            commission_schedule: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SettlePlay {
+            reveal: Some(house_seed),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[settle_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction_with_metadata(tx).await.unwrap();
+    result.result.unwrap();
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    let transcript = slot_machine::SpinTranscript::try_from_slice(&return_data.data).unwrap();
+    assert_eq!((transcript.grid_reels, transcript.grid_rows), (5, 3));
+    assert!(transcript.spins.iter().all(|sp| sp.reels.len() == 15));
+
+    let profile_acc = context.banks_client.get_account(player_profile).await.unwrap().unwrap();
+    let mut profile_slice: &[u8] = &profile_acc.data;
+    let profile = slot_machine::PlayerProfile::try_deserialize(&mut profile_slice).unwrap();
+    assert_eq!(profile.last_result.grid_reels, 5);
+    assert_eq!(profile.last_result.reels[0], transcript.spins[0].reels[..]);
+    assert_eq!(profile.last_result.payout, transcript.payout);
+
+    let player_acc = context.banks_client.get_account(player_token_account).await.unwrap().unwrap();
+    let player_token = TokenAccount::unpack(&player_acc.data).unwrap();
+    assert_eq!(player_token.amount, player_amount_before - bet + transcript.payout);
+}
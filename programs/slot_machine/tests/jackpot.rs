@@ -0,0 +1,301 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn jackpot_accrues_separately_and_pays_out_on_trigger() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+    let owner_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let pending_play = pending_play_address(&program_id, &game_state, &player.pubkey(), 0);
+    let (jackpot, _) = Pubkey::find_program_address(&[b"jackpot", game_state.as_ref()], &program_id);
+    let (commitment, _) = Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &0u64.to_le_bytes()],
+        &program_id,
+    );
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+
+    add_token_account(&mut program_test, owner_token_account, mint, owner.pubkey(), 2_000_000);
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 0);
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    // 佣金 10% + jackpot 5% 须留在 RTP 上限之外的边际内
+    state.rtp_max_bps = 8_000;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seed = [42u8; 32];
+    let post_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::PostSeedCommitment {
+            game_state,
+            commitment,
+            owner: owner.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::PostSeedCommitment {
+            seed_hash: hash(&house_seed).to_bytes(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[post_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let send = |ixs: Vec<solana_sdk::instruction::Instruction>, signer: &Keypair, blockhash| {
+        Transaction::new_signed_with_payer(&ixs, Some(&context.payer.pubkey()), &[&context.payer, signer], blockhash)
+    };
+    let init_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::InitJackpot {
+            game_state,
+            jackpot_token_account: jackpot,
+            pool_mint: mint,
+            owner: owner.pubkey(),
+            token_program: spl_token::id(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::InitJackpot {}.data(),
+    };
+    let seed_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SeedJackpot {
+            game_state,
+            jackpot_token_account: jackpot,
+            owner: owner.pubkey(),
+            owner_token_account,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SeedJackpot { amount: 1_000_000 }.data(),
+    };
+    let set_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetJackpot {
+            bps: 500,
+            trigger: slot_machine::JackpotTrigger::Draw { one_in: 1, min_bet: 1 },
+        }
+        .data(),
+    };
+    let tx = send(vec![init_ix, seed_ix, set_ix], &owner, context.last_blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.jackpot_token_account, jackpot);
+    assert_eq!(state.jackpot_total, 1_000_000);
+    assert_eq!(state.jackpot_bps, 500);
+
+    // withdraw_pool 只接受 game_state.pool_token_account，无法提取 jackpot
+    let withdraw_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::WithdrawPool {
+            game_state,
+            owner: owner.pubkey(),
+            pool_token_account: jackpot,
+            owner_token_account,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::WithdrawPool { amount: 1 }.data(),
+    };
+    let tx = send(vec![withdraw_ix], &owner, context.last_blockhash);
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let bet: u64 = 1_000_000;
+    let request_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RequestPlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf: None,
+            oracle_queue: None,
+            queue_authority: None,
+            data_buffer: None,
+            permission: None,
+            escrow: None,
+            payer_wallet: None,
+            recent_blockhashes: None,
+            program_state: None,
+            switchboard_program: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            game_config: None,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RequestPlay {
+            bets: [bet, 0, 0, 0, 0, 0],
+            room_card: None,
+            client_seed: [9u8; 32],
+            switchboard_state_bump: 0,
+            permission_bump: 0,
+        }
+        .data(),
+    };
+    let tx = send(vec![request_ix], &player, context.last_blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pending_acc = context.banks_client.get_account(pending_play).await.unwrap().unwrap();
+    let mut pending_slice: &[u8] = &pending_acc.data;
+    let pending = slot_machine::PendingPlay::try_deserialize(&mut pending_slice).unwrap();
+    assert_eq!(pending.jackpot_contribution, 50_000);
+
+    let settle_ix = |jackpot_token_account: Option<Pubkey>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SettlePlay {
+            game_state,
+            player_profile,
+            player_stats,
+            pending_play,
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            keeper: player.pubkey(),
+            keeper_token_account: None,
+            token_program: spl_token::id(),
+            vrf: None,
+            commitment: Some(commitment),
+            agent_account: None,
+            jackpot_token_account,
+            system_program: system_program::id(),
+            commission_schedule: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SettlePlay { reveal: Some(house_seed) }.data(),
+    };
+
+    // 需划入 jackpot 时必须传入 jackpot 账户
+    let tx = send(vec![settle_ix(None)], &player, context.last_blockhash);
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = send(vec![settle_ix(Some(jackpot))], &player, context.last_blockhash);
+    let result = context.banks_client.process_transaction_with_metadata(tx).await.unwrap();
+    result.result.unwrap();
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    let transcript = slot_machine::SpinTranscript::try_from_slice(&return_data.data).unwrap();
+    assert_eq!(transcript.payout, 0);
+    assert_eq!(transcript.jackpot, 1_050_000);
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.jackpot_total, 0);
+    assert_eq!(state.total_pool, bet - 50_000);
+
+    let pool_acc = context.banks_client.get_account(pool_token_account).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&pool_acc.data).unwrap().amount, bet - 50_000);
+    let jackpot_acc = context.banks_client.get_account(jackpot).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&jackpot_acc.data).unwrap().amount, 0);
+    let player_acc = context.banks_client.get_account(player_token_account).await.unwrap().unwrap();
+    assert_eq!(
+        TokenAccount::unpack(&player_acc.data).unwrap().amount,
+        player_amount_before - bet + 1_050_000
+    );
+
+    // close_game 把 jackpot 余额转给所有者并关闭 jackpot 账户，不留无人可签名的余额
+    let seed_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SeedJackpot {
+            game_state,
+            jackpot_token_account: jackpot,
+            owner: owner.pubkey(),
+            owner_token_account,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SeedJackpot { amount: 400_000 }.data(),
+    };
+    let tx = send(vec![seed_ix], &owner, context.last_blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let close_ix = |jackpot_token_account: Option<Pubkey>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::CloseGame {
+            game_state,
+            owner: owner.pubkey(),
+            pool_token_account,
+            owner_token_account,
+            token_program: spl_token::id(),
+            jackpot_token_account,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::CloseGame {}.data(),
+    };
+    let tx = send(vec![close_ix(None)], &owner, context.last_blockhash);
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let owner_before = context.banks_client.get_account(owner_token_account).await.unwrap().unwrap();
+    let owner_before = TokenAccount::unpack(&owner_before.data).unwrap().amount;
+    let tx = send(vec![close_ix(Some(jackpot))], &owner, context.last_blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    assert!(context.banks_client.get_account(jackpot).await.unwrap().is_none());
+    assert!(context.banks_client.get_account(game_state).await.unwrap().is_none());
+    let owner_acc = context.banks_client.get_account(owner_token_account).await.unwrap().unwrap();
+    assert_eq!(
+        TokenAccount::unpack(&owner_acc.data).unwrap().amount,
+        owner_before + (bet - 50_000) + 400_000
+    );
+}
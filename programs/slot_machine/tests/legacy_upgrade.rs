@@ -0,0 +1,196 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::program_pack::Pack;
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account as SolanaAccount,
+    instruction::AccountMeta,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+async fn token_amount(context: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    let acc = context.banks_client.get_account(address).await.unwrap().unwrap();
+    TokenAccount::unpack(&acc.data).unwrap().amount
+}
+
+// 已部署的 8 + 4096 旧版账户：migrate_agents 升级后，旧代理商可提取佣金，玩家可带房卡下注
+#[tokio::test]
+async fn legacy_game_state_serves_withdraw_and_play_after_migration() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let agent = Keypair::new();
+    let player = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let agent_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+    let vrf = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (agent_account, _) = Pubkey::find_program_address(
+        &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+        &program_id,
+    );
+    let (room_card_index, _) = Pubkey::find_program_address(
+        &[b"room_card", game_state.as_ref(), &10000u64.to_le_bytes()],
+        &program_id,
+    );
+
+    let pool_amount_before: u64 = 1_000_000_000;
+    let player_amount_before: u64 = 1_000_000;
+    let stake: u64 = 2_000_000;
+
+    for who in [owner.pubkey(), agent.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+    add_mint(&mut program_test, mint);
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, pool_amount_before);
+    add_token_account(&mut program_test, agent_token_account, mint, agent.pubkey(), 0);
+    add_token_account(
+        &mut program_test,
+        player_token_account,
+        mint,
+        player.pubkey(),
+        player_amount_before,
+    );
+    program_test.add_account(
+        vrf,
+        SolanaAccount {
+            lamports: 1_000_000_000,
+            data: vec![7u8; 64],
+            owner: slot_machine::SWITCHBOARD_V2_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    program_test.add_account(
+        game_state,
+        SolanaAccount {
+            lamports: 1_000_000_000,
+            data: legacy_game_state_data(
+                owner.pubkey(),
+                bump,
+                mint,
+                pool_token_account,
+                pool_amount_before,
+                vrf,
+                agent.pubkey(),
+                stake,
+            ),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    // 旧代理商 last_settlement = 6，推进时钟越过结算周期
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    clock.unix_timestamp = clock.unix_timestamp.max(6 + 86_400);
+    context.set_sysvar(&clock);
+
+    let mut accounts = slot_machine::accounts::MigrateAgents {
+        game_state,
+        owner: owner.pubkey(),
+        system_program: system_program::id(),
+    }
+    .to_account_metas(None);
+    accounts.push(AccountMeta::new(agent_account, false));
+    accounts.push(AccountMeta::new(room_card_index, false));
+    let ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts,
+        data: slot_machine::instruction::MigrateAgents {}.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // 旧版内联代理商的 777 佣金从迁移后的奖池提取
+    let ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::WithdrawCommission {
+            game_state,
+            agent_account,
+            agent: agent.pubkey(),
+            pool_token_account,
+            agent_token_account,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::WithdrawCommission {}.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    assert_eq!(token_amount(&mut context, agent_token_account).await, 777);
+    let pool_after_withdraw = pool_amount_before - 777;
+    assert_eq!(token_amount(&mut context, pool_token_account).await, pool_after_withdraw);
+
+    // 带迁移后的房卡下注，VRF 取自旧账户的 vrf / vrf_result_offset
+    let bets = [100, 0, 0, 0, 0, 0];
+    let ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::Play {
+            game_state,
+            player_profile: player_profile_address(&program_id, &game_state, &player.pubkey()),
+            player_stats: player_stats_address(&program_id, &game_state, &player.pubkey()),
+            player: player.pubkey(),
+            player_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf,
+            system_program: system_program::id(),
+            agent_account: Some(agent_account),
+            game_config: None,
+            jackpot_token_account: None,
+            commission_schedule: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::Play { bets, room_card: Some(10000) }.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let pool_after = token_amount(&mut context, pool_token_account).await;
+    let player_after = token_amount(&mut context, player_token_account).await;
+    assert_eq!(pool_after + player_after, pool_after_withdraw + player_amount_before);
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.nonce, 43);
+    assert_eq!(state.total_pool, pool_after);
+
+    let stats_address = player_stats_address(&program_id, &game_state, &player.pubkey());
+    let stats_acc = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let mut stats_slice: &[u8] = &stats_acc.data;
+    let stats = slot_machine::PlayerStats::try_deserialize(&mut stats_slice).unwrap();
+    assert_eq!(stats.play_count, 1);
+    assert_eq!(stats.total_wagered, 100);
+    assert_eq!(stats.room_card, 10000);
+    assert_eq!(stats.bound_agent, agent.pubkey());
+}
@@ -0,0 +1,163 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account as SolanaAccount,
+    instruction::AccountMeta,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn migrate_agents_moves_inline_agent_to_pdas_and_redeem_uses_pda_stake() {
+    let program_id = slot_machine::id();
+    let mut program_test = ProgramTest::new("slot_machine", program_id, processor!(slot_machine_process));
+
+    let owner = Keypair::new();
+    let agent = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (agent_account, _) = Pubkey::find_program_address(
+        &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+        &program_id,
+    );
+    let (room_card_index, _) = Pubkey::find_program_address(
+        &[b"room_card", game_state.as_ref(), &10000u64.to_le_bytes()],
+        &program_id,
+    );
+
+    for who in [owner.pubkey(), agent.pubkey()] {
+        program_test.add_account(
+            who,
+            SolanaAccount {
+                lamports: 5_000_000_000,
+                data: vec![],
+                owner: system_program::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    let stake: u64 = 2_000_000;
+    let game_state_lamports: u64 = 1_000_000_000;
+    let game_state_data = legacy_game_state_data(
+        owner.pubkey(),
+        bump,
+        mint,
+        pool_token_account,
+        0,
+        Pubkey::default(),
+        agent.pubkey(),
+        stake,
+    );
+    program_test.add_account(
+        game_state,
+        SolanaAccount {
+            lamports: game_state_lamports,
+            data: game_state_data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut context = program_test.start_with_context().await;
+
+    let mut accounts = slot_machine::accounts::MigrateAgents {
+        game_state,
+        owner: owner.pubkey(),
+        system_program: system_program::id(),
+    }
+    .to_account_metas(None);
+    accounts.push(AccountMeta::new(agent_account, false));
+    accounts.push(AccountMeta::new(room_card_index, false));
+    let ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts,
+        data: slot_machine::instruction::MigrateAgents {}.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // 旧版账户扩容到新布局，租金差额由 owner 支付
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let top_up = rent.minimum_balance(8 + slot_machine::GameState::SPACE)
+        - rent.minimum_balance(8 + 4096);
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    assert_eq!(state_acc.data.len(), 8 + slot_machine::GameState::SPACE);
+    assert_eq!(state_acc.lamports, game_state_lamports - stake + top_up);
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert!(state.agents.is_empty());
+    assert_eq!(state.owner, owner.pubkey());
+    assert_eq!(state.bump, bump);
+    assert_eq!(state.game_id, 0);
+    assert_eq!(state.pool_mint, mint);
+    assert_eq!(state.pool_token_account, pool_token_account);
+    assert_eq!(state.nonce, 42);
+    assert_eq!(state.next_room_card, 10001);
+    assert_eq!(state.reel_weights, [LEGACY_WEIGHTS; 3]);
+    assert_eq!(state.payout_triple, [220, 180, 2000, 360, 450, 0]);
+    assert_eq!(state.min_bet, 1);
+    assert_eq!(state.vrf_pool[0].pending_play, Pubkey::default());
+
+    let agent_acc = context.banks_client.get_account(agent_account).await.unwrap().unwrap();
+    let mut agent_slice: &[u8] = &agent_acc.data;
+    let a = slot_machine::AgentAccount::try_deserialize(&mut agent_slice).unwrap();
+    assert_eq!(a.agent, agent.pubkey());
+    assert_eq!(a.stake, stake);
+    assert_eq!(a.room_card, 10000);
+    assert_eq!(a.commission, 777);
+    assert_eq!(a.last_settlement, 6);
+
+    let idx_acc = context.banks_client.get_account(room_card_index).await.unwrap().unwrap();
+    let mut idx_slice: &[u8] = &idx_acc.data;
+    let idx = slot_machine::RoomCardIndex::try_deserialize(&mut idx_slice).unwrap();
+    assert_eq!(idx.agent, agent.pubkey());
+    assert_eq!(idx.room_card, 10000);
+
+    let agent_lamports_before = context.banks_client.get_balance(agent.pubkey()).await.unwrap();
+    let idx_rent = idx_acc.lamports;
+    let ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::RedeemAgentStake {
+            game_state,
+            agent_account,
+            room_card_index,
+            agent: agent.pubkey(),
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::RedeemAgentStake {}.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    assert_eq!(
+        context.banks_client.get_balance(agent.pubkey()).await.unwrap(),
+        agent_lamports_before + stake + idx_rent
+    );
+    assert!(context.banks_client.get_account(room_card_index).await.unwrap().is_none());
+    let agent_acc = context.banks_client.get_account(agent_account).await.unwrap().unwrap();
+    let mut agent_slice: &[u8] = &agent_acc.data;
+    let a = slot_machine::AgentAccount::try_deserialize(&mut agent_slice).unwrap();
+    assert_eq!(a.stake, 0);
+    assert_eq!(a.room_card, 0);
+}
@@ -0,0 +1,118 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+fn game_state_address(program_id: &Pubkey, game_id: u64) -> Pubkey {
+    Pubkey::find_program_address(&[b"game_state", &game_id.to_le_bytes()], program_id).0
+}
+
+#[tokio::test]
+async fn games_are_isolated_by_game_id() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owners = [Keypair::new(), Keypair::new()];
+    let game_ids = [1u64, 2u64];
+    let games = game_ids.map(|id| game_state_address(&program_id, id));
+    let mints = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let pools = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+    for owner in &owners {
+        add_wallet(&mut program_test, owner.pubkey());
+    }
+    for i in 0..2 {
+        add_mint(&mut program_test, mints[i]);
+        add_token_account(
+            &mut program_test,
+            pools[i],
+            mints[i],
+            games[i],
+            1_000_000 * (i as u64 + 1),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+
+    let init_ix = |i: usize, game_state: Pubkey| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::Initialize {
+            game_state,
+            user: owners[i].pubkey(),
+            token_mint: mints[i],
+            pool_token_account: pools[i],
+            system_program: system_program::id(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::Initialize { game_id: game_ids[i] }.data(),
+    };
+
+    // game_id 与地址不符时无法初始化
+    let tx = Transaction::new_signed_with_payer(
+        &[init_ix(0, games[1])],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owners[0]],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    for i in 0..2 {
+        let tx = Transaction::new_signed_with_payer(
+            &[init_ix(i, games[i])],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &owners[i]],
+            context.last_blockhash,
+        );
+        context.banks_client.process_transaction(tx).await.unwrap();
+    }
+
+    let set_min_bet = |signer: Pubkey, game_state: Pubkey, min_bet: u64| {
+        solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::SetOwnerConfig {
+                game_state,
+                owner: signer,
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::SetMinBet { min_bet }.data(),
+        }
+    };
+
+    // 游戏 2 的所有者无权管理游戏 1
+    let tx = Transaction::new_signed_with_payer(
+        &[set_min_bet(owners[1].pubkey(), games[0], 7)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owners[1]],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[set_min_bet(owners[0].pubkey(), games[0], 7)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owners[0]],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let mut states = Vec::new();
+    for game in games {
+        let acc = context.banks_client.get_account(game).await.unwrap().unwrap();
+        let mut slice: &[u8] = &acc.data;
+        states.push(slot_machine::GameState::try_deserialize(&mut slice).unwrap());
+    }
+    for i in 0..2 {
+        assert_eq!(states[i].game_id, game_ids[i]);
+        assert_eq!(states[i].owner, owners[i].pubkey());
+        assert_eq!(states[i].pool_mint, mints[i]);
+        assert_eq!(states[i].total_pool, 1_000_000 * (i as u64 + 1));
+    }
+    assert_eq!(states[0].min_bet, 7);
+    assert_eq!(states[1].min_bet, 100);
+}
@@ -0,0 +1,146 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn owner_transfer_is_two_step_and_roles_are_scoped() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let new_owner = Keypair::new();
+    let config_admin = Keypair::new();
+    let treasurer = Keypair::new();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+
+    for who in [owner.pubkey(), new_owner.pubkey(), config_admin.pubkey(), treasurer.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let config_ix = |signer: Pubkey, data: Vec<u8>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: signer,
+        }
+        .to_account_metas(None),
+        data,
+    };
+    let set_weights = |signer: Pubkey, w: [u16; 6]| {
+        config_ix(signer, slot_machine::instruction::SetSymbolWeights { w }.data())
+    };
+    let accept_ix = |signer: Pubkey| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::AcceptOwner {
+            game_state,
+            new_owner: signer,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::AcceptOwner {}.data(),
+    };
+
+    // 所有者分配配置管理员与财务角色
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            config_ix(
+                owner.pubkey(),
+                slot_machine::instruction::SetRole {
+                    role: slot_machine::Role::ConfigAdmin,
+                    key: config_admin.pubkey(),
+                }
+                .data(),
+            ),
+            config_ix(
+                owner.pubkey(),
+                slot_machine::instruction::SetRole {
+                    role: slot_machine::Role::Treasurer,
+                    key: treasurer.pubkey(),
+                }
+                .data(),
+            ),
+        ],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // 财务不能修改权重，配置管理员可以
+    let weights = [2000, 2000, 500, 1500, 2000, 2000];
+    let tx = Transaction::new_signed_with_payer(
+        &[set_weights(treasurer.pubkey(), weights)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &treasurer],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[set_weights(config_admin.pubkey(), weights)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &config_admin],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    // 未被提名时不能接受所有权
+    let tx = Transaction::new_signed_with_payer(
+        &[accept_ix(new_owner.pubkey())],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &new_owner],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            config_ix(
+                owner.pubkey(),
+                slot_machine::instruction::ProposeOwner {
+                    new_owner: new_owner.pubkey(),
+                }
+                .data(),
+            ),
+            accept_ix(new_owner.pubkey()),
+        ],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner, &new_owner],
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.owner, new_owner.pubkey());
+    assert_eq!(state.pending_owner, Pubkey::default());
+    assert_eq!(state.config_admin, config_admin.pubkey());
+    assert_eq!(state.treasurer, treasurer.pubkey());
+    assert_eq!(state.reel_weights, [weights; 3]);
+
+    // 旧所有者失去权限
+    let tx = Transaction::new_signed_with_payer(
+        &[set_weights(owner.pubkey(), [2500, 2500, 250, 1600, 2150, 1000])],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+}
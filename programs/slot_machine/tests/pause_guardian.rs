@@ -0,0 +1,153 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn guardian_can_pause_but_only_owner_can_unpause() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let guardian = Keypair::new();
+    let agent = Keypair::new();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (agent_account, _) = Pubkey::find_program_address(
+        &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+        &program_id,
+    );
+    let (room_card_index, _) = Pubkey::find_program_address(
+        &[b"room_card", game_state.as_ref(), &10000u64.to_le_bytes()],
+        &program_id,
+    );
+
+    for who in [owner.pubkey(), guardian.pubkey(), agent.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let set_pause_ix = |signer: Pubkey, mask: u8| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: signer,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetPause { mask }.data(),
+    };
+    let guardian_pause_ix = |signer: Pubkey, mask: u8| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::GuardianPause {
+            game_state,
+            authority: signer,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::GuardianPause { mask }.data(),
+    };
+    let become_agent_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::BecomeAgent {
+            game_state,
+            agent_account,
+            room_card_index,
+            agent: agent.pubkey(),
+            system_program: system_program::id(),
+            funder: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::BecomeAgent {
+            stake_amount: 1_000_000,
+        }
+        .data(),
+    };
+
+    let set_guardian_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetGuardian {
+            guardian: guardian.pubkey(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[set_guardian_ix, guardian_pause_ix(guardian.pubkey(), slot_machine::PAUSE_AGENT_STAKE)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner, &guardian],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.guardian, guardian.pubkey());
+    assert_eq!(state.paused, slot_machine::PAUSE_AGENT_STAKE);
+
+    // 暂停代理质押后 become_agent 被拒绝
+    let tx = Transaction::new_signed_with_payer(
+        &[become_agent_ix.clone()],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // guardian 不能解除暂停，其他人也不能暂停
+    let tx = Transaction::new_signed_with_payer(
+        &[set_pause_ix(guardian.pubkey(), 0)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &guardian],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[guardian_pause_ix(agent.pubkey(), slot_machine::PAUSE_PLAY)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[set_pause_ix(owner.pubkey(), 0)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[become_agent_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent],
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.paused, 0);
+    assert_eq!(state.next_room_card, 10001);
+}
@@ -0,0 +1,145 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn room_card_binding_is_sticky_and_respects_cooldown() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+    let agents = [Keypair::new(), Keypair::new()];
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (player_stats, _) = Pubkey::find_program_address(
+        &[b"player_stats", game_state.as_ref(), player.pubkey().as_ref()],
+        &program_id,
+    );
+
+    for who in [owner.pubkey(), player.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let mut agent_accounts = Vec::new();
+    for (i, agent) in agents.iter().enumerate() {
+        let (agent_account, agent_bump) = Pubkey::find_program_address(
+            &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+            &program_id,
+        );
+        let agent_state = slot_machine::AgentAccount {
+            agent: agent.pubkey(),
+            bump: agent_bump,
+            stake: 1_000_000,
+            room_card: 10_000 + i as u64,
+            commission: 0,
+            stake_time: 0,
+            last_settlement: 0,
+            is_active: true,
+            stake_funder: Pubkey::default(),
+            referred_volume: 0,
+            parent: Pubkey::default(),
+            commission_override: None,
+            period_start: 0,
+            period_volume: 0,
+            prev_period_volume: 0,
+        };
+        add_program_account(
+            &mut program_test,
+            agent_account,
+            slot_machine::AgentAccount::SPACE,
+            &agent_state,
+        );
+        agent_accounts.push(agent_account);
+    }
+
+    let state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let bind_ix = |agent_account: Pubkey, bound_agent_account: Option<Pubkey>, room_card: u64| {
+        solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::BindRoomCard {
+                game_state,
+                player_stats,
+                agent_account,
+                bound_agent_account,
+                player: player.pubkey(),
+                system_program: system_program::id(),
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::BindRoomCard { room_card }.data(),
+        }
+    };
+    let read_stats = |data: Vec<u8>| {
+        let mut slice: &[u8] = &data;
+        slot_machine::PlayerStats::try_deserialize(&mut slice).unwrap()
+    };
+
+    // 房卡必须属于传入的代理商
+    let tx = Transaction::new_signed_with_payer(
+        &[bind_ix(agent_accounts[0], None, 10_001)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let tx = Transaction::new_signed_with_payer(
+        &[bind_ix(agent_accounts[0], None, 10_000)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(player_stats).await.unwrap().unwrap();
+    let stats = read_stats(acc.data);
+    assert_eq!(stats.player, player.pubkey());
+    assert_eq!(stats.room_card, 10_000);
+    assert_eq!(stats.bound_agent, agents[0].pubkey());
+
+    // 默认绑定永久有效，原代理商仍活跃时无法换绑
+    let tx = Transaction::new_signed_with_payer(
+        &[bind_ix(agent_accounts[1], Some(agent_accounts[0]), 10_001)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &player],
+        context.last_blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // 所有者将冷却设为 0 后可换绑
+    let cooldown_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetRebindCooldown { cooldown: 0 }.data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[cooldown_ix, bind_ix(agent_accounts[1], None, 10_001)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner, &player],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let acc = context.banks_client.get_account(player_stats).await.unwrap().unwrap();
+    let stats = read_stats(acc.data);
+    assert_eq!(stats.room_card, 10_001);
+    assert_eq!(stats.bound_agent, agents[1].pubkey());
+}
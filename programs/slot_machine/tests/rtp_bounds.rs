@@ -0,0 +1,168 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn rtp_view_matches_tables_and_bounds_reject_overpaying_config() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+
+    for who in [owner.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let mut state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    state.commission_rate = 4;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let rtp_ix = |odds: Option<slot_machine::Odds>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::ViewGameState {
+            game_state,
+            game_config: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::ComputeRtp { odds, lines: None }.data(),
+    };
+    let config_ix = |data: Vec<u8>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data,
+    };
+
+    // 默认赔率表：Double 概率 q = 1 - 0.9³，最多 4 次自动转轮
+    let tx = Transaction::new_signed_with_payer(
+        &[rtp_ix(None)],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    let result = context.banks_client.process_transaction_with_metadata(tx).await.unwrap();
+    result.result.unwrap();
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    let report = slot_machine::RtpReport::try_from_slice(&return_data.data).unwrap();
+    assert_eq!(report.config_version, 0);
+    assert_eq!(report.per_symbol_bps, [2617, 2048, 44, 1313, 2856, 0]);
+    assert_eq!(report.min_bps, 44);
+    assert_eq!(report.max_bps, 2856);
+
+    // 试算一个超过 100% 的赔率表
+    let overpaying = slot_machine::Odds {
+        reel_weights: [[2500, 2500, 250, 1600, 2150, 1000]; 3],
+        payout_triple: [5000, 180, 2000, 360, 450, 0],
+        payout_double: [65, 50, 100, 75, 85, 0],
+        max_auto_spins: 5,
+        layout: slot_machine::Layout::Classic,
+        roles: slot_machine::DEFAULT_SYMBOL_ROLES,
+        scatter_pays: [0; 4],
+        multipliers: slot_machine::DEFAULT_MULTIPLIERS,
+        trigger_count: 1,
+        jackpot_bps: 0,
+        jackpot_trigger: slot_machine::JackpotTrigger::Disabled,
+    };
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[rtp_ix(Some(overpaying))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        blockhash,
+    );
+    let result = context.banks_client.process_transaction_with_metadata(tx).await.unwrap();
+    result.result.unwrap();
+    let return_data = result.metadata.unwrap().return_data.unwrap();
+    let report = slot_machine::RtpReport::try_from_slice(&return_data.data).unwrap();
+    assert!(report.per_symbol_bps[0] > 10_000);
+
+    let tx = Transaction::new_signed_with_payer(
+        &[config_ix(
+            slot_machine::instruction::SetPayoutTriple {
+                p: overpaying.payout_triple,
+            }
+            .data(),
+        )],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    // 收紧下限后，低于下限的修改同样被拒绝；上限不得超过 100%
+    let tx = Transaction::new_signed_with_payer(
+        &[config_ix(
+            slot_machine::instruction::SetRtpBounds {
+                min_bps: 0,
+                max_bps: 10_001,
+            }
+            .data(),
+        )],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+    let tx = Transaction::new_signed_with_payer(
+        &[
+            config_ix(
+                slot_machine::instruction::SetRtpBounds {
+                    min_bps: 40,
+                    max_bps: 9_500,
+                }
+                .data(),
+            ),
+            config_ix(
+                slot_machine::instruction::SetPayoutDouble {
+                    p: [65, 50, 80, 75, 85, 0],
+                }
+                .data(),
+            ),
+        ],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.payout_triple, [220, 180, 2000, 360, 450, 0]);
+    assert_eq!(state.payout_double, [65, 50, 100, 75, 85, 0]);
+    assert!(!state.queued_config.pending);
+    assert_eq!(state.rtp_max_bps, 10_000);
+
+    // 佣金率 + RTP 上限须严格低于 100%：上限 9_700 时 4% 佣金已超出庄家优势
+    let tx = Transaction::new_signed_with_payer(
+        &[config_ix(
+            slot_machine::instruction::SetRtpBounds {
+                min_bps: 0,
+                max_bps: 9_700,
+            }
+            .data(),
+        )],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        blockhash,
+    );
+    assert!(context.banks_client.process_transaction(tx).await.is_err());
+}
@@ -0,0 +1,218 @@
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program::{
+    hash::hash,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    instruction::AccountMeta,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+use spl_token::state::Account as TokenAccount;
+
+mod common;
+use common::*;
+
+fn commitment_address(program_id: &Pubkey, game_state: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"seed_commitment", game_state.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+    .0
+}
+
+#[tokio::test]
+async fn settle_many_settles_batch_and_pays_keeper_fee() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let player = Keypair::new();
+    let keeper = Keypair::new();
+
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let player_token_account = Pubkey::new_unique();
+    let keeper_token_account = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let player_profile = player_profile_address(&program_id, &game_state, &player.pubkey());
+    let player_stats = player_stats_address(&program_id, &game_state, &player.pubkey());
+
+    add_mint(&mut program_test, mint);
+
+    for who in [owner.pubkey(), player.pubkey(), keeper.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let player_amount_before: u64 = 10_000_000;
+    for (account, owner_key, amount) in [
+        (player_token_account, player.pubkey(), player_amount_before),
+        (keeper_token_account, keeper.pubkey(), 0),
+        (pool_token_account, game_state, 0),
+    ] {
+        add_token_account(&mut program_test, account, mint, owner_key, amount);
+    }
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.randomness_source = slot_machine::RandomnessSource::CommitReveal;
+    state.keeper_fee_bps = 100;
+    state.payout_triple = [0, 0, 0, 0, 0, 0];
+    state.payout_double = [0, 0, 0, 0, 0, 0];
+    state.max_auto_spins = 1;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let house_seeds = [[42u8; 32], [43u8; 32]];
+    let bet: u64 = 1_000_000;
+    for (i, house_seed) in house_seeds.iter().enumerate() {
+        let commitment = commitment_address(&program_id, &game_state, i as u64);
+        let post_ix = solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::PostSeedCommitment {
+                game_state,
+                commitment,
+                owner: owner.pubkey(),
+                system_program: system_program::id(),
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::PostSeedCommitment {
+                seed_hash: hash(house_seed).to_bytes(),
+            }
+            .data(),
+        };
+        let request_ix = solana_sdk::instruction::Instruction {
+            program_id,
+            accounts: slot_machine::accounts::RequestPlay {
+                game_state,
+                player_profile,
+                player_stats,
+                pending_play: pending_play_address(&program_id, &game_state, &player.pubkey(), i as u64),
+                player: player.pubkey(),
+                player_token_account,
+                pool_token_account,
+                token_program: spl_token::id(),
+                vrf: None,
+                oracle_queue: None,
+                queue_authority: None,
+                data_buffer: None,
+                permission: None,
+                escrow: None,
+                payer_wallet: None,
+                recent_blockhashes: None,
+                program_state: None,
+                switchboard_program: None,
+                commitment: Some(commitment),
+                agent_account: None,
+                game_config: None,
+                system_program: system_program::id(),
+            }
+            .to_account_metas(None),
+            data: slot_machine::instruction::RequestPlay {
+                bets: [bet, 0, 0, 0, 0, 0],
+                room_card: None,
+                client_seed: [i as u8; 32],
+                switchboard_state_bump: 0,
+                permission_bump: 0,
+            }
+            .data(),
+        };
+        let tx = Transaction::new_signed_with_payer(
+            &[post_ix, request_ix],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &owner, &player],
+            context.last_blockhash,
+        );
+        context.banks_client.process_transaction(tx).await.unwrap();
+    }
+
+    let profile_acc = context.banks_client.get_account(player_profile).await.unwrap().unwrap();
+    let mut profile_slice: &[u8] = &profile_acc.data;
+    let profile = slot_machine::PlayerProfile::try_deserialize(&mut profile_slice).unwrap();
+    assert_eq!(profile.next_play_seq, 2);
+    assert_eq!(profile.open_plays, 2);
+
+    let mut accounts = slot_machine::accounts::SettleMany {
+        game_state,
+        pool_token_account,
+        keeper: keeper.pubkey(),
+        keeper_token_account: Some(keeper_token_account),
+        token_program: spl_token::id(),
+        jackpot_token_account: None,
+        commission_schedule: None,
+    }
+    .to_account_metas(None);
+    for i in 0..2u64 {
+        accounts.push(AccountMeta::new(pending_play_address(&program_id, &game_state, &player.pubkey(), i), false));
+        accounts.push(AccountMeta::new(player_profile, false));
+        accounts.push(AccountMeta::new(player_stats, false));
+        accounts.push(AccountMeta::new(player.pubkey(), false));
+        accounts.push(AccountMeta::new(player_token_account, false));
+        accounts.push(AccountMeta::new(commitment_address(&program_id, &game_state, i), false));
+    }
+    let settle_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts,
+        data: slot_machine::instruction::SettleMany {
+            reveals: house_seeds.to_vec(),
+        }
+        .data(),
+    };
+    let tx = Transaction::new_signed_with_payer(
+        &[settle_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &keeper],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    for i in 0..2u64 {
+        let pending = pending_play_address(&program_id, &game_state, &player.pubkey(), i);
+        assert!(context.banks_client.get_account(pending).await.unwrap().is_none());
+        let commitment_acc = context
+            .banks_client
+            .get_account(commitment_address(&program_id, &game_state, i))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut commitment_slice: &[u8] = &commitment_acc.data;
+        let c = slot_machine::SeedCommitment::try_deserialize(&mut commitment_slice).unwrap();
+        assert!(c.revealed);
+    }
+
+    let profile_acc = context.banks_client.get_account(player_profile).await.unwrap().unwrap();
+    let mut profile_slice: &[u8] = &profile_acc.data;
+    let profile = slot_machine::PlayerProfile::try_deserialize(&mut profile_slice).unwrap();
+    assert_eq!(profile.open_plays, 0);
+
+    let stats_acc = context.banks_client.get_account(player_stats).await.unwrap().unwrap();
+    let mut stats_slice: &[u8] = &stats_acc.data;
+    let stats = slot_machine::PlayerStats::try_deserialize(&mut stats_slice).unwrap();
+    assert_eq!(stats.play_count, 2);
+    assert_eq!(stats.total_wagered, 2 * bet);
+    assert_eq!(stats.total_won, 0);
+    assert_eq!(stats.recent_head, 2);
+    assert_eq!(stats.recent[1].total_bet, bet);
+
+    // 两局各 1% keeper 奖励，由奖池承担；赔率全为 0，玩家无派彩
+    let fee = 2 * bet / 100;
+    let keeper_acc = context.banks_client.get_account(keeper_token_account).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&keeper_acc.data).unwrap().amount, fee);
+
+    let pool_acc = context.banks_client.get_account(pool_token_account).await.unwrap().unwrap();
+    assert_eq!(TokenAccount::unpack(&pool_acc.data).unwrap().amount, 2 * bet - fee);
+
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.total_pool, 2 * bet - fee);
+
+    let player_acc = context.banks_client.get_account(player_token_account).await.unwrap().unwrap();
+    assert_eq!(
+        TokenAccount::unpack(&player_acc.data).unwrap().amount,
+        player_amount_before - 2 * bet
+    );
+}
@@ -0,0 +1,159 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account as SolanaAccount,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+async fn agent_state(
+    context: &mut ProgramTestContext,
+    address: Pubkey,
+) -> slot_machine::AgentAccount {
+    let acc = context.banks_client.get_account(address).await.unwrap().unwrap();
+    let mut slice: &[u8] = &acc.data;
+    slot_machine::AgentAccount::try_deserialize(&mut slice).unwrap()
+}
+
+// 其他钱包追加质押不会覆盖首个代付人，首个代付人下注仍不计佣金
+#[tokio::test]
+async fn top_up_from_another_wallet_keeps_original_stake_funder() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let agent = Keypair::new();
+    let funder = Keypair::new();
+    let other_funder = Keypair::new();
+    let mint = Pubkey::new_unique();
+    let pool_token_account = Pubkey::new_unique();
+    let funder_token_account = Pubkey::new_unique();
+    let vrf = Pubkey::new_unique();
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (agent_account, _) = Pubkey::find_program_address(
+        &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+        &program_id,
+    );
+    let (room_card_index, _) = Pubkey::find_program_address(
+        &[b"room_card", game_state.as_ref(), &10000u64.to_le_bytes()],
+        &program_id,
+    );
+
+    for who in [owner.pubkey(), agent.pubkey(), funder.pubkey(), other_funder.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+    add_mint(&mut program_test, mint);
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, 1_000_000_000);
+    add_token_account(&mut program_test, funder_token_account, mint, funder.pubkey(), 1_000_000);
+    program_test.add_account(
+        vrf,
+        SolanaAccount {
+            lamports: 1_000_000_000,
+            data: vec![7u8; 64],
+            owner: slot_machine::SWITCHBOARD_V2_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let mut state = game_state_fixture(owner.pubkey(), bump, mint, pool_token_account);
+    state.total_pool = 1_000_000_000;
+    state.rtp_max_bps = 8_800;
+    state.vrf = vrf;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let become_agent_ix = |funder: Option<Pubkey>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::BecomeAgent {
+            game_state,
+            agent_account,
+            room_card_index,
+            agent: agent.pubkey(),
+            system_program: system_program::id(),
+            funder,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::BecomeAgent {
+            stake_amount: 1_000_000,
+        }
+        .data(),
+    };
+    let play_ix = solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::Play {
+            game_state,
+            player_profile: player_profile_address(&program_id, &game_state, &funder.pubkey()),
+            player_stats: player_stats_address(&program_id, &game_state, &funder.pubkey()),
+            player: funder.pubkey(),
+            player_token_account: funder_token_account,
+            pool_token_account,
+            token_program: spl_token::id(),
+            vrf,
+            system_program: system_program::id(),
+            agent_account: Some(agent_account),
+            game_config: None,
+            jackpot_token_account: None,
+            commission_schedule: None,
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::Play {
+            bets: [100, 0, 0, 0, 0, 0],
+            room_card: Some(10000),
+        }
+        .data(),
+    };
+
+    // 代理商自行质押，funder 此时只是普通玩家，带房卡下注完成绑定并正常计佣
+    let tx = Transaction::new_signed_with_payer(
+        &[become_agent_ix(None), play_ix.clone()],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent, &funder],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let before = agent_state(&mut context, agent_account).await;
+    assert_eq!(before.stake_funder, Pubkey::default());
+    assert_eq!(before.referred_volume, 100);
+
+    // funder 代付追加质押后，另一个钱包再追加质押
+    let tx = Transaction::new_signed_with_payer(
+        &[become_agent_ix(Some(funder.pubkey()))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent, &funder],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[become_agent_ix(Some(other_funder.pubkey()))],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &agent, &other_funder],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let a = agent_state(&mut context, agent_account).await;
+    assert_eq!(a.stake, 3_000_000);
+    assert_eq!(a.stake_funder, funder.pubkey());
+
+    // funder 仍视为自推广：再次下注不计佣金、不计推广量
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(
+        &[play_ix],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &funder],
+        blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+
+    let a = agent_state(&mut context, agent_account).await;
+    assert_eq!(a.commission, before.commission);
+    assert_eq!(a.referred_volume, before.referred_volume);
+}
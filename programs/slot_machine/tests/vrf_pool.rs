@@ -0,0 +1,87 @@
+use anchor_lang::prelude::*;
+use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn vrf_pool_keeps_in_flight_slots_and_rejects_bad_sets() {
+    let program_id = slot_machine::id();
+    let mut program_test = new_program_test();
+
+    let owner = Keypair::new();
+    let busy_vrf = Pubkey::new_unique();
+    let busy_play = Pubkey::new_unique();
+    let mut vrf_pool = [slot_machine::VrfSlot::default(); slot_machine::MAX_VRF_POOL];
+    vrf_pool[0] = slot_machine::VrfSlot {
+        vrf: busy_vrf,
+        pending_play: busy_play,
+        counter: 3,
+        request_slot: 1,
+    };
+
+    let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+
+    for who in [owner.pubkey()] {
+        add_wallet(&mut program_test, who);
+    }
+
+    let mut state = game_state_fixture(
+        owner.pubkey(),
+        bump,
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    state.vrf_pool = vrf_pool;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let mut context = program_test.start_with_context().await;
+
+    let pool_ix = |vrfs: Vec<Pubkey>| solana_sdk::instruction::Instruction {
+        program_id,
+        accounts: slot_machine::accounts::SetOwnerConfig {
+            game_state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: slot_machine::instruction::SetVrfPool { vrfs }.data(),
+    };
+    let other = Pubkey::new_unique();
+
+    // 重复、超过上限，或移除仍有进行中请求的 VRF 均被拒绝
+    for vrfs in [
+        vec![busy_vrf, other, other],
+        (0..=slot_machine::MAX_VRF_POOL).map(|_| Pubkey::new_unique()).collect(),
+        vec![other],
+    ] {
+        let tx = Transaction::new_signed_with_payer(
+            &[pool_ix(vrfs)],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &owner],
+            context.last_blockhash,
+        );
+        assert!(context.banks_client.process_transaction(tx).await.is_err());
+    }
+
+    // 保留的 VRF 沿用原槽位状态
+    let tx = Transaction::new_signed_with_payer(
+        &[pool_ix(vec![other, busy_vrf])],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, &owner],
+        context.last_blockhash,
+    );
+    context.banks_client.process_transaction(tx).await.unwrap();
+    let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let mut state_slice: &[u8] = &state_acc.data;
+    let state = slot_machine::GameState::try_deserialize(&mut state_slice).unwrap();
+    assert_eq!(state.vrf_pool[0].vrf, other);
+    assert_eq!(state.vrf_pool[0].pending_play, Pubkey::default());
+    assert_eq!(state.vrf_pool[1].vrf, busy_vrf);
+    assert_eq!(state.vrf_pool[1].pending_play, busy_play);
+    assert_eq!(state.vrf_pool[1].counter, 3);
+    assert_eq!(state.vrf_pool[2].vrf, Pubkey::default());
+}
@@ -1,61 +1,19 @@
 use anchor_lang::prelude::*;
 use anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas};
-use solana_program::{
-    entrypoint::ProgramResult,
-    program_option::COption,
-    program_pack::Pack,
-};
-use solana_program_test::*;
+use solana_program::program_pack::Pack;
 use solana_sdk::{
-    account::Account as SolanaAccount,
     signature::{Keypair, Signer},
     transaction::Transaction,
 };
-use spl_token::state::{Account as TokenAccount, AccountState, Mint};
-
-fn slot_machine_process<'a, 'b, 'c, 'd>(
-    program_id: &'a Pubkey,
-    accounts: &'b [AccountInfo<'c>],
-    data: &'d [u8],
-) -> ProgramResult {
-    let accounts: &'c [AccountInfo<'c>] = unsafe { std::mem::transmute(accounts) };
-    slot_machine::entry(program_id, accounts, data)
-}
+use spl_token::state::Account as TokenAccount;
 
-fn pack_mint(mint_authority: Pubkey, decimals: u8) -> Vec<u8> {
-    let mint = Mint {
-        mint_authority: COption::Some(mint_authority),
-        supply: 0,
-        decimals,
-        is_initialized: true,
-        freeze_authority: COption::None,
-    };
-    let mut data = vec![0u8; Mint::LEN];
-    Mint::pack(mint, &mut data).unwrap();
-    data
-}
-
-fn pack_token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
-    let token = TokenAccount {
-        mint,
-        owner,
-        amount,
-        delegate: COption::None,
-        state: AccountState::Initialized,
-        is_native: COption::None,
-        delegated_amount: 0,
-        close_authority: COption::None,
-    };
-    let mut data = vec![0u8; TokenAccount::LEN];
-    TokenAccount::pack(token, &mut data).unwrap();
-    data
-}
+mod common;
+use common::*;
 
 #[tokio::test]
 async fn withdraw_commission_transfers_exact_amount_and_resets_state() {
     let program_id = slot_machine::id();
-    let mut program_test = ProgramTest::new("slot_machine", program_id, processor!(slot_machine_process));
-    program_test.add_program("spl_token", spl_token::id(), processor!(spl_token::processor::Processor::process));
+    let mut program_test = new_program_test();
 
     let agent = Keypair::new();
     let mint = Pubkey::new_unique();
@@ -63,86 +21,45 @@ async fn withdraw_commission_transfers_exact_amount_and_resets_state() {
     let agent_token_account = Pubkey::new_unique();
 
     let (game_state, bump) = Pubkey::find_program_address(&[b"game_state"], &program_id);
+    let (agent_account, agent_bump) = Pubkey::find_program_address(
+        &[b"agent", game_state.as_ref(), agent.pubkey().as_ref()],
+        &program_id,
+    );
 
     let commission_amount: u64 = 123_456;
     let pool_amount_before: u64 = 1_000_000;
 
-    program_test.add_account(
-        mint,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_mint(Pubkey::new_unique(), 6),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
-
-    program_test.add_account(
-        pool_token_account,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_token_account(mint, game_state, pool_amount_before),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
-
-    program_test.add_account(
-        agent_token_account,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: pack_token_account(mint, agent.pubkey(), 0),
-            owner: spl_token::id(),
-            executable: false,
-            rent_epoch: 0,
-        },
-    );
-
-    let mut game_state_data = vec![0u8; 8 + slot_machine::GameState::SPACE];
-    {
-        let mut cursor = std::io::Cursor::new(&mut game_state_data[..]);
-        let state = slot_machine::GameState {
-            owner: Pubkey::new_unique(),
-            bump,
-            pool_mint: mint,
-            pool_token_account,
-            total_pool: pool_amount_before,
-            nonce: 0,
-            agents: vec![slot_machine::Agent {
-                pubkey: agent.pubkey(),
-                stake: 0,
-                room_card: 10000,
-                commission: commission_amount as i64,
-                stake_time: 0,
-                last_settlement: -86_401,
-                is_active: true,
-            }],
-            next_room_card: 10001,
-            commission_rate: 10,
-            stake_threshold: 1_000_000,
-            settlement_period: 86_400,
-            vrf: Pubkey::default(),
-            vrf_result_offset: 0,
-            symbol_weights: [2500, 2500, 250, 1600, 2150, 1000],
-            payout_triple: [220, 180, 2000, 360, 450, 0],
-            payout_double: [65, 50, 100, 75, 85, 0],
-            max_auto_spins: 5,
-            min_bet: 1,
-        };
-        state.try_serialize(&mut cursor).unwrap();
-    }
-
-    program_test.add_account(
-        game_state,
-        SolanaAccount {
-            lamports: 1_000_000_000,
-            data: game_state_data,
-            owner: program_id,
-            executable: false,
-            rent_epoch: 0,
-        },
+    add_mint(&mut program_test, mint);
+    add_token_account(&mut program_test, pool_token_account, mint, game_state, pool_amount_before);
+    add_token_account(&mut program_test, agent_token_account, mint, agent.pubkey(), 0);
+
+    let mut state = game_state_fixture(Pubkey::new_unique(), bump, mint, pool_token_account);
+    state.total_pool = pool_amount_before;
+    state.next_room_card = 10001;
+    add_game_state(&mut program_test, game_state, &state);
+
+    let agent_state = slot_machine::AgentAccount {
+        agent: agent.pubkey(),
+        bump: agent_bump,
+        stake: 0,
+        room_card: 10000,
+        commission: commission_amount as i64,
+        stake_time: 0,
+        last_settlement: -86_401,
+        is_active: true,
+        stake_funder: Pubkey::default(),
+        referred_volume: 0,
+        parent: Pubkey::default(),
+        commission_override: None,
+        period_start: 0,
+        period_volume: 0,
+        prev_period_volume: 0,
+    };
+    add_program_account(
+        &mut program_test,
+        agent_account,
+        slot_machine::AgentAccount::SPACE,
+        &agent_state,
     );
 
     let mut context = program_test.start_with_context().await;
@@ -151,6 +68,7 @@ async fn withdraw_commission_transfers_exact_amount_and_resets_state() {
         program_id,
         accounts: slot_machine::accounts::WithdrawCommission {
             game_state,
+            agent_account,
             agent: agent.pubkey(),
             pool_token_account,
             agent_token_account,
@@ -171,6 +89,7 @@ async fn withdraw_commission_transfers_exact_amount_and_resets_state() {
     let pool_acc = context.banks_client.get_account(pool_token_account).await.unwrap().unwrap();
     let agent_acc = context.banks_client.get_account(agent_token_account).await.unwrap().unwrap();
     let state_acc = context.banks_client.get_account(game_state).await.unwrap().unwrap();
+    let agent_acc_after = context.banks_client.get_account(agent_account).await.unwrap().unwrap();
 
     let pool_token = TokenAccount::unpack(&pool_acc.data).unwrap();
     let agent_token = TokenAccount::unpack(&agent_acc.data).unwrap();
@@ -181,7 +100,8 @@ async fn withdraw_commission_transfers_exact_amount_and_resets_state() {
     let mut state_data_slice: &[u8] = &state_acc.data;
     let state = slot_machine::GameState::try_deserialize(&mut state_data_slice).unwrap();
     assert_eq!(state.total_pool, pool_amount_before - commission_amount);
-    let agent_state = state.agents.iter().find(|a| a.pubkey == agent.pubkey()).unwrap();
+    let mut agent_data_slice: &[u8] = &agent_acc_after.data;
+    let agent_state = slot_machine::AgentAccount::try_deserialize(&mut agent_data_slice).unwrap();
     assert_eq!(agent_state.commission, 0);
     assert!(agent_state.last_settlement >= 0);
 }
@@ -58,6 +58,18 @@ function formatBaseUnits(amount, decimals) {
   return (neg ? "-" : "") + whole.toString() + (fracStr.length ? "." + fracStr : "");
 }
 
+// game_state PDA：game_id 0 沿用 ["game_state"]，其余追加 game_id 的 u64 LE 种子
+function deriveGameState(programId, gameId) {
+  const id = BigInt(gameId);
+  const seeds = [Buffer.from("game_state")];
+  if (id !== 0n) {
+    const b = Buffer.alloc(8);
+    b.writeBigUInt64LE(id);
+    seeds.push(b);
+  }
+  return web3.PublicKey.findProgramAddressSync(seeds, programId)[0];
+}
+
 function parseArgs(argv) {
   const out = {
     rpcUrl: DEFAULTS.rpcUrl,
@@ -75,6 +87,7 @@ function parseArgs(argv) {
     if (a === "--rpc") out.rpcUrl = argv[++i];
     else if (a === "--program-id") out.programId = argv[++i];
     else if (a === "--game-state") out.gameState = argv[++i];
+    else if (a === "--game-id") out.gameId = argv[++i];
     else if (a === "--pool") out.poolTokenAccount = argv[++i];
     else if (a === "--mint") out.mint = argv[++i];
     else if (a === "--room-card") out.roomCard = argv[++i];
@@ -99,6 +112,7 @@ function printUsage() {
     "  --rpc <url>           默认 https://api.devnet.solana.com",
     "  --program-id <pk>     默认 slot_machine program id",
     "  --game-state <pk>     默认当前 game_state PDA",
+    "  --game-id <u64>       按 game_id 派生 game_state PDA（优先于 --game-state）",
     "  --pool <pk>           默认当前奖池 token account",
     "  --mint <pk>           默认当前赔付代币 mint",
     "  --room-card <u64>     可选房卡号（不填则 None）",
@@ -157,7 +171,8 @@ async function main() {
   const connection = new web3.Connection(args.rpcUrl, { commitment: "confirmed" });
 
   const programId = new web3.PublicKey(args.programId);
-  const gameState = new web3.PublicKey(args.gameState);
+  const gameState =
+    args.gameId != null ? deriveGameState(programId, args.gameId) : new web3.PublicKey(args.gameState);
   const poolTokenAccount = new web3.PublicKey(args.poolTokenAccount);
   const mint = new web3.PublicKey(args.mint);
   const tokenProgramId = token.TOKEN_PROGRAM_ID;
@@ -6,6 +6,18 @@ const anchor = require("@coral-xyz/anchor");
 
 const SYMBOL_NAMES = ["CHERRY", "LEMON", "SEVEN", "BELL", "STAR", "DOUBLE"];
 
+// game_state PDA：game_id 0 沿用 ["game_state"]，其余追加 game_id 的 u64 LE 种子
+function deriveGameState(programId, gameId) {
+  const id = BigInt(gameId);
+  const seeds = [Buffer.from("game_state")];
+  if (id !== 0n) {
+    const b = Buffer.alloc(8);
+    b.writeBigUInt64LE(id);
+    seeds.push(b);
+  }
+  return web3.PublicKey.findProgramAddressSync(seeds, programId)[0];
+}
+
 function parseArgs(argv) {
   const out = {
     rpcUrl: "https://api.devnet.solana.com",
@@ -19,6 +31,7 @@ function parseArgs(argv) {
     if (a === "--rpc") out.rpcUrl = argv[++i];
     else if (a === "--program-id") out.programId = argv[++i];
     else if (a === "--game-state") out.gameState = argv[++i];
+    else if (a === "--game-id") out.gameId = argv[++i];
     else if (a === "--trials") out.trials = Number(argv[++i]);
     else if (a === "--bet") out.bet = String(argv[++i]);
     else if (a === "--help" || a === "-h") out.help = true;
@@ -140,7 +153,7 @@ function simulateSession(cfg, betSymbolIdx, betBaseUnits, trials) {
 async function main() {
   const args = parseArgs(process.argv.slice(2));
   if (args.help) {
-    console.log("用法：node scripts/slot_machine_ev_simulate.js [--trials N] [--bet 金额] [--rpc url]");
+    console.log("用法：node scripts/slot_machine_ev_simulate.js [--trials N] [--bet 金额] [--rpc url] [--game-id N]");
     process.exit(0);
   }
 
@@ -149,7 +162,8 @@ async function main() {
 
   const connection = new web3.Connection(args.rpcUrl, { commitment: "confirmed" });
   const programId = new web3.PublicKey(args.programId);
-  const gameStatePk = new web3.PublicKey(args.gameState);
+  const gameStatePk =
+    args.gameId != null ? deriveGameState(programId, args.gameId) : new web3.PublicKey(args.gameState);
 
   const coder = new anchor.BorshCoder(idl);
   const raw = await connection.getAccountInfo(gameStatePk, "confirmed");
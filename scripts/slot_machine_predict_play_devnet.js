@@ -34,6 +34,18 @@ function readKeypair(filePath) {
   return web3.Keypair.fromSecretKey(secret);
 }
 
+// game_state PDA：game_id 0 沿用 ["game_state"]，其余追加 game_id 的 u64 LE 种子
+function deriveGameState(programId, gameId) {
+  const id = BigInt(gameId);
+  const seeds = [Buffer.from("game_state")];
+  if (id !== 0n) {
+    const b = Buffer.alloc(8);
+    b.writeBigUInt64LE(id);
+    seeds.push(b);
+  }
+  return web3.PublicKey.findProgramAddressSync(seeds, programId)[0];
+}
+
 function parseArgs(argv) {
   const out = { ...DEFAULTS, help: false };
   for (let i = 0; i < argv.length; i++) {
@@ -41,6 +53,7 @@ function parseArgs(argv) {
     if (a === "--rpc") out.rpcUrl = argv[++i];
     else if (a === "--program-id") out.programId = argv[++i];
     else if (a === "--game-state") out.gameState = argv[++i];
+    else if (a === "--game-id") out.gameId = argv[++i];
     else if (a === "--pool") out.poolTokenAccount = argv[++i];
     else if (a === "--mint") out.mint = argv[++i];
     else if (a === "--search-plays") out.searchPlays = Number(argv[++i]);
@@ -73,6 +86,7 @@ function printUsage() {
       `  --rpc <url>              默认 ${DEFAULTS.rpcUrl}`,
       `  --program-id <pk>        默认 ${DEFAULTS.programId}`,
       `  --game-state <pk>        默认 ${DEFAULTS.gameState}`,
+      "  --game-id <u64>          按 game_id 派生 game_state PDA（优先于 --game-state）",
       `  --pool <pk>              默认 ${DEFAULTS.poolTokenAccount}`,
       `  --mint <pk>              默认 ${DEFAULTS.mint}`,
       `  --search-plays <n>        默认 ${DEFAULTS.searchPlays}（最多向前推演多少次 play）`,
@@ -572,7 +586,8 @@ async function main() {
   const connection = new web3.Connection(args.rpcUrl, { commitment: "confirmed" });
 
   const programId = new web3.PublicKey(args.programId);
-  const gameStatePk = new web3.PublicKey(args.gameState);
+  const gameStatePk =
+    args.gameId != null ? deriveGameState(programId, args.gameId) : new web3.PublicKey(args.gameState);
   const poolTokenAccount = new web3.PublicKey(args.poolTokenAccount);
   const mint = new web3.PublicKey(args.mint);
 
@@ -9,6 +9,7 @@ import {
 } from "@solana/spl-token";
 import { Keypair, LAMPORTS_PER_SOL } from "@solana/web3.js";
 import BN from "bn.js";
+import { createHash, randomBytes } from "crypto";
 import fs from 'fs';
 import path from 'path';
 
@@ -18,6 +19,12 @@ describe("slot_machine_optimized", () => {
 
   let program: Program;
   const programId = new anchor.web3.PublicKey("8cozexydPUo9jTBT7PRWVe5Qmi3bpkjgQuPo2ZaTKHus");
+  // game_id 0 的 game_state PDA 不带 game_id 种子，与多游戏支持之前的地址一致
+  const gameId = new BN(0);
+  const gameStateSeeds = (id: BN) =>
+    id.isZero()
+      ? [Buffer.from("game_state")]
+      : [Buffer.from("game_state"), id.toArrayLike(Buffer, "le", 8)];
 
   before(async () => {
     const idl = JSON.parse(fs.readFileSync(path.join(process.cwd(), "target/idl/slot_machine.json"), "utf8"));
@@ -45,6 +52,77 @@ describe("slot_machine_optimized", () => {
   const payer = (owner as any).payer as Keypair;
   const player = Keypair.generate();
   const agent = Keypair.generate();
+  const clientSeed = Array(32).fill(7);
+
+  const pda = (...seeds: Buffer[]) =>
+    anchor.web3.PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const u64le = (n: BN) => n.toArrayLike(Buffer, "le", 8);
+
+  // request_play 的 PDA 账户：PendingPlay 以 player_profile.next_play_seq 为序号派生
+  async function playAccounts() {
+    const playerProfile = pda(
+      Buffer.from("player_profile"),
+      gameState.toBuffer(),
+      player.publicKey.toBuffer()
+    );
+    let seq = new BN(0);
+    try {
+      seq = (await (program.account as any).playerProfile.fetch(playerProfile)).nextPlaySeq;
+    } catch {}
+    return {
+      gameState,
+      playerProfile,
+      playerStats: pda(Buffer.from("player_stats"), gameState.toBuffer(), player.publicKey.toBuffer()),
+      pendingPlay: pda(
+        Buffer.from("pending_play"),
+        gameState.toBuffer(),
+        player.publicKey.toBuffer(),
+        u64le(seq)
+      ),
+      player: player.publicKey,
+      playerTokenAccount,
+      poolTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+  }
+
+  // settle_play 账户：玩家自行结算（keeper 为玩家本人，不付 keeper 奖励）
+  function settleAccounts(req: any) {
+    return {
+      gameState,
+      pendingPlay: req.pendingPlay,
+      playerProfile: req.playerProfile,
+      playerStats: req.playerStats,
+      player: player.publicKey,
+      playerTokenAccount,
+      poolTokenAccount,
+      keeper: player.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+  }
+
+  // 承诺-揭示：提交 hash(seed) 作为下一个种子承诺
+  async function postCommitment() {
+    const state: any = await (program.account as any).gameState.fetch(gameState);
+    const seed = randomBytes(32);
+    const commitment = pda(
+      Buffer.from("seed_commitment"),
+      gameState.toBuffer(),
+      u64le(state.commitPosted)
+    );
+    await program.methods
+      .postSeedCommitment(Array.from(createHash("sha256").update(seed).digest()))
+      .accounts({
+        gameState,
+        commitment,
+        owner: owner.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .rpc();
+    return { seed: Array.from(seed), commitment };
+  }
 
   before(async () => {
     await provider.connection.confirmTransaction(
@@ -53,7 +131,7 @@ describe("slot_machine_optimized", () => {
 
     // 查找 PDA
     [gameState, gameStateBump] = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("game_state")],
+      gameStateSeeds(gameId),
       program.programId
     );
 
@@ -74,7 +152,7 @@ describe("slot_machine_optimized", () => {
       ).address;
 
       await program.methods
-        .initialize()
+        .initialize(gameId)
         .accounts({
           gameState,
           user: owner.publicKey,
@@ -167,7 +245,7 @@ describe("slot_machine_optimized", () => {
     );
 
     await program.methods
-      .setVrf(vrf.publicKey, 0)
+      .setVrfPool([vrf.publicKey])
       .accounts({
         gameState,
         owner: owner.publicKey,
@@ -344,12 +422,12 @@ describe("slot_machine_optimized", () => {
   });
 
   describe("游戏功能", () => {
-    let activeAgent: Keypair;
+    let activeAgentAccount: anchor.web3.PublicKey;
     let activeAgentRoomCard: number;
 
     before(async () => {
       // 创建一个活跃的代理商用于测试
-      activeAgent = Keypair.generate();
+      const activeAgent = Keypair.generate();
       await provider.connection.confirmTransaction(
         await provider.connection.requestAirdrop(
           activeAgent.publicKey,
@@ -357,63 +435,81 @@ describe("slot_machine_optimized", () => {
         )
       );
 
+      const state: any = await (program.account as any).gameState.fetch(gameState);
+      activeAgentAccount = pda(
+        Buffer.from("agent"),
+        gameState.toBuffer(),
+        activeAgent.publicKey.toBuffer()
+      );
       await program.methods
         .becomeAgent(new BN(2 * LAMPORTS_PER_SOL))
         .accounts({
           gameState,
+          agentAccount: activeAgentAccount,
+          roomCardIndex: pda(Buffer.from("room_card"), gameState.toBuffer(), u64le(state.nextRoomCard)),
           agent: activeAgent.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId,
         })
         .signers([activeAgent])
         .rpc();
 
-      const gameStateAccount = await (program.account as any).gameState.fetch(gameState);
-      activeAgentRoomCard = gameStateAccount.agents.find(
-        (a) => a.pubkey.toString() === activeAgent.publicKey.toString() && a.isActive
-      ).roomCard.toNumber();
+      const agentAccount: any = await (program.account as any).agentAccount.fetch(activeAgentAccount);
+      activeAgentRoomCard = agentAccount.roomCard.toNumber();
+
+      // 本地验证器没有 Switchboard 预言机：完整的两段式流程使用承诺-揭示随机源
+      await program.methods
+        .setRandomnessSource({ commitReveal: {} })
+        .accounts({ gameState, owner: owner.publicKey })
+        .rpc();
     });
 
-    it("request_play/settle_play: settle 在 VRF 未更新时失败", async () => {
-      const pendingPlay = Keypair.generate();
-      const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
+    after(async () => {
+      await program.methods
+        .setRandomnessSource({ switchboard: {} })
+        .accounts({ gameState, owner: owner.publicKey })
+        .rpc();
+    });
 
+    it("Switchboard 模式：request_play 只接受 VRF 池中的真实 VRF 账户", async () => {
       await program.methods
-        .requestPlay(bets, new BN(activeAgentRoomCard))
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: vrf.publicKey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
-        .signers([player, pendingPlay])
+        .setRandomnessSource({ switchboard: {} })
+        .accounts({ gameState, owner: owner.publicKey })
         .rpc();
+      const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
 
       try {
+        try {
+          await program.methods
+            .requestPlay(bets, null, clientSeed, 0, 0)
+            .accounts({ ...(await playAccounts()), vrf: Keypair.generate().publicKey })
+            .signers([player])
+            .rpc();
+          expect.fail("应该抛出错误");
+        } catch (error) {
+          expect(error.message).to.include("InvalidVrfAccount");
+        }
+
+        // 池中的占位 VRF 不是 Switchboard VrfAccountData，请求前读取轮次即失败
+        try {
+          await program.methods
+            .requestPlay(bets, null, clientSeed, 0, 0)
+            .accounts({ ...(await playAccounts()), vrf: vrf.publicKey })
+            .signers([player])
+            .rpc();
+          expect.fail("应该抛出错误");
+        } catch (error) {
+          expect(error.message).to.include("InvalidVrfData");
+        }
+      } finally {
         await program.methods
-          .settlePlay()
-          .accounts({
-            gameState,
-            pendingPlay: pendingPlay.publicKey,
-            player: player.publicKey,
-            playerTokenAccount,
-            poolTokenAccount,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            vrf: vrf.publicKey,
-            systemProgram: anchor.web3.SystemProgram.programId,
-          })
+          .setRandomnessSource({ commitReveal: {} })
+          .accounts({ gameState, owner: owner.publicKey })
           .rpc();
-        expect.fail("应该抛出错误");
-      } catch (error) {
-        expect(error.message).to.include("VrfNotUpdated");
       }
     });
 
-    it("request_play 会把下注转入奖池并创建 PendingPlay；settle_play 在 VRF 未更新时失败", async () => {
-      const pendingPlay = Keypair.generate();
+    it("request_play 会把下注转入奖池并创建 PendingPlay；settle_play 校验揭示值后结算", async () => {
+      const { seed, commitment } = await postCommitment();
       const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
 
       const playerBefore = (
@@ -421,19 +517,11 @@ describe("slot_machine_optimized", () => {
       ).value.amount;
       const poolBefore = (await provider.connection.getTokenAccountBalance(poolTokenAccount)).value.amount;
 
+      const req = await playAccounts();
       await program.methods
-        .requestPlay(bets, null)
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: vrf.publicKey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
-        .signers([player, pendingPlay])
+        .requestPlay(bets, null, clientSeed, 0, 0)
+        .accounts({ ...req, commitment })
+        .signers([player])
         .rpc();
 
       const playerAfterRequest = (
@@ -446,55 +534,79 @@ describe("slot_machine_optimized", () => {
       expect(BigInt(playerAfterRequest)).to.equal(BigInt(playerBefore) - 1_000_000n);
       expect(BigInt(poolAfterRequest)).to.equal(BigInt(poolBefore) + 1_000_000n);
 
-      const pending = await (program.account as any).pendingPlay.fetch(pendingPlay.publicKey);
+      const pending = await (program.account as any).pendingPlay.fetch(req.pendingPlay);
       expect(pending.player.toString()).to.equal(player.publicKey.toString());
       expect(pending.totalBet.toString()).to.equal("1000000");
       expect(pending.hasRoomCard).to.equal(false);
 
       try {
         await program.methods
-          .settlePlay()
-          .accounts({
-            gameState,
-            pendingPlay: pendingPlay.publicKey,
-            player: player.publicKey,
-            playerTokenAccount,
-            poolTokenAccount,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            vrf: vrf.publicKey,
-            systemProgram: anchor.web3.SystemProgram.programId,
-          })
+          .settlePlay(Array(32).fill(0))
+          .accounts({ ...settleAccounts(req), commitment })
+          .signers([player])
           .rpc();
         expect.fail("应该抛出错误");
       } catch (error) {
-        expect(error.message).to.include("VrfNotUpdated");
+        expect(error.message).to.include("InvalidReveal");
       }
+
+      await program.methods
+        .settlePlay(seed)
+        .accounts({ ...settleAccounts(req), commitment })
+        .signers([player])
+        .rpc();
+      expect(await provider.connection.getAccountInfo(req.pendingPlay)).to.equal(null);
     });
 
     it("request_play 应拒绝对 DOUBLE 符号下注", async () => {
-      const pendingPlay = Keypair.generate();
       const bets = [new BN(0), new BN(0), new BN(0), new BN(0), new BN(0), new BN(1_000_000)];
 
       try {
         await program.methods
-          .requestPlay(bets, null)
-          .accounts({
-            gameState,
-            pendingPlay: pendingPlay.publicKey,
-            player: player.publicKey,
-            playerTokenAccount,
-            poolTokenAccount,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            vrf: vrf.publicKey,
-            systemProgram: anchor.web3.SystemProgram.programId,
-          })
-          .signers([player, pendingPlay])
+          .requestPlay(bets, null, clientSeed, 0, 0)
+          .accounts({ ...(await playAccounts()), vrf: vrf.publicKey })
+          .signers([player])
           .rpc();
         expect.fail("应该抛出错误");
       } catch (error) {
         expect(error.message).to.include("InvalidBetTable");
       }
     });
+
+    // 房卡绑定是粘性的，放在本组最后
+    it("request_play/settle_play: 带房卡的局须传入代理商账户结算", async () => {
+      const { seed, commitment } = await postCommitment();
+      const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
+
+      const req = await playAccounts();
+      await program.methods
+        .requestPlay(bets, new BN(activeAgentRoomCard), clientSeed, 0, 0)
+        .accounts({ ...req, commitment, agentAccount: activeAgentAccount })
+        .signers([player])
+        .rpc();
+
+      const pending = await (program.account as any).pendingPlay.fetch(req.pendingPlay);
+      expect(pending.hasRoomCard).to.equal(true);
+      expect(pending.roomCard.toNumber()).to.equal(activeAgentRoomCard);
+
+      try {
+        await program.methods
+          .settlePlay(seed)
+          .accounts({ ...settleAccounts(req), commitment, agentAccount: null })
+          .signers([player])
+          .rpc();
+        expect.fail("应该抛出错误");
+      } catch (error) {
+        expect(error.message).to.include("InvalidRoomCard");
+      }
+
+      await program.methods
+        .settlePlay(seed)
+        .accounts({ ...settleAccounts(req), commitment, agentAccount: activeAgentAccount })
+        .signers([player])
+        .rpc();
+      expect(await provider.connection.getAccountInfo(req.pendingPlay)).to.equal(null);
+    });
   });
 
   describe("提取功能", () => {
@@ -573,23 +685,13 @@ describe("slot_machine_optimized", () => {
     });
 
     it("request_play 应拒绝总下注为 0", async () => {
-      const pendingPlay = Keypair.generate();
       const bets = [new BN(0), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
 
       try {
         await program.methods
-          .requestPlay(bets, null)
-          .accounts({
-            gameState,
-            pendingPlay: pendingPlay.publicKey,
-            player: player.publicKey,
-            playerTokenAccount,
-            poolTokenAccount,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            vrf: vrf.publicKey,
-            systemProgram: anchor.web3.SystemProgram.programId,
-          })
-          .signers([player, pendingPlay])
+          .requestPlay(bets, null, clientSeed, 0, 0)
+          .accounts({ ...(await playAccounts()), vrf: vrf.publicKey })
+          .signers([player])
           .rpc();
         expect.fail("应该抛出错误");
       } catch (error) {
@@ -606,23 +708,13 @@ describe("slot_machine_optimized", () => {
         })
         .rpc();
 
-      const pendingPlay = Keypair.generate();
       const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
 
       try {
         await program.methods
-          .requestPlay(bets, null)
-          .accounts({
-            gameState,
-            pendingPlay: pendingPlay.publicKey,
-            player: player.publicKey,
-            playerTokenAccount,
-            poolTokenAccount,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            vrf: vrf.publicKey,
-            systemProgram: anchor.web3.SystemProgram.programId,
-          })
-          .signers([player, pendingPlay])
+          .requestPlay(bets, null, clientSeed, 0, 0)
+          .accounts({ ...(await playAccounts()), vrf: vrf.publicKey })
+          .signers([player])
           .rpc();
         expect.fail("应该抛出错误");
       } catch (error) {
@@ -647,23 +739,13 @@ describe("slot_machine_optimized", () => {
         })
         .rpc();
 
-      const pendingPlay = Keypair.generate();
       const bets = [new BN(1_000_000), new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0)];
 
       try {
         await program.methods
-          .requestPlay(bets, null)
-          .accounts({
-            gameState,
-            pendingPlay: pendingPlay.publicKey,
-            player: player.publicKey,
-            playerTokenAccount,
-            poolTokenAccount,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            vrf: vrf.publicKey,
-            systemProgram: anchor.web3.SystemProgram.programId,
-          })
-          .signers([player, pendingPlay])
+          .requestPlay(bets, null, clientSeed, 0, 0)
+          .accounts({ ...(await playAccounts()), vrf: vrf.publicKey })
+          .signers([player])
           .rpc();
         expect.fail("应该抛出错误");
       } catch (error) {
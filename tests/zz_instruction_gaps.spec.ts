@@ -17,6 +17,12 @@ describe("instruction_gaps", () => {
   anchor.setProvider(provider);
 
   const programId = new anchor.web3.PublicKey("8cozexydPUo9jTBT7PRWVe5Qmi3bpkjgQuPo2ZaTKHus");
+  // game_id 0 的 game_state PDA 不带 game_id 种子，与多游戏支持之前的地址一致
+  const gameId = new BN(0);
+  const gameStateSeeds = (id: BN) =>
+    id.isZero()
+      ? [Buffer.from("game_state")]
+      : [Buffer.from("game_state"), id.toArrayLike(Buffer, "le", 8)];
   const owner = provider.wallet;
   const payer = (owner as any).payer as Keypair;
   const player = Keypair.generate();
@@ -63,7 +69,7 @@ describe("instruction_gaps", () => {
 
     program = await loadProgram();
     expect(program.programId.toBase58()).to.equal(programId.toBase58());
-    [gameState] = PublicKey.findProgramAddressSync([Buffer.from("game_state")], program.programId);
+    [gameState] = PublicKey.findProgramAddressSync(gameStateSeeds(gameId), program.programId);
 
     let state: any;
     try {
@@ -82,7 +88,7 @@ describe("instruction_gaps", () => {
       ).address;
 
       await program.methods
-        .initialize()
+        .initialize(gameId)
         .accounts({
           gameState,
           user: owner.publicKey,
@@ -288,7 +294,7 @@ describe("instruction_gaps", () => {
 
     await mintTo(provider.connection, payer, mint, poolTokenAccount, owner.publicKey, 10_000_000_000);
     await program.methods
-      .initialize()
+      .initialize(gameId)
       .accounts({
         gameState,
         user: owner.publicKey,
@@ -9,6 +9,7 @@ import {
 } from "@solana/spl-token";
 import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
 import BN from "bn.js";
+import { createHash, randomBytes } from "crypto";
 import fs from "fs";
 import path from "path";
 
@@ -18,6 +19,12 @@ describe("predictability_exploit", () => {
 
   let program: Program;
   const programId = new anchor.web3.PublicKey("8cozexydPUo9jTBT7PRWVe5Qmi3bpkjgQuPo2ZaTKHus");
+  // game_id 0 的 game_state PDA 不带 game_id 种子，与多游戏支持之前的地址一致
+  const gameId = new BN(0);
+  const gameStateSeeds = (id: BN) =>
+    id.isZero()
+      ? [Buffer.from("game_state")]
+      : [Buffer.from("game_state"), id.toArrayLike(Buffer, "le", 8)];
   const owner = provider.wallet;
   const payer = (owner as any).payer as Keypair;
 
@@ -27,6 +34,61 @@ describe("predictability_exploit", () => {
 
   const player = Keypair.generate();
   let playerTokenAccount: PublicKey;
+  const clientSeed = Array(32).fill(7);
+
+  const pda = (...seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  const u64le = (n: BN) => n.toArrayLike(Buffer, "le", 8);
+
+  // request_play 的 PDA 账户：PendingPlay 以 player_profile.next_play_seq 为序号派生
+  async function playAccounts() {
+    const playerProfile = pda(Buffer.from("player_profile"), gameState.toBuffer(), player.publicKey.toBuffer());
+    let seq = new BN(0);
+    try {
+      seq = (await (program.account as any).playerProfile.fetch(playerProfile)).nextPlaySeq;
+    } catch {}
+    return {
+      gameState,
+      playerProfile,
+      playerStats: pda(Buffer.from("player_stats"), gameState.toBuffer(), player.publicKey.toBuffer()),
+      pendingPlay: pda(Buffer.from("pending_play"), gameState.toBuffer(), player.publicKey.toBuffer(), u64le(seq)),
+      player: player.publicKey,
+      playerTokenAccount,
+      poolTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+  }
+
+  function settleAccounts(req: any) {
+    return {
+      gameState,
+      pendingPlay: req.pendingPlay,
+      playerProfile: req.playerProfile,
+      playerStats: req.playerStats,
+      player: player.publicKey,
+      playerTokenAccount,
+      poolTokenAccount,
+      keeper: player.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+  }
+
+  // 承诺-揭示：提交 hash(seed) 作为下一个种子承诺
+  async function postCommitment() {
+    const state: any = await (program.account as any).gameState.fetch(gameState);
+    const seed = randomBytes(32);
+    const commitment = pda(Buffer.from("seed_commitment"), gameState.toBuffer(), u64le(state.commitPosted));
+    await program.methods
+      .postSeedCommitment(Array.from(createHash("sha256").update(seed).digest()))
+      .accounts({ gameState, commitment, owner: owner.publicKey, systemProgram: anchor.web3.SystemProgram.programId })
+      .rpc();
+    return { seed: Array.from(seed), commitment };
+  }
+
+  async function setSource(source: any) {
+    await program.methods.setRandomnessSource(source).accounts({ gameState, owner: owner.publicKey }).rpc();
+  }
 
   before(async () => {
     await provider.connection.confirmTransaction(
@@ -46,7 +108,7 @@ describe("predictability_exploit", () => {
     idl.address = programId.toBase58();
     program = new Program(idl, provider);
 
-    [gameState] = PublicKey.findProgramAddressSync([Buffer.from("game_state")], program.programId);
+    [gameState] = PublicKey.findProgramAddressSync(gameStateSeeds(gameId), program.programId);
 
     let gameStateAccount: any;
     try {
@@ -65,7 +127,7 @@ describe("predictability_exploit", () => {
       ).address;
 
       await program.methods
-        .initialize()
+        .initialize(gameId)
         .accounts({
           gameState,
           user: owner.publicKey,
@@ -88,7 +150,7 @@ describe("predictability_exploit", () => {
     await mintTo(provider.connection, payer, mint, poolTokenAccount, owner.publicKey, 50_000_000_000);
 
     const state: any = await (program.account as any).gameState.fetch(gameState);
-    if ((state.vrf as PublicKey).toString() === anchor.web3.SystemProgram.programId.toString()) {
+    if ((state.vrfPool[0].vrf as PublicKey).toString() === anchor.web3.SystemProgram.programId.toString()) {
       const vrf = Keypair.generate();
       const vrfSpace = 64;
       const vrfRent = await provider.connection.getMinimumBalanceForRentExemption(vrfSpace);
@@ -107,130 +169,96 @@ describe("predictability_exploit", () => {
         ),
         [vrf]
       );
-      await program.methods.setVrf(vrf.publicKey, 0).accounts({ gameState, owner: owner.publicKey }).rpc();
+      await program.methods.setVrfPool([vrf.publicKey]).accounts({ gameState, owner: owner.publicKey }).rpc();
     }
   });
 
-  it("GameState 不再暴露可预测 RNG；request_play 必须配置 VRF", async () => {
+  it("GameState 不再暴露可预测 RNG；request_play 必须使用 VRF 池中的账户", async () => {
     const state: any = await (program.account as any).gameState.fetch(gameState);
     expect(state.rng).to.equal(undefined);
+    expect(state.randomnessSource.switchboard).to.not.equal(undefined);
 
-    const prevVrf = state.vrf as PublicKey;
-    const prevOffset = Number(state.vrfResultOffset);
-    await program.methods
-      .setVrf(anchor.web3.SystemProgram.programId, 0)
-      .accounts({ gameState, owner: owner.publicKey })
-      .rpc();
-
-    const pendingPlay = Keypair.generate();
     const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
     try {
       await program.methods
-        .requestPlay(bets, null)
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: Keypair.generate().publicKey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
-        .signers([player, pendingPlay])
+        .requestPlay(bets, null, clientSeed, 0, 0)
+        .accounts({ ...(await playAccounts()), vrf: Keypair.generate().publicKey })
+        .signers([player])
         .rpc();
       expect.fail("应该抛出错误");
     } catch (error) {
       expect(error.message).to.include("InvalidVrfAccount");
     }
-
-    await program.methods
-      .setVrf(prevVrf, prevOffset)
-      .accounts({ gameState, owner: owner.publicKey })
-      .rpc();
   });
 
-  it("配置 VRF 后 request_play 可提交；settle_play 在 VRF 未更新时会拒绝结算", async () => {
-    const state: any = await (program.account as any).gameState.fetch(gameState);
-    const vrfPubkey = state.vrf as PublicKey;
-    const vrfOffset = Number(state.vrfResultOffset);
-    await program.methods.setVrf(vrfPubkey, vrfOffset).accounts({ gameState, owner: owner.publicKey }).rpc();
+  // 本地验证器没有 Switchboard 预言机：完整的两段式流程使用承诺-揭示随机源
+  it("承诺-揭示模式下 request_play 可提交；settle_play 拒绝与承诺不符的揭示值", async () => {
+    await setSource({ commitReveal: {} });
+    try {
+      const { seed, commitment } = await postCommitment();
+      const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
+      const req = await playAccounts();
+      await program.methods
+        .requestPlay(bets, null, clientSeed, 0, 0)
+        .accounts({ ...req, commitment })
+        .signers([player])
+        .rpc();
 
-    const pendingPlay = Keypair.generate();
-    const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
-    await program.methods
-      .requestPlay(bets, null)
-      .accounts({
-        gameState,
-        pendingPlay: pendingPlay.publicKey,
-        player: player.publicKey,
-        playerTokenAccount,
-        poolTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        vrf: vrfPubkey,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      })
-      .signers([player, pendingPlay])
-      .rpc();
+      const bound: any = await (program.account as any).seedCommitment.fetch(commitment);
+      expect(bound.pendingPlay.toString()).to.equal(req.pendingPlay.toString());
+
+      try {
+        await program.methods
+          .settlePlay(Array.from(randomBytes(32)))
+          .accounts({ ...settleAccounts(req), commitment })
+          .signers([player])
+          .rpc();
+        expect.fail("应该抛出错误");
+      } catch (error) {
+        expect(error.message).to.include("InvalidReveal");
+      }
 
-    try {
       await program.methods
-        .settlePlay()
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: vrfPubkey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
+        .settlePlay(seed)
+        .accounts({ ...settleAccounts(req), commitment })
+        .signers([player])
         .rpc();
-      expect.fail("应该抛出错误");
-    } catch (error) {
+    } finally {
+      await setSource({ switchboard: {} });
     }
   });
 
-  it("request_play/settle_play：settle_play 在 VRF 未更新时拒绝结算", async () => {
-    const state: any = await (program.account as any).gameState.fetch(gameState);
-    expect(state.rng).to.equal(undefined);
+  it("request_play/settle_play：未提供揭示值时拒绝结算", async () => {
+    await setSource({ commitReveal: {} });
+    try {
+      const { seed, commitment } = await postCommitment();
+      const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
+      const req = await playAccounts();
+      await program.methods
+        .requestPlay(bets, null, clientSeed, 0, 0)
+        .accounts({ ...req, commitment })
+        .signers([player])
+        .rpc();
 
-    const pendingPlay = Keypair.generate();
-    const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
-    await program.methods
-      .requestPlay(bets, null)
-      .accounts({
-        gameState,
-        pendingPlay: pendingPlay.publicKey,
-        player: player.publicKey,
-        playerTokenAccount,
-        poolTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        vrf: state.vrf as PublicKey,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      })
-      .signers([player, pendingPlay])
-      .rpc();
+      try {
+        await program.methods
+          .settlePlay(null)
+          .accounts({ ...settleAccounts(req), commitment })
+          .signers([player])
+          .rpc();
+        expect.fail("应该抛出错误");
+      } catch (error) {
+        expect(error.message).to.include("InvalidReveal");
+      }
 
-    try {
       await program.methods
-        .settlePlay()
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: state.vrf as PublicKey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
+        .settlePlay(seed)
+        .accounts({ ...settleAccounts(req), commitment })
+        .signers([player])
         .rpc();
-      expect.fail("应该抛出错误");
-    } catch (error) {
-      expect(error.message).to.include("VrfNotUpdated");
+      expect(await provider.connection.getAccountInfo(req.pendingPlay)).to.equal(null);
+    } finally {
+      await setSource({ switchboard: {} });
     }
   });
 });
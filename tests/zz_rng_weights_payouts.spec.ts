@@ -18,6 +18,12 @@ describe("rng_weights_payouts", () => {
 
   let program: Program;
   const programId = new anchor.web3.PublicKey("8cozexydPUo9jTBT7PRWVe5Qmi3bpkjgQuPo2ZaTKHus");
+  // game_id 0 的 game_state PDA 不带 game_id 种子，与多游戏支持之前的地址一致
+  const gameId = new BN(0);
+  const gameStateSeeds = (id: BN) =>
+    id.isZero()
+      ? [Buffer.from("game_state")]
+      : [Buffer.from("game_state"), id.toArrayLike(Buffer, "le", 8)];
 
   const owner = provider.wallet;
   const payer = (owner as any).payer as Keypair;
@@ -28,6 +34,35 @@ describe("rng_weights_payouts", () => {
   let poolTokenAccount: PublicKey;
   let playerTokenAccount: PublicKey;
   let vrfPubkey: PublicKey;
+  const clientSeed = Array(32).fill(7);
+
+  const pda = (...seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+
+  // request_play 的 PDA 账户：PendingPlay 以 player_profile.next_play_seq 为序号派生
+  async function playAccounts() {
+    const playerProfile = pda(Buffer.from("player_profile"), gameState.toBuffer(), player.publicKey.toBuffer());
+    let seq = new BN(0);
+    try {
+      seq = (await (program.account as any).playerProfile.fetch(playerProfile)).nextPlaySeq;
+    } catch {}
+    return {
+      gameState,
+      playerProfile,
+      playerStats: pda(Buffer.from("player_stats"), gameState.toBuffer(), player.publicKey.toBuffer()),
+      pendingPlay: pda(
+        Buffer.from("pending_play"),
+        gameState.toBuffer(),
+        player.publicKey.toBuffer(),
+        seq.toArrayLike(Buffer, "le", 8)
+      ),
+      player: player.publicKey,
+      playerTokenAccount,
+      poolTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      vrf: vrfPubkey,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+  }
 
   before(async () => {
     await provider.connection.confirmTransaction(
@@ -47,7 +82,7 @@ describe("rng_weights_payouts", () => {
     idl.address = programId.toBase58();
     program = new Program(idl, provider);
 
-    [gameState] = PublicKey.findProgramAddressSync([Buffer.from("game_state")], program.programId);
+    [gameState] = PublicKey.findProgramAddressSync(gameStateSeeds(gameId), program.programId);
 
     let gameStateAccount: any;
     try {
@@ -59,7 +94,7 @@ describe("rng_weights_payouts", () => {
     if (gameStateAccount) {
       mint = gameStateAccount.poolMint as PublicKey;
       poolTokenAccount = gameStateAccount.poolTokenAccount as PublicKey;
-      vrfPubkey = gameStateAccount.vrf as PublicKey;
+      vrfPubkey = gameStateAccount.vrfPool[0].vrf as PublicKey;
     } else {
       mint = await createMint(provider.connection, payer, owner.publicKey, null, 6);
       poolTokenAccount = (
@@ -67,7 +102,7 @@ describe("rng_weights_payouts", () => {
       ).address;
 
       await program.methods
-        .initialize()
+        .initialize(gameId)
         .accounts({
           gameState,
           user: owner.publicKey,
@@ -111,7 +146,7 @@ describe("rng_weights_payouts", () => {
         [vrf]
       );
 
-      await program.methods.setVrf(vrf.publicKey, 0).accounts({ gameState, owner: owner.publicKey }).rpc();
+      await program.methods.setVrfPool([vrf.publicKey]).accounts({ gameState, owner: owner.publicKey }).rpc();
       vrfPubkey = vrf.publicKey;
     }
   });
@@ -127,22 +162,12 @@ describe("rng_weights_payouts", () => {
   });
 
   it("request_play: 禁止对 DOUBLE 符号下注", async () => {
-    const pendingPlay = Keypair.generate();
     const bets = [new BN(0), new BN(0), new BN(0), new BN(0), new BN(0), new BN(1_000_000)];
     try {
       await program.methods
-        .requestPlay(bets, null)
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: vrfPubkey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
-        .signers([player, pendingPlay])
+        .requestPlay(bets, null, clientSeed, 0, 0)
+        .accounts(await playAccounts())
+        .signers([player])
         .rpc();
       expect.fail("应该抛出错误");
     } catch (error) {
@@ -150,42 +175,20 @@ describe("rng_weights_payouts", () => {
     }
   });
 
-  it("request_play: VRF 未更新时 settle_play 会失败", async () => {
-    const pendingPlay = Keypair.generate();
+  it("request_play: Switchboard 模式下池中 VRF 须为 Switchboard VRF 账户", async () => {
     const bets = [new BN(1_000_000), new BN(0), new BN(0), new BN(0), new BN(0), new BN(0)];
-
-    await program.methods
-      .requestPlay(bets, null)
-      .accounts({
-        gameState,
-        pendingPlay: pendingPlay.publicKey,
-        player: player.publicKey,
-        playerTokenAccount,
-        poolTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        vrf: vrfPubkey,
-        systemProgram: anchor.web3.SystemProgram.programId,
-      })
-      .signers([player, pendingPlay])
-      .rpc();
+    const req = await playAccounts();
 
     try {
       await program.methods
-        .settlePlay()
-        .accounts({
-          gameState,
-          pendingPlay: pendingPlay.publicKey,
-          player: player.publicKey,
-          playerTokenAccount,
-          poolTokenAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          vrf: vrfPubkey,
-          systemProgram: anchor.web3.SystemProgram.programId,
-        })
+        .requestPlay(bets, null, clientSeed, 0, 0)
+        .accounts(req)
+        .signers([player])
         .rpc();
       expect.fail("应该抛出错误");
     } catch (error) {
-      expect(error.message).to.include("VrfNotUpdated");
+      expect(error.message).to.include("InvalidVrfData");
     }
+    expect(await provider.connection.getAccountInfo(req.pendingPlay)).to.equal(null);
   });
 });